@@ -0,0 +1,82 @@
+//! Compare key-based isomorphism checks with `petgraph::algo::is_isomorphic`
+//! on random graphs of several sizes and densities.
+//!
+//! For each size and density, both methods check isomorphic pairs (a graph and
+//! a relabelled copy) and non-isomorphic pairs (a graph and a relabelled copy
+//! with one edge moved), and the table reports the mean time per pair.
+//!
+//! The densities stay high enough for the graphs to have few isolated nodes:
+//! their permutations are automorphisms, and the search keeps a leaf for each
+//! automorphism.
+//!
+//! Run with `cargo run --release --example benchmark`.
+
+use std::time::{Duration, Instant};
+
+use petgraph::Graph;
+use petgraph::Undirected;
+use petgraph::algo::is_isomorphic;
+
+use graphkey::GraphKey;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{generate_random_graph, generate_permutated_graph, generate_rewired_graph};
+
+const SIZES : [usize; 4] = [20, 50, 100, 200];
+const DENSITIES : [f64; 3] = [0.2, 0.35, 0.5];
+const PAIRS : usize = 10;
+
+type UnGraph = Graph<usize, (), Undirected>;
+
+/// Mean time per pair of each method, checking that both give `expected`.
+fn time_pairs(pairs : &[(UnGraph, UnGraph)], expected : bool) -> (Duration, Duration) {
+    let start = Instant::now();
+    for (g1, g2) in pairs {
+        assert_eq!(is_isomorphic(g1, g2), expected);
+    }
+    let petgraph = start.elapsed();
+
+    let start = Instant::now();
+    for (g1, g2) in pairs {
+        assert_eq!(GraphKey::new(g1) == GraphKey::new(g2), expected);
+    }
+    let graphkey = start.elapsed();
+
+    (petgraph / pairs.len() as u32, graphkey / pairs.len() as u32)
+}
+
+fn main() {
+
+    println!("{:>5} {:>8} | {:>12} {:>12} {:>8} | {:>12} {:>12} {:>8}", "n", "p", "iso petgraph", "iso graphkey", "speedup", "non petgraph", "non graphkey", "speedup");
+
+    for n in SIZES {
+        for p in DENSITIES {
+            let isomorphic : Vec<(UnGraph, UnGraph)> = (0..PAIRS).map(|_| {
+                let g = generate_random_graph(n, p);
+                let h = generate_permutated_graph(&g);
+                (g, h)
+            }).collect();
+
+            // Rewiring an edge may give back an isomorphic graph: try again
+            let non_isomorphic : Vec<(UnGraph, UnGraph)> = (0..PAIRS).map(|_| loop {
+                let g = generate_random_graph(n, p);
+                let h = generate_permutated_graph(&generate_rewired_graph(&g));
+                if GraphKey::new(&g) != GraphKey::new(&h) {
+                    break (g, h);
+                }
+            }).collect();
+
+            let (iso_petgraph, iso_graphkey) = time_pairs(&isomorphic, true);
+            let (non_petgraph, non_graphkey) = time_pairs(&non_isomorphic, false);
+
+            println!(
+                "{:>5} {:>8.2} | {:>12.2?} {:>12.2?} {:>7.1}x | {:>12.2?} {:>12.2?} {:>7.1}x",
+                n, p,
+                iso_petgraph, iso_graphkey, iso_petgraph.as_secs_f64() / iso_graphkey.as_secs_f64(),
+                non_petgraph, non_graphkey, non_petgraph.as_secs_f64() / non_graphkey.as_secs_f64(),
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Time the keys of graphs whose search trees are wide and deep: vertex
+//! transitive graphs where colour refinement splits nothing until many nodes
+//! are individualized.
+//!
+//! Run with `cargo run --release --example search_heavy`.
+
+use std::time::Instant;
+
+use graphkey::GraphKey;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::graph;
+
+const RUNS : u32 = 5;
+
+/// Hypercube of dimension d: nodes are bit strings, adjacent when they differ
+/// by one bit.
+fn hypercube(d : usize) -> Vec<(usize, usize)> {
+    (0..(1 << d)).flat_map(|u| (0..d).map(move |i| (u, u ^ (1 << i)))).filter(|(u, v)| u < v).collect()
+}
+
+/// Rook's graph on a k x k board.
+fn rook(k : usize) -> Vec<(usize, usize)> {
+    let n = k * k;
+    (0..n).flat_map(|u| ((u + 1)..n).map(move |v| (u, v))).filter(|(u, v)| u / k == v / k || u % k == v % k).collect()
+}
+
+/// Paley graph of prime order q = 1 mod 4.
+fn paley(q : usize) -> Vec<(usize, usize)> {
+    let squares : Vec<usize> = (1..q).map(|x| x * x % q).collect();
+    (0..q).flat_map(|u| ((u + 1)..q).map(move |v| (u, v))).filter(|(u, v)| squares.contains(&(v - u))).collect()
+}
+
+fn main() {
+
+    let graphs = [
+        ("hypercube 6", 64, hypercube(6)),
+        ("rook 5x5", 25, rook(5)),
+        ("paley 29", 29, paley(29)),
+        ("paley 37", 37, paley(37)),
+    ];
+
+    println!("{:>12} {:>6} {:>12}", "graph", "nodes", "key");
+    for (name, n, edges) in graphs {
+        let g = graph(n, &edges);
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            GraphKey::new(&g);
+        }
+        println!("{:>12} {:>6} {:>12.2?}", name, n, start.elapsed() / RUNS);
+    }
+}
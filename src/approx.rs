@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors};
+
+use crate::{GraphKey, fnv1a};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+//
+// Approximate keys
+//
+
+/// Maximal number of Weisfeiler-Leman rounds run by `GraphKey::approximate`.
+const APPROX_MAX_ROUNDS : usize = 16;
+
+/// Number of Weisfeiler-Leman rounds run by `GraphKey::lsh_bands`: the color
+/// of a node after one round is its degree and the degrees of its neighbors.
+const LSH_ROUNDS : usize = 1;
+
+/// Number of min-hashes combined in each band of `GraphKey::lsh_bands`.
+const LSH_BAND_ROWS : usize = 2;
+
+/// Best-effort fingerprint of a graph, see `GraphKey::approximate`.
+///
+/// Isomorphic graphs always get the same `ApproxKey`, but non-isomorphic
+/// graphs may collide: equal approximate keys do not prove isomorphism.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ApproxKey(u64);
+
+impl ApproxKey {
+    pub fn get_fingerprint(&self) -> u64 {
+        self.0
+    }
+}
+
+impl GraphKey {
+
+    /// Compute an approximate key of `g`, for graphs too large to be fully
+    /// canonicalized.
+    ///
+    /// Runs at most `APPROX_MAX_ROUNDS` rounds of Weisfeiler-Leman colour
+    /// refinement, then hashes the multiset of the final colors. Graphs that
+    /// colour refinement cannot tell apart (e.g. regular graphs of the same
+    /// size and degree) get the same key.
+    pub fn approximate<G>(g : G) -> ApproxKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let n = g.node_count();
+        let mut colors = degrees(g);
+        let mut color_count = colors.iter().collect::<HashSet<_>>().len();
+
+        for _ in 0..APPROX_MAX_ROUNDS {
+            let new_colors = wl_round(g, &colors);
+            let new_color_count = new_colors.iter().collect::<HashSet<_>>().len();
+            colors = new_colors;
+
+            // Stable partition : further rounds only rename the colors
+            if new_color_count == color_count { break; }
+            color_count = new_color_count;
+        }
+
+        colors.sort();
+        ApproxKey(hash_of(std::iter::once(n as u64).chain(colors)))
+    }
+
+    /// Compute `bands` locality-sensitive values of `g`, for bucketing near
+    /// duplicates: graphs within a small edit distance tend to agree on at
+    /// least one band, unrelated graphs rarely do.
+    ///
+    /// The features of g are the colors of its nodes after `LSH_ROUNDS` rounds
+    /// of Weisfeiler-Leman refinement, the i-th occurrence of a color being a
+    /// feature of its own. An edit only changes the features of the nodes close
+    /// to it. Each band hashes `LSH_BAND_ROWS` min-hashes of the features, so
+    /// two graphs agree on a band with probability about J^LSH_BAND_ROWS, where
+    /// J is the Jaccard similarity of their features.
+    ///
+    /// Like `approximate`, this is a prefilter: isomorphic graphs get the same
+    /// bands, but sharing a band proves nothing.
+    pub fn lsh_bands<G>(g : G, bands : usize) -> Vec<u64>
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let mut colors = degrees(g);
+        for _ in 0..LSH_ROUNDS {
+            colors = wl_round(g, &colors);
+        }
+
+        colors.sort();
+        let features : Vec<(u64, usize)> = colors.iter().enumerate().map(|(i, c)| {
+            (*c, i - colors.partition_point(|d| d < c))
+        }).collect();
+
+        (0..bands).map(|b| {
+            let rows : Vec<u64> = (0..LSH_BAND_ROWS).map(|r| {
+                let k = b * LSH_BAND_ROWS + r;
+                features.iter().map(|(c, i)| hash_of([k as u64, *c, *i as u64])).min().unwrap_or(0)
+            }).collect();
+            hash_of(std::iter::once(b as u64).chain(rows))
+        }).collect()
+    }
+}
+
+/// FNV-1a hash of the little-endian bytes of `values`, the same on every
+/// target and with every version of Rust.
+fn hash_of<I>(values : I) -> u64
+where
+    I : IntoIterator<Item = u64>
+{
+    fnv1a(values.into_iter().flat_map(u64::to_le_bytes))
+}
+
+/// Degree of each node of `g`, the colors before the first round.
+fn degrees<G>(g : G) -> Vec<u64>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    (0..g.node_count()).map(|u| g.neighbors(g.from_index(u)).count() as u64).collect()
+}
+
+/// One round of Weisfeiler-Leman refinement: the new color of a node hashes
+/// its color and the multiset of the colors of its neighbors.
+fn wl_round<G>(g : G, colors : &[u64]) -> Vec<u64>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    (0..g.node_count()).map(|u| {
+        let mut neigh : Vec<u64> = g.neighbors(g.from_index(u)).map(|v| colors[g.to_index(v)]).collect();
+        neigh.sort();
+        hash_of(std::iter::once(colors[u]).chain(neigh))
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::GraphKey;
+    use crate::common::{gen_test_graph, generate_random_graph, generate_permutated_graph};
+    use crate::generators::random_gnp;
+    use petgraph::graph::{NodeIndex, UnGraph};
+
+    #[test]
+    fn approximate_keys() {
+
+        for _ in 0..10 {
+            let g = generate_random_graph(40, 0.2);
+            assert_eq!(GraphKey::approximate(&g), GraphKey::approximate(&generate_permutated_graph(&g)));
+        }
+
+        let g = gen_test_graph();
+        let mut h = gen_test_graph();
+        h.remove_edge(h.edge_indices().next().unwrap());
+        assert_ne!(GraphKey::approximate(&g), GraphKey::approximate(&h));
+
+        // False positive : a 6-cycle and two triangles are both 2-regular
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let triangles = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+        assert_eq!(GraphKey::approximate(&c6), GraphKey::approximate(&triangles));
+        assert_ne!(GraphKey::new(&c6), GraphKey::new(&triangles));
+    }
+
+    #[test]
+    fn lsh_bands_bucket_near_duplicates() {
+
+        let g = generate_random_graph(30, 0.2);
+        assert_eq!(GraphKey::lsh_bands(&g, 8), GraphKey::lsh_bands(&generate_permutated_graph(&g), 8));
+        assert_eq!(GraphKey::lsh_bands(&g, 8).len(), 8);
+
+        let share = |a : &[u64], b : &[u64]| a.iter().zip(b).any(|(x, y)| x == y);
+        let (mut edited, mut unrelated) = (0, 0);
+        for seed in 0..20 {
+            let g = random_gnp(30, 0.15, seed);
+            let mut h = g.clone();
+            let (u, v) = (NodeIndex::new(0), NodeIndex::new(1 + seed as usize));
+            match h.find_edge(u, v) {
+                Some(e) => { h.remove_edge(e); }
+                None => { h.add_edge(u, v, ()); }
+            }
+            if share(&GraphKey::lsh_bands(&g, 8), &GraphKey::lsh_bands(&h, 8)) { edited += 1; }
+
+            let other = random_gnp(30, 0.15, 100 + seed);
+            if share(&GraphKey::lsh_bands(&g, 8), &GraphKey::lsh_bands(&other, 8)) { unrelated += 1; }
+        }
+        assert!(edited > unrelated, "{} edited graphs and {} unrelated graphs share a band", edited, unrelated);
+    }
+}
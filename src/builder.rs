@@ -0,0 +1,188 @@
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges, GraphProp, EdgeRef};
+
+use crate::{GraphKey, CanonConfig, CanonError, Label, SelectorKind, LeafSelection, SearchOrder};
+use crate::search_with_limits;
+use crate::coloring::{Colouring, Neighbors};
+use crate::derived::attributed_graph;
+
+//
+// GraphKeyBuilder object
+//
+
+/// Computes keys with a combination of options, see the setters.
+///
+/// A builder without options computes `GraphKey::new`. Options matching an
+/// existing constructor give the same keys: `.rooted(v)` gives
+/// `GraphKey::new_rooted`, `.node_labels(f)` gives `GraphKey::new_with_labels`
+/// and `.node_labels(f).edge_labels(h)` gives `GraphKey::attributed`.
+///
+/// The labelling functions must be `Send + Sync`, so that a builder can be
+/// shared by the threads computing keys.
+#[derive(Default)]
+pub struct GraphKeyBuilder<'a> {
+    config : CanonConfig,
+    selection : LeafSelection,
+    root : Option<usize>,
+    node_labels : Option<Box<dyn Fn(usize) -> usize + Send + Sync + 'a>>,
+    edge_labels : Option<Box<dyn Fn(usize, usize) -> usize + Send + Sync + 'a>>,
+}
+
+impl<'a> GraphKeyBuilder<'a> {
+
+    pub fn new() -> GraphKeyBuilder<'a> {
+        GraphKeyBuilder::default()
+    }
+
+    /// Rule choosing the target cells of the search.
+    pub fn selector(mut self, selector : SelectorKind) -> GraphKeyBuilder<'a> {
+        self.config.selector = selector;
+        self
+    }
+
+    /// Order in which the search explores its tree.
+    pub fn search_order(mut self, order : SearchOrder) -> GraphKeyBuilder<'a> {
+        self.config.order = order;
+        self
+    }
+
+    /// Criterion choosing the canonical leaf.
+    pub fn leaf_selection(mut self, selection : LeafSelection) -> GraphKeyBuilder<'a> {
+        self.selection = selection;
+        self
+    }
+
+    /// Label the node n with f(n). The values of the labels are written into
+    /// the key, not only their order.
+    pub fn node_labels<F>(mut self, f : F) -> GraphKeyBuilder<'a>
+    where
+        F : Fn(usize) -> usize + Send + Sync + 'a
+    {
+        self.node_labels = Some(Box::new(f));
+        self
+    }
+
+    /// Label the edge from u to v with f(u, v), written into the key like the
+    /// node labels.
+    pub fn edge_labels<F>(mut self, f : F) -> GraphKeyBuilder<'a>
+    where
+        F : Fn(usize, usize) -> usize + Send + Sync + 'a
+    {
+        self.edge_labels = Some(Box::new(f));
+        self
+    }
+
+    /// Distinguish the node `root`.
+    pub fn rooted(mut self, root : usize) -> GraphKeyBuilder<'a> {
+        self.root = Some(root);
+        self
+    }
+
+    /// Fail once the search creates more than `budget` tree nodes.
+    pub fn budget(mut self, budget : usize) -> GraphKeyBuilder<'a> {
+        self.config.search_node_budget = Some(budget);
+        self
+    }
+
+    /// Study at most `rounds` colors in each refinement.
+    pub fn max_refine_rounds(mut self, rounds : usize) -> GraphKeyBuilder<'a> {
+        self.config.max_refine_rounds = Some(rounds);
+        self
+    }
+
+    /// Compute the key of `g` with the options set so far.
+    ///
+    /// Returns `CanonError::BudgetExceeded` if the search exceeds its budget.
+    pub fn build<G>(&self, g : G) -> Result<GraphKey, CanonError>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges + GraphProp
+    {
+        if self.root.is_none() && self.node_labels.is_none() && self.edge_labels.is_none() {
+            let result = search_with_limits(Neighbors(g), Colouring::new(g), self.config.selector.select(), self.selection, self.config.order, self.config.max_refine_rounds, self.config.search_node_budget)?;
+            return Ok(GraphKey::from_descriptor(result.descriptor));
+        }
+
+        // The root comes first, then the nodes by label. Each option alone labels
+        // the nodes as its constructor does, so that they get the same keys
+        match (self.root, &self.node_labels) {
+            (Some(root), None) => self.build_labelled(g, |u| u != root),
+            (None, Some(f)) => self.build_labelled(g, f),
+            (None, None) => self.build_labelled(g, |_| ()),
+            (root, Some(f)) => self.build_labelled(g, |u| (root.is_some_and(|r| r != u), f(u))),
+        }
+    }
+
+    /// Compute the key of `g` where the node n carries the label node_label(n),
+    /// and its edges the edge labels if any.
+    fn build_labelled<G, F, L>(&self, g : G, node_label : F) -> Result<GraphKey, CanonError>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges + GraphProp,
+        F : Fn(usize) -> L,
+        L : Label
+    {
+        match &self.edge_labels {
+            None => {
+                let labels : Vec<L> = (0..g.node_count()).map(node_label).collect();
+                GraphKey::try_from_labels(Neighbors(g), &labels, &self.config, self.selection)
+            }
+            Some(edge_label) => {
+                let (_g, labels) = attributed_graph(g, node_label, |e| edge_label(g.to_index(e.source()), g.to_index(e.target())));
+                GraphKey::try_from_labels(Neighbors(&_g), &labels, &self.config, self.selection)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{GraphKey, GraphKeyBuilder, CanonError, SelectorKind, LeafSelection, CanonConfig};
+    use crate::common::{gen_test_graph, generate_random_graph};
+    use petgraph::graph::UnGraph;
+    use petgraph::visit::EdgeRef;
+
+    #[test]
+    fn default_builder_matches_new() {
+
+        for g in [gen_test_graph(), generate_random_graph(30, 0.2)] {
+            assert_eq!(GraphKeyBuilder::new().build(&g), Ok(GraphKey::new(&g)));
+        }
+    }
+
+    #[test]
+    fn builder_options() {
+
+        let g = gen_test_graph();
+
+        // Options matching the existing constructors
+        let labels = [0, 1, 0, 1, 2, 0, 1, 0, 1, 2];
+        assert_eq!(GraphKeyBuilder::new().rooted(3).build(&g), Ok(GraphKey::new_rooted(&g, 3)));
+        assert_eq!(GraphKeyBuilder::new().node_labels(|u| labels[u]).build(&g), Ok(GraphKey::new_with_labels(&g, &labels)));
+        assert_eq!(GraphKeyBuilder::new().leaf_selection(LeafSelection::MinDescriptor).build(&g), Ok(GraphKey::new_with_selection(&g, LeafSelection::MinDescriptor)));
+        let cfg = CanonConfig { selector : SelectorKind::FirstNonSingleton, ..CanonConfig::default() };
+        assert_eq!(GraphKeyBuilder::new().selector(SelectorKind::FirstNonSingleton).build(&g), GraphKey::new_with_config(&g, &cfg));
+
+        // Labelled triangle with a pendant edge
+        let t = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3)]);
+        let weight = |u : usize, v : usize| if u.min(v) == 0 { 1 } else { 2 };
+        assert_eq!(
+            GraphKeyBuilder::new().node_labels(|u| u % 2).edge_labels(weight).build(&t),
+            Ok(GraphKey::attributed(&t, |u| u % 2, |e| weight(e.source().index(), e.target().index())))
+        );
+
+        // Combined options are isomorphism-invariant : relabel t by u -> p[u],
+        // where p is its own inverse
+        let p = [2, 3, 0, 1];
+        let relabelled = UnGraph::<usize, ()>::from_edges([(2, 3), (3, 0), (0, 2), (0, 1)]);
+        let options = |builder : GraphKeyBuilder<'static>| builder.selector(SelectorKind::FirstNonSingleton).budget(10_000);
+        let key = options(GraphKeyBuilder::new().node_labels(|u| u % 2).edge_labels(weight)).build(&t).unwrap();
+        assert_eq!(options(GraphKeyBuilder::new().node_labels(move |u| p[u] % 2).edge_labels(move |u, v| weight(p[u], p[v]))).build(&relabelled), Ok(key.clone()));
+        assert_ne!(options(GraphKeyBuilder::new().node_labels(|u| u % 2)).build(&t), Ok(key));
+
+        // Label values are written into the key, even when they are uniform
+        assert_ne!(GraphKeyBuilder::new().node_labels(|_| 1).build(&t), GraphKeyBuilder::new().node_labels(|_| 2).build(&t));
+        assert_ne!(GraphKeyBuilder::new().edge_labels(|_, _| 1).build(&t), GraphKeyBuilder::new().edge_labels(|_, _| 2).build(&t));
+
+        // The budget applies to the labelled graph too
+        assert_eq!(GraphKeyBuilder::new().rooted(0).budget(0).build(&UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2)])), Err(CanonError::BudgetExceeded { budget : 0 }));
+    }
+}
@@ -0,0 +1,851 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use petgraph::visit::EdgeRef;
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges};
+
+use crate::{GraphKey, SearchResult, canonical_search, components, SEPARATOR};
+use crate::derived::induced_subgraph;
+use crate::coloring::Colouring;
+
+//
+// Exports of the canonical form
+//
+
+/// Largest node count accepted by `GraphKey::to_u128`: the node count takes 4
+/// bits, the adjacency of 15 nodes the 105 bits of the pairs.
+pub const U128_MAX_NODES : usize = 15;
+
+impl GraphKey {
+
+    /// Compute the canonical form of `g` as adjacency lists: entry i holds the
+    /// sorted neighbors of the canonical node i.
+    ///
+    /// Isomorphic graphs get identical adjacency lists.
+    pub fn canonical_adjacency<G>(g : G) -> Vec<Vec<usize>>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        relabelled_adjacency(g, &canonical_search(g).leaf)
+    }
+
+    /// Order the nodes of `g` as a breadth-first search from the canonical node
+    /// 0 visits them, the neighbors of a node being visited in canonical order.
+    /// Entry i is the index of the i-th node visited.
+    ///
+    /// Once a component is exhausted, the search restarts from the first
+    /// unvisited canonical node. Relabelling isomorphic graphs by their order
+    /// gives the same graph.
+    pub fn canonical_bfs_order<G>(g : G) -> Vec<usize>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = g.node_count();
+        let leaf = canonical_search(g).leaf;
+        let adjacency = relabelled_adjacency(g, &leaf);
+        let mut node_at = vec![0; n];
+        (0..n).for_each(|u| node_at[leaf.color_of(u)] = u);
+
+        let mut visited = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+        for root in 0..n {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            let mut i = order.len();
+            order.push(root);
+            while i < order.len() {
+                for v in adjacency[order[i]].iter() {
+                    if !visited[*v] {
+                        visited[*v] = true;
+                        order.push(*v);
+                    }
+                }
+                i += 1;
+            }
+        }
+
+        order.into_iter().map(|c| node_at[c]).collect()
+    }
+
+    /// Compute the key of `g` along with its canonical labelling: entry u is
+    /// the canonical position of the node of index u.
+    ///
+    /// The labelling comes from the leaf that produced the key, so relabelling
+    /// two isomorphic graphs with their labellings gives the same graph.
+    pub fn with_labelling<G>(g : G) -> (GraphKey, Vec<usize>)
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        let labelling = (0..g.node_count()).map(|u| result.leaf.color_of(u)).collect();
+        (GraphKey::from_descriptor(result.descriptor), labelling)
+    }
+
+    /// Compute the discrete colouring of the leaf that produced the key of `g`:
+    /// the color of each node is its canonical position.
+    pub fn winning_colouring<G>(g : G) -> Colouring
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        canonical_search(g).leaf
+    }
+
+    /// Map each node id of `g` to its canonical position, i.e. its index in
+    /// `GraphKey::canonical_adjacency(g)`.
+    pub fn canonical_map<G>(g : G) -> HashMap<G::NodeId, usize>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        G::NodeId : Hash + Eq
+    {
+        let leaf = canonical_search(g).leaf;
+        (0..g.node_count()).map(|u| (g.from_index(u), leaf.color_of(u))).collect()
+    }
+
+    /// Compute the canonical position of each node of `g`, entry u holding the
+    /// position of the node of index u, as a compact array.
+    ///
+    /// The array is a minimal perfect hash of the nodes onto 0..n: relabelling
+    /// g with it gives `GraphKey::canonical_adjacency(g)`. Panics if g has
+    /// more than `u32::MAX` nodes.
+    pub fn canonical_mph<G>(g : G) -> Vec<u32>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        assert!(u32::try_from(g.node_count()).is_ok(), "too many nodes for u32 positions");
+        let leaf = canonical_search(g).leaf;
+        (0..g.node_count()).map(|u| leaf.color_of(u) as u32).collect()
+    }
+
+    /// Compute a canonical labelling of `g`, entry u holding the position of
+    /// the node u, chosen among the maximal leaves as the closest to
+    /// `preferred`, the preferred position of each node.
+    ///
+    /// All the maximal leaves give the same canonical form, so the key does not
+    /// depend on `preferred`. The distance to it is the sum over the nodes of
+    /// the difference between both positions, ties going to the first leaf
+    /// found. All the |Aut(g)| leaves are compared. Panics if preferred does
+    /// not have one entry per node.
+    pub fn canonical_permutation_preferring<G>(g : G, preferred : &[usize]) -> Vec<usize>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = g.node_count();
+        assert_eq!(preferred.len(), n, "one preferred position per node is required");
+
+        let result = canonical_search(g);
+        std::iter::once(&result.leaf).chain(result.equivalent_leaves.iter())
+            .map(|leaf| (0..n).map(|u| leaf.color_of(u)).collect::<Vec<usize>>())
+            .min_by_key(|labelling| labelling.iter().zip(preferred).map(|(p, q)| p.abs_diff(*q)).sum::<usize>())
+            .unwrap_or_default()
+    }
+
+    /// Compute the canonical form of `g` as a CSR adjacency `(indptr, indices)`.
+    ///
+    /// The neighbors of the canonical node i are `indices[indptr[i]..indptr[i+1]]`,
+    /// in increasing order. Each edge is listed in the rows of both endpoints,
+    /// except a self-loop, listed once in the row of its node: `indices` holds
+    /// twice the edge count minus the self-loop count.
+    pub fn canonical_csr<G>(g : G) -> (Vec<usize>, Vec<usize>)
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let adjacency = GraphKey::canonical_adjacency(g);
+
+        let mut indptr = Vec::with_capacity(adjacency.len() + 1);
+        indptr.push(0);
+        for row in adjacency.iter() {
+            indptr.push(indptr[indptr.len() - 1] + row.len());
+        }
+
+        (indptr, adjacency.concat())
+    }
+
+    /// Compute the canonical form of `g` as a single-line edge list, such as
+    /// `"n=4;0-1;0-2;1-3"`.
+    ///
+    /// Edges are written u-v with u <= v, in increasing order. Isomorphic graphs
+    /// get byte-identical strings.
+    pub fn canonical_edge_list<G>(g : G) -> String
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let adjacency = GraphKey::canonical_adjacency(g);
+
+        let mut edge_list = format!("n={}", adjacency.len());
+        for (u, row) in adjacency.iter().enumerate() {
+            for v in row.iter().filter(|v| u <= **v) {
+                edge_list.push_str(&format!(";{u}-{v}"));
+            }
+        }
+
+        edge_list
+    }
+
+    /// Pack the canonical form of the simple graph `g` into a u128, or None if
+    /// g has more than `U128_MAX_NODES` nodes, self-loops or parallel edges.
+    ///
+    /// The top 4 bits hold the node count, and the bit j(j-1)/2 + i tells if
+    /// the canonical nodes i < j are adjacent. The packing is injective: two
+    /// graphs get the same value iff they are isomorphic.
+    pub fn to_u128<G>(g : G) -> Option<u128>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = g.node_count();
+        if n > U128_MAX_NODES {
+            return None;
+        }
+
+        let mut bits = 0u128;
+        for (u, row) in GraphKey::canonical_adjacency(g).iter().enumerate() {
+            for v in row.iter().filter(|v| **v >= u) {
+                if *v == u {
+                    return None;
+                }
+                let bit = 1 << (v * (v - 1) / 2 + u);
+                if bits & bit != 0 {
+                    return None;
+                }
+                bits |= bit;
+            }
+        }
+
+        Some((n as u128) << 124 | bits)
+    }
+
+    /// Record the colourings along the path of the search tree leading to the
+    /// canonical leaf of `g`, as snapshots of the color of each node.
+    ///
+    /// The first snapshot is the refined uniform colouring, and each following
+    /// one results from individualizing a node and refining. The last one is
+    /// the discrete canonical labelling.
+    pub fn trace_colourings<G>(g : G) -> Vec<Vec<usize>>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let leaf = canonical_search(g).leaf;
+        let snapshot = |gc : &Colouring| (0..g.node_count()).map(|u| gc.color_of(u)).collect::<Vec<usize>>();
+
+        let mut gc = Colouring::new(g);
+        gc.refine(g);
+        let mut snapshots = vec![snapshot(&gc)];
+
+        // Individualizing a node gives it the color of its cell, which it keeps
+        // until the leaf: the node of the path is the one whose color in the leaf
+        // is the color of the target cell.
+        while !gc.is_discrete() {
+            let target = gc.select_cell_smallest_first();
+            let members = gc.get_cell_members(target);
+            let color = gc.color_of(members[0]);
+            let node = *members.iter().find(|u| leaf.color_of(**u) == color).unwrap();
+
+            gc.individualize_and_refine(target, node, g);
+            snapshots.push(snapshot(&gc));
+        }
+
+        snapshots
+    }
+
+    /// List the connected components of `g`, sorted by the descriptors of their
+    /// keys, so smaller components come first.
+    ///
+    /// Each component is sorted. Isomorphic components are ordered by their
+    /// smallest node, so isomorphic graphs give components with the same keys
+    /// in the same order.
+    pub fn canonical_components<G>(g : G) -> Vec<Vec<usize>>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let mut keyed : Vec<(GraphKey, Vec<usize>)> = components(g).into_iter().map(|c| (GraphKey::new(&induced_subgraph(g, &c)), c)).collect();
+        keyed.sort_by(|(a, c), (b, d)| a.get_descriptor().cmp(b.get_descriptor()).then(c[0].cmp(&d[0])));
+        keyed.into_iter().map(|(_, c)| c).collect()
+    }
+
+    /// Find an isomorphism from `g` to `h`.
+    ///
+    /// Returns a permutation p such that (u, v) is an edge of g iff (p[u], p[v])
+    /// is an edge of h, or `None` if the graphs are not isomorphic. It is derived
+    /// by composing the canonical labellings of both graphs, and checked against
+    /// their edge sets before being returned.
+    pub fn isomorphism_proof<G, H>(g : G, h : H) -> Option<Vec<usize>>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        if g.node_count() != h.node_count() {
+            return None;
+        }
+
+        let (search_g, search_h) = (canonical_search(g), canonical_search(h));
+        if search_g.descriptor != search_h.descriptor {
+            return None;
+        }
+
+        let p = map_leaves(&search_g, &search_h);
+        if maps_edges(g, h, &p) { Some(p) } else { None }
+    }
+}
+
+/// Adjacency lists of `g` relabelled by the discrete colouring `leaf`, each
+/// sorted.
+fn relabelled_adjacency<G>(g : G, leaf : &Colouring) -> Vec<Vec<usize>>
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let mut adjacency = vec![Vec::new(); g.node_count()];
+
+    for e in g.edge_references() {
+        let (u, v) = (leaf.color_of(g.to_index(e.source())), leaf.color_of(g.to_index(e.target())));
+        adjacency[u].push(v);
+        if u != v {
+            adjacency[v].push(u);
+        }
+    }
+
+    for row in adjacency.iter_mut() {
+        row.sort();
+    }
+
+    adjacency
+}
+
+/// Map each node of g to the node of h at the same position in the leaves of
+/// their searches.
+fn map_leaves(search_g : &SearchResult, search_h : &SearchResult) -> Vec<usize> {
+    let n = search_h.leaf.size();
+
+    // node of h at each canonical position
+    let mut h_at = vec![0; n];
+    for w in 0..n {
+        h_at[search_h.leaf.color_of(w)] = w;
+    }
+
+    (0..search_g.leaf.size()).map(|u| h_at[search_g.leaf.color_of(u)]).collect()
+}
+
+/// Where the keys of two graphs diverge, see `diff_keys`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyDiff {
+    /// First position where the descriptors differ, None if the keys are equal
+    pub index : Option<usize>,
+    /// Canonical node whose row of the descriptor holds that position, None if
+    /// the graphs do not have the same number of nodes
+    pub node : Option<usize>,
+    /// Edges (node, j), with j > node, of the canonical graph of g
+    pub g_edges : Vec<(usize, usize)>,
+    /// Edges (node, j), with j > node, of the canonical graph of h
+    pub h_edges : Vec<(usize, usize)>,
+}
+
+impl KeyDiff {
+
+    /// Tells if the keys are equal.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_none()
+    }
+}
+
+/// Explain why `g` and `h` get different keys.
+///
+/// The descriptor lists the later neighbors of each canonical node in turn:
+/// the first position where the descriptors differ belongs to a canonical
+/// node, whose edges to later nodes differ between the canonical graphs. The
+/// diff is empty iff the keys are equal.
+pub fn diff_keys<G, H>(g : G, h : H) -> KeyDiff
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+    H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    diff_searches(g, h, &canonical_search(g), &canonical_search(h))
+}
+
+/// Same as `diff_keys`, from the searches of both graphs.
+fn diff_searches<G, H>(g : G, h : H, search_g : &SearchResult, search_h : &SearchResult) -> KeyDiff
+where
+    G : NodeCompactIndexable + IntoEdges,
+    H : NodeCompactIndexable + IntoEdges
+{
+    let (dg, dh) = (&search_g.descriptor, &search_h.descriptor);
+
+    let Some(index) = (0..dg.len().max(dh.len())).find(|i| dg.get(*i) != dh.get(*i)) else {
+        return KeyDiff::default();
+    };
+    if index == 0 {
+        return KeyDiff { index : Some(0), ..KeyDiff::default() };
+    }
+
+    let node = dg[1..index].iter().filter(|v| **v == SEPARATOR).count();
+    let later_edges = |colour : &dyn Fn(usize) -> usize, edges : Vec<(usize, usize)>| {
+        let mut row : Vec<(usize, usize)> = edges.into_iter()
+            .map(|(u, v)| (colour(u).min(colour(v)), colour(u).max(colour(v))))
+            .filter(|(u, v)| *u == node && *v > node)
+            .collect();
+        row.sort();
+        row.dedup();
+        row
+    };
+
+    KeyDiff {
+        index : Some(index),
+        node : Some(node),
+        g_edges : later_edges(&|u| search_g.leaf.color_of(u), g.edge_references().map(|e| (g.to_index(e.source()), g.to_index(e.target()))).collect()),
+        h_edges : later_edges(&|u| search_h.leaf.color_of(u), h.edge_references().map(|e| (h.to_index(e.source()), h.to_index(e.target()))).collect()),
+    }
+}
+
+/// Outcome of `certified_compare`, with the evidence supporting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompareResult {
+    /// The graphs are isomorphic: p maps the edges of g onto the edges of h,
+    /// as checked before returning it
+    Isomorphic(Vec<usize>),
+    /// The graphs are not isomorphic: their keys differ as described
+    NotIsomorphic(KeyDiff),
+}
+
+/// Compare `g` and `h`, returning the evidence of the outcome.
+///
+/// Isomorphic graphs come with the isomorphism found by
+/// `GraphKey::isomorphism_proof`. Other graphs come with the `KeyDiff` of
+/// `diff_keys`, which is never empty: either the node counts differ, or the
+/// canonical graphs, see `GraphKey::canonical_adjacency`, differ in the edges
+/// of `node` to later nodes. Each graph is searched once.
+pub fn certified_compare<G, H>(g : G, h : H) -> CompareResult
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+    H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    let (search_g, search_h) = (canonical_search(g), canonical_search(h));
+    if search_g.descriptor != search_h.descriptor {
+        return CompareResult::NotIsomorphic(diff_searches(g, h, &search_g, &search_h));
+    }
+
+    let p = map_leaves(&search_g, &search_h);
+    assert!(maps_edges(g, h, &p), "graphs with the same key must be mapped onto each other by their canonical labellings");
+    CompareResult::Isomorphic(p)
+}
+
+/// Checks that p maps the edge multiset of g onto the edge multiset of h.
+pub(crate) fn maps_edges<G, H>(g : G, h : H, p : &[usize]) -> bool
+where
+    G : NodeCompactIndexable + IntoEdges,
+    H : NodeCompactIndexable + IntoEdges
+{
+    let normalize = |u : usize, v : usize| (u.min(v), u.max(v));
+
+    let mut mapped : Vec<(usize, usize)> = g
+        .edge_references()
+        .map(|e| normalize(p[g.to_index(e.source())], p[g.to_index(e.target())]))
+        .collect();
+    let mut target : Vec<(usize, usize)> = h
+        .edge_references()
+        .map(|e| normalize(h.to_index(e.source()), h.to_index(e.target())))
+        .collect();
+
+    mapped.sort();
+    target.sort();
+    mapped == target
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{GraphKey, CompareResult, certified_compare, diff_keys};
+    use crate::derived::induced_subgraph;
+    use crate::common::{gen_test_graph, generate_random_graph, generate_permutated_graph};
+    use petgraph::graph::{NodeIndex, UnGraph};
+    use petgraph::algo::is_isomorphic;
+    use petgraph::visit::EdgeRef;
+    use std::collections::HashSet;
+
+    #[test]
+    fn key_diffs() {
+
+        let g = gen_test_graph();
+        assert!(diff_keys(&g, &generate_permutated_graph(&g)).is_empty());
+
+        // 6-cycle and two triangles : same degrees, different canonical graphs
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let triangles = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+        let diff = diff_keys(&c6, &triangles);
+        let (index, node) = (diff.index.unwrap(), diff.node.unwrap());
+        assert_ne!(diff.g_edges, diff.h_edges);
+        assert!(diff.g_edges.iter().chain(diff.h_edges.iter()).all(|(u, v)| *u == node && *v > node));
+
+        let (dg, dh) = (GraphKey::new(&c6), GraphKey::new(&triangles));
+        assert_eq!(dg.get_descriptor()[..index], dh.get_descriptor()[..index]);
+        assert_ne!(dg.get_descriptor()[index], dh.get_descriptor()[index]);
+
+        // Node counts differ from the start
+        let diff = diff_keys(&c6, &UnGraph::<usize, ()>::from_edges([(0, 1)]));
+        assert_eq!((diff.index, diff.node), (Some(0), None));
+    }
+
+    #[test]
+    fn certified_comparisons() {
+
+        let edges = |g : &UnGraph<usize, ()>, p : &dyn Fn(usize) -> usize| {
+            let mut edges : Vec<(usize, usize)> = g.edge_references().map(|e| {
+                let (u, v) = (p(e.source().index()), p(e.target().index()));
+                (u.min(v), u.max(v))
+            }).collect();
+            edges.sort();
+            edges
+        };
+
+        let g = generate_random_graph(30, 0.2);
+        let h = generate_permutated_graph(&g);
+        match certified_compare(&g, &h) {
+            CompareResult::Isomorphic(p) => assert_eq!(edges(&g, &|u| p[u]), edges(&h, &|u| u)),
+            CompareResult::NotIsomorphic(_) => panic!("a permuted graph is isomorphic"),
+        }
+
+        // One more edge : the witness row matches the canonical graphs
+        let mut h = generate_permutated_graph(&gen_test_graph());
+        h.add_edge(0.into(), 5.into(), ());
+        let g = gen_test_graph();
+        let CompareResult::NotIsomorphic(witness) = certified_compare(&g, &h) else { panic!("graphs with different edge counts are not isomorphic") };
+        assert_eq!(witness, diff_keys(&g, &h));
+        let node = witness.node.unwrap();
+        assert_ne!(witness.g_edges, witness.h_edges);
+        let later = |adjacency : Vec<Vec<usize>>| adjacency[node].iter().filter(|v| **v > node).map(|v| (node, *v)).collect::<Vec<_>>();
+        assert_eq!(witness.g_edges, later(GraphKey::canonical_adjacency(&g)));
+        assert_eq!(witness.h_edges, later(GraphKey::canonical_adjacency(&h)));
+
+        let CompareResult::NotIsomorphic(witness) = certified_compare(&g, &UnGraph::<usize, ()>::from_edges([(0, 1)])) else { panic!("graphs with different node counts are not isomorphic") };
+        assert_eq!((witness.index, witness.node), (Some(0), None));
+    }
+
+    #[test]
+    fn u128_encodings() {
+
+        // The 64 graphs on the nodes 0..4, one per subset of the 6 pairs
+        let pairs = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+        let graphs : Vec<UnGraph<usize, ()>> = (0..64u32).map(|subset| {
+            let mut g = UnGraph::<usize, ()>::default();
+            (0..4).for_each(|i| { g.add_node(i); });
+            for (_, (u, v)) in pairs.iter().enumerate().filter(|(i, _)| subset & (1 << i) != 0) {
+                g.add_edge(NodeIndex::new(*u), NodeIndex::new(*v), ());
+            }
+            g
+        }).collect();
+
+        let codes : Vec<u128> = graphs.iter().map(|g| GraphKey::to_u128(g).unwrap()).collect();
+        assert_eq!(codes.iter().collect::<HashSet<_>>().len(), 11);
+        for (g, code_g) in graphs.iter().zip(codes.iter()) {
+            for (h, code_h) in graphs.iter().zip(codes.iter()) {
+                assert_eq!(code_g == code_h, is_isomorphic(g, h));
+            }
+        }
+
+        // Isolated nodes count
+        let edge = UnGraph::<usize, ()>::from_edges([(0, 1)]);
+        let mut edge_and_node = edge.clone();
+        edge_and_node.add_node(2);
+        assert_ne!(GraphKey::to_u128(&edge), GraphKey::to_u128(&edge_and_node));
+
+        let c15 = UnGraph::<usize, ()>::from_edges((0..15).map(|u| (u, (u + 1) % 15)));
+        assert!(GraphKey::to_u128(&c15).is_some());
+        assert_eq!(GraphKey::to_u128(&UnGraph::<usize, ()>::from_edges((0..16).map(|u| (u, (u + 1) % 16)))), None);
+        assert_eq!(GraphKey::to_u128(&UnGraph::<usize, ()>::from_edges([(0, 1), (1, 1)])), None);
+        assert_eq!(GraphKey::to_u128(&UnGraph::<usize, ()>::from_edges([(0, 1), (0, 1)])), None);
+    }
+
+    #[test]
+    fn labellings_align_isomorphic_graphs() {
+
+        let relabelled_edges = |g : &UnGraph<usize, ()>, labelling : &[usize]| {
+            let mut edges : Vec<(usize, usize)> = g.edge_references().map(|e| {
+                let (u, v) = (labelling[e.source().index()], labelling[e.target().index()]);
+                (u.min(v), u.max(v))
+            }).collect();
+            edges.sort();
+            edges
+        };
+
+        // The symmetric graphs keep several leaves until the final selection
+        let c6 = UnGraph::<usize, ()>::from_edges((0..6).map(|u| (u, (u + 1) % 6)));
+        for g in [gen_test_graph(), generate_random_graph(25, 0.2), c6] {
+            let g2 = generate_permutated_graph(&g);
+            let (key, labelling) = GraphKey::with_labelling(&g);
+            let (key2, labelling2) = GraphKey::with_labelling(&g2);
+
+            assert_eq!(key, key2);
+            assert_eq!(key, GraphKey::new(&g));
+            assert_eq!(labelling.iter().collect::<HashSet<_>>().len(), g.node_count());
+            assert_eq!(relabelled_edges(&g, &labelling), relabelled_edges(&g2, &labelling2));
+        }
+    }
+
+    #[test]
+    fn canonical_bfs_orders() {
+
+        // Edges of g relabelled by the position of each node in order
+        let edges = |g : &UnGraph<usize, ()>, order : &[usize]| {
+            let mut position = vec![0; order.len()];
+            order.iter().enumerate().for_each(|(i, u)| position[*u] = i);
+            let mut edges : Vec<(usize, usize)> = g.edge_references().map(|e| {
+                let (u, v) = (position[e.source().index()], position[e.target().index()]);
+                (u.min(v), u.max(v))
+            }).collect();
+            edges.sort();
+            edges
+        };
+
+        let mut g = generate_random_graph(30, 0.1);
+        g.add_node(30);
+        let h = generate_permutated_graph(&g);
+        let (order_g, order_h) = (GraphKey::canonical_bfs_order(&g), GraphKey::canonical_bfs_order(&h));
+        assert_eq!(order_g.iter().collect::<HashSet<_>>().len(), 31);
+        assert_eq!(edges(&g, &order_g), edges(&h, &order_h));
+
+        // Each node after the first of its component has an earlier neighbor
+        let path = UnGraph::<usize, ()>::from_edges([(3, 1), (1, 0), (0, 2)]);
+        let order = GraphKey::canonical_bfs_order(&path);
+        let position : Vec<usize> = (0..4).map(|u| order.iter().position(|v| *v == u).unwrap()).collect();
+        for u in order.iter().skip(1) {
+            assert!(path.neighbors(NodeIndex::new(*u)).any(|v| position[v.index()] < position[*u]));
+        }
+    }
+
+    #[test]
+    fn canonical_mph_relabels_to_canonical_form() {
+
+        let g = generate_random_graph(25, 0.3);
+        let mph = GraphKey::canonical_mph(&g);
+
+        #[cfg(feature = "serde")]
+        let mph : Vec<u32> = bincode::deserialize(&bincode::serialize(&mph).unwrap()).unwrap();
+
+        let mut positions = mph.clone();
+        positions.sort();
+        assert_eq!(positions, (0..25).collect::<Vec<u32>>());
+
+        let mut adjacency = vec![Vec::new(); 25];
+        for e in g.edge_references() {
+            let (u, v) = (mph[e.source().index()] as usize, mph[e.target().index()] as usize);
+            adjacency[u].push(v);
+            adjacency[v].push(u);
+        }
+        adjacency.iter_mut().for_each(|row| row.sort());
+        assert_eq!(adjacency, GraphKey::canonical_adjacency(&g));
+    }
+
+    #[test]
+    fn preferred_canonical_permutations() {
+
+        // The path 0 - 1 - 2 has two maximal leaves, swapping its ends
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        let (_, labellings) = GraphKey::maximal_leaves(&path);
+        assert_eq!(labellings.len(), 2);
+        for labelling in labellings.iter() {
+            assert_eq!(&GraphKey::canonical_permutation_preferring(&path, labelling), labelling);
+        }
+
+        // An unreachable preference still gives a maximal leaf
+        let g = generate_random_graph(15, 0.3);
+        let (_, labellings) = GraphKey::maximal_leaves(&g);
+        let labelling = GraphKey::canonical_permutation_preferring(&g, &(0..15).rev().collect::<Vec<usize>>());
+        assert!(labellings.contains(&labelling));
+    }
+
+    #[test]
+    fn components_in_canonical_order() {
+
+        // Path 3 - 4 - 8 - 9 and two triangles, the second one on 5, 6, 7
+        let g = UnGraph::<usize, ()>::from_edges([(5, 6), (3, 4), (0, 1), (4, 8), (1, 2), (6, 7), (8, 9), (2, 0), (7, 5)]);
+        let components = GraphKey::canonical_components(&g);
+        assert_eq!(components, vec![vec![0, 1, 2], vec![5, 6, 7], vec![3, 4, 8, 9]]);
+
+        // The components of a relabelled graph have the same keys
+        let keys = |g : &UnGraph<usize, ()>| -> Vec<GraphKey> {
+            GraphKey::canonical_components(g).iter().map(|c| GraphKey::new(&induced_subgraph(g, c))).collect()
+        };
+        for _ in 0..5 {
+            let h = generate_permutated_graph(&g);
+            assert_eq!(GraphKey::canonical_components(&h).iter().map(Vec::len).collect::<Vec<usize>>(), vec![3, 3, 4]);
+            assert_eq!(keys(&h), keys(&g));
+        }
+    }
+
+    #[test]
+    fn isomorphism_proof_maps_edges() {
+
+        for g in [gen_test_graph(), generate_random_graph(40, 0.2)] {
+            let h = generate_permutated_graph(&g);
+            let p = GraphKey::isomorphism_proof(&g, &h).unwrap();
+
+            let mut mapped : Vec<(usize, usize)> = g.edge_references().map(|e| {
+                let (u, v) = (p[e.source().index()], p[e.target().index()]);
+                (u.min(v), u.max(v))
+            }).collect();
+            let mut expected : Vec<(usize, usize)> = h.edge_references().map(|e| {
+                let (u, v) = (e.source().index(), e.target().index());
+                (u.min(v), u.max(v))
+            }).collect();
+            mapped.sort();
+            expected.sort();
+
+            assert_eq!(mapped, expected);
+        }
+
+        let mut h = generate_permutated_graph(&gen_test_graph());
+        h.add_edge(0.into(), 1.into(), ());
+        assert_eq!(GraphKey::isomorphism_proof(&gen_test_graph(), &h), None);
+    }
+
+    #[test]
+    fn canonical_adjacency_of_isomorphic_graphs() {
+
+        for g in [gen_test_graph(), generate_random_graph(40, 0.2)] {
+            let adjacency = GraphKey::canonical_adjacency(&g);
+            assert_eq!(GraphKey::canonical_adjacency(&generate_permutated_graph(&g)), adjacency);
+
+            let canonical = GraphKey::to_record(&g).graph;
+            for (u, row) in adjacency.iter().enumerate() {
+                let mut neighbors : Vec<usize> = canonical.neighbors(NodeIndex::new(u)).map(|v| v.index()).collect();
+                neighbors.sort();
+                assert_eq!(*row, neighbors);
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_map_relabels_to_the_canonical_adjacency() {
+
+        for g in [gen_test_graph(), generate_random_graph(30, 0.2)] {
+            let map = GraphKey::canonical_map(&g);
+            assert_eq!(map.len(), g.node_count());
+
+            let mut adjacency = vec![Vec::new(); g.node_count()];
+            for e in g.edge_references() {
+                let (u, v) = (map[&e.source()], map[&e.target()]);
+                adjacency[u].push(v);
+                adjacency[v].push(u);
+            }
+            adjacency.iter_mut().for_each(|row| row.sort());
+
+            assert_eq!(adjacency, GraphKey::canonical_adjacency(&g));
+        }
+    }
+
+    #[test]
+    fn winning_colourings_are_discrete() {
+
+        for g in [gen_test_graph(), generate_random_graph(30, 0.2)] {
+            let leaf = GraphKey::winning_colouring(&g);
+            assert!(leaf.is_discrete());
+            assert_eq!(leaf.cells_iter().map(|(color, _)| color).collect::<Vec<usize>>(), (0..g.node_count()).collect::<Vec<usize>>());
+
+            let graph = leaf.compute_graph_from_discrete(&g);
+            let canonical = GraphKey::to_record(&g).graph;
+            let edges = |h : &UnGraph<usize, ()>| {
+                let mut edges : Vec<(usize, usize)> = h.edge_references().map(|e| {
+                    let (u, v) = (e.source().index(), e.target().index());
+                    (u.min(v), u.max(v))
+                }).collect();
+                edges.sort();
+                edges
+            };
+            assert_eq!(edges(&graph), edges(&canonical));
+            assert_eq!(GraphKey::new(&graph), GraphKey::new(&g));
+        }
+    }
+
+    #[test]
+    fn canonical_edge_lists() {
+
+        let g = gen_test_graph();
+        let edge_list = GraphKey::canonical_edge_list(&g);
+        assert_eq!(GraphKey::canonical_edge_list(&generate_permutated_graph(&g)), edge_list);
+        assert!(edge_list.starts_with("n=10;"));
+        assert_eq!(edge_list.matches(';').count(), g.edge_count());
+
+        let mut h = generate_permutated_graph(&g);
+        h.add_edge(NodeIndex::new(0), NodeIndex::new(1), ());
+        assert_ne!(GraphKey::canonical_edge_list(&h), edge_list);
+
+        let path = UnGraph::<usize, ()>::from_edges([(2, 0), (0, 1)]);
+        assert_eq!(GraphKey::canonical_edge_list(&path), "n=3;0-2;1-2");
+    }
+
+    #[test]
+    fn trace_colourings_refine_to_the_leaf() {
+
+        for g in [gen_test_graph(), generate_random_graph(30, 0.2)] {
+            let snapshots = GraphKey::trace_colourings(&g);
+            let n = g.node_count();
+
+            // Two nodes share a cell iff they share a color
+            let cell_count = |colors : &Vec<usize>| colors.iter().collect::<HashSet<_>>().len();
+
+            for w in snapshots.windows(2) {
+                assert!(cell_count(&w[1]) > cell_count(&w[0]));
+                for u in 0..n {
+                    for v in 0..n {
+                        assert!(w[1][u] != w[1][v] || w[0][u] == w[0][v]);
+                    }
+                }
+            }
+
+            let last = snapshots.last().unwrap();
+            assert_eq!(cell_count(last), n);
+
+            // The last snapshot is the canonical labelling
+            let canonical = GraphKey::to_record(&g).graph;
+            let mut relabelled : Vec<(usize, usize)> = g.edge_references().map(|e| {
+                let (u, v) = (last[e.source().index()], last[e.target().index()]);
+                (u.min(v), u.max(v))
+            }).collect();
+            let mut expected : Vec<(usize, usize)> = canonical.edge_references().map(|e| (e.source().index(), e.target().index())).collect();
+            relabelled.sort();
+            expected.sort();
+            assert_eq!(relabelled, expected);
+        }
+    }
+
+    #[test]
+    fn canonical_csr_matches_canonical_graph() {
+
+        for g in [gen_test_graph(), generate_random_graph(40, 0.2)] {
+            let (indptr, indices) = GraphKey::canonical_csr(&g);
+
+            assert_eq!(indptr.len(), g.node_count() + 1);
+            assert_eq!(indices.len(), 2 * g.edge_count());
+
+            let mut csr_edges = Vec::new();
+            for u in 0..g.node_count() {
+                let row = &indices[indptr[u]..indptr[u + 1]];
+                assert!(row.windows(2).all(|w| w[0] <= w[1]));
+                csr_edges.extend(row.iter().filter(|v| u < **v).map(|v| (u, *v)));
+            }
+
+            let canonical = GraphKey::to_record(&g).graph;
+            let mut graph_edges : Vec<(usize, usize)> = canonical
+                .edge_references()
+                .map(|e| (e.source().index(), e.target().index()))
+                .collect();
+            graph_edges.sort();
+
+            assert_eq!(csr_edges, graph_edges);
+            assert_eq!(GraphKey::canonical_csr(&generate_permutated_graph(&g)), (indptr, indices));
+        }
+    }
+
+    #[test]
+    fn canonical_csr_lists_self_loops_once() {
+
+        // Path 0 - 1 - 2 with a loop on an end: 2 edges listed twice, the loop once
+        let g = UnGraph::<usize, ()>::from_edges([(0, 0), (0, 1), (1, 2)]);
+        let (indptr, indices) = GraphKey::canonical_csr(&g);
+        assert_eq!(indices.len(), 5);
+
+        let loops : Vec<usize> = (0..3).filter(|u| indices[indptr[*u]..indptr[u + 1]].contains(u)).collect();
+        assert_eq!(loops.len(), 1);
+        let u = loops[0];
+        assert_eq!(indices[indptr[u]..indptr[u + 1]].iter().filter(|v| **v == u).count(), 1);
+        assert_eq!(indptr[u + 1] - indptr[u], 2);
+    }
+}
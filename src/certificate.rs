@@ -0,0 +1,202 @@
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges};
+
+use crate::{GraphKey, PermGroup, canonical_search};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+//
+// Certificate object
+//
+
+/// Everything a canonical labelling of a graph produces, computed by a single
+/// search, see `GraphKey::certificate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Certificate {
+    /// Key of the graph
+    pub key : GraphKey,
+    /// labelling[u] is the canonical position of the node u
+    pub labelling : Vec<usize>,
+    /// Automorphisms generating the automorphism group, as permutations of the
+    /// nodes. Empty when the group is trivial.
+    pub generators : Vec<Vec<usize>>,
+    /// Order of the automorphism group
+    pub group_order : u128,
+    /// Orbits of the automorphism group on the nodes, each one sorted, ordered
+    /// by their smallest node
+    pub orbits : Vec<Vec<usize>>,
+}
+
+impl GraphKey {
+
+    /// Compute the certificate of `g`: its key, its canonical labelling, and
+    /// its automorphism group given by generators, order and orbits.
+    ///
+    /// The leaves of the search reaching the canonical descriptor are the
+    /// images of the canonical leaf by the automorphisms of `g`. Generators are
+    /// picked among them, keeping only those outside the group generated so far.
+    pub fn certificate<G>(g : G) -> Certificate
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        let n = g.node_count();
+        let labelling : Vec<usize> = (0..n).map(|u| result.leaf.color_of(u)).collect();
+
+        // node at each canonical position
+        let mut node_at = vec![0; n];
+        for (u, position) in labelling.iter().enumerate() {
+            node_at[*position] = u;
+        }
+
+        let mut group = PermGroup::new(n, &[]);
+        let mut generators = Vec::new();
+
+        for leaf in result.equivalent_leaves.iter() {
+            let automorphism : Vec<usize> = (0..n).map(|u| node_at[leaf.color_of(u)]).collect();
+            if !group.contains(&automorphism) {
+                generators.push(automorphism);
+                group = PermGroup::new(n, &generators);
+            }
+        }
+
+        Certificate {
+            key : GraphKey::from_descriptor(result.descriptor),
+            labelling,
+            orbits : orbits(&generators, n),
+            generators,
+            group_order : result.group_order as u128,
+        }
+    }
+
+    /// Compute the key of `g` and the labelling of every leaf of the search
+    /// reaching the canonical descriptor, the canonical leaf first.
+    ///
+    /// labellings[i][u] is the position of the node u in the i-th leaf. For any
+    /// two labellings p and q, the map sending u to the node at position p[u]
+    /// in q is an automorphism, and each automorphism arises once from the
+    /// first labelling: there are |Aut(g)| labellings.
+    pub fn maximal_leaves<G>(g : G) -> (GraphKey, Vec<Vec<usize>>)
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        let n = g.node_count();
+        let labellings = std::iter::once(&result.leaf).chain(result.equivalent_leaves.iter())
+            .map(|leaf| (0..n).map(|u| leaf.color_of(u)).collect())
+            .collect();
+
+        (GraphKey::from_descriptor(result.descriptor), labellings)
+    }
+}
+
+/// Orbits of the group generated by `generators` on 0..n.
+fn orbits(generators : &[Vec<usize>], n : usize) -> Vec<Vec<usize>> {
+    let mut orbit_of = vec![usize::MAX; n];
+    let mut orbits = Vec::new();
+
+    for u in 0..n {
+        if orbit_of[u] != usize::MAX { continue; }
+
+        let mut orbit = vec![u];
+        orbit_of[u] = orbits.len();
+        let mut i = 0;
+        while i < orbit.len() {
+            for s in generators {
+                let v = s[orbit[i]];
+                if orbit_of[v] == usize::MAX {
+                    orbit_of[v] = orbits.len();
+                    orbit.push(v);
+                }
+            }
+            i += 1;
+        }
+
+        orbit.sort();
+        orbits.push(orbit);
+    }
+
+    orbits
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{GraphKey, PermGroup, is_automorphism};
+    use crate::common::{gen_test_graph, generate_permutated_graph};
+    use petgraph::graph::UnGraph;
+
+    #[test]
+    fn petersen_certificate() {
+
+        // Outer 5-cycle, inner pentagram and spokes
+        let petersen = UnGraph::<usize, ()>::from_edges([
+            (0, 1), (1, 2), (2, 3), (3, 4), (4, 0),
+            (5, 7), (7, 9), (9, 6), (6, 8), (8, 5),
+            (0, 5), (1, 6), (2, 7), (3, 8), (4, 9),
+        ]);
+
+        let certificate = GraphKey::certificate(&petersen);
+        assert_eq!(certificate.key, GraphKey::new(&petersen));
+        assert_eq!(certificate.group_order, 120);
+        assert_eq!(certificate.orbits, vec![(0..10).collect::<Vec<usize>>()]);
+        assert!(!certificate.generators.is_empty());
+        assert!(certificate.generators.iter().all(|p| is_automorphism(&petersen, p)));
+        assert_eq!(PermGroup::new(10, &certificate.generators).order(), 120);
+
+        let mut positions = certificate.labelling.clone();
+        positions.sort();
+        assert_eq!(positions, (0..10).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn certificate_of_asymmetric_graph() {
+
+        // Spider with legs of lengths 1, 2 and 3
+        let spider = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)]);
+        let certificate = GraphKey::certificate(&spider);
+        assert_eq!(certificate.group_order, 1);
+        assert!(certificate.generators.is_empty());
+        assert_eq!(certificate.orbits.len(), 7);
+
+        let g = gen_test_graph();
+        let certificate = GraphKey::certificate(&g);
+        assert_eq!(GraphKey::certificate(&generate_permutated_graph(&g)).key, certificate.key);
+        assert_eq!(Some(certificate.group_order), GraphKey::automorphism_group_order(&g));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn certificate_round_trip() {
+
+        let certificate = GraphKey::certificate(&gen_test_graph());
+        let bytes = bincode::serialize(&certificate).unwrap();
+        let loaded : super::Certificate = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded, certificate);
+    }
+
+    #[test]
+    fn maximal_leaves_of_c4() {
+
+        let c4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0)]);
+        let (key, labellings) = GraphKey::maximal_leaves(&c4);
+        assert_eq!(key, GraphKey::new(&c4));
+        assert_eq!(labellings.len(), 8);
+
+        // node_at[i][k] : node at position k in the i-th leaf
+        let node_at : Vec<Vec<usize>> = labellings.iter().map(|p| {
+            let mut inverse = vec![0; 4];
+            p.iter().enumerate().for_each(|(u, k)| inverse[*k] = u);
+            inverse
+        }).collect();
+
+        let mut automorphisms = std::collections::HashSet::new();
+        for q in node_at.iter() {
+            let automorphism : Vec<usize> = (0..4).map(|u| q[labellings[0][u]]).collect();
+            assert!(is_automorphism(&c4, &automorphism));
+            automorphisms.insert(automorphism);
+        }
+        assert_eq!(automorphisms.len(), 8);
+    }
+}
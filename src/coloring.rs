@@ -1,24 +1,55 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::collections::HashSet;
 use std::collections::BinaryHeap;
+use std::collections::VecDeque;
 use std::cmp::Reverse;
 
-use petgraph::Undirected;
+use petgraph::{Direction, Undirected};
 use petgraph::graph::{NodeIndex, UnGraph, Graph};
 
 use petgraph::visit::EdgeRef;
-use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges};
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoNeighborsDirected, IntoEdges};
 
 use std::cmp::Ordering;
 
+use crate::GraphKey;
+use crate::derived::induced_subgraph;
+
 /// A `Color` is a subset of graph nodes.
 ///
-/// Example : Cell{ color : 0, members : { 0, 1, 2 } }
+/// Example : Cell{ color : 0, members : { 0, 1, 2 }, parent : None }
 
 #[derive(Debug, Clone)]
 struct Cell {
     color : usize,
     members : HashSet<usize>,
+    /// Index of the cell it was split from, None for the initial cells
+    parent : Option<usize>,
+}
+
+/// Sum of a color, or a degree, and a count. With the `checked-arithmetic`
+/// feature, an overflow panics even in release builds, instead of wrapping
+/// around to a color that would silently corrupt the key.
+#[cfg(feature = "checked-arithmetic")]
+pub(crate) fn color_add(a : usize, b : usize) -> usize {
+    a.checked_add(b).expect("color arithmetic overflows usize")
+}
+
+#[cfg(not(feature = "checked-arithmetic"))]
+pub(crate) fn color_add(a : usize, b : usize) -> usize {
+    a + b
+}
+
+/// Product of two counts, e.g. the number of pairs of nodes, checked like
+/// `color_add`.
+#[cfg(feature = "checked-arithmetic")]
+pub(crate) fn color_mul(a : usize, b : usize) -> usize {
+    a.checked_mul(b).expect("color arithmetic overflows usize")
+}
+
+#[cfg(not(feature = "checked-arithmetic"))]
+pub(crate) fn color_mul(a : usize, b : usize) -> usize {
+    a * b
 }
 
 /// A `Colouring` is a set of colors covering the graph.
@@ -46,6 +77,17 @@ pub struct Colouring {
     node_color : Vec<usize>,
 }
 
+/// Bucket `(f(n) / eps).round()` of the weight of each node n of 0..size.
+///
+/// Panics if eps is not positive.
+pub(crate) fn quantize<F>(size : usize, f : F, eps : f64) -> Vec<i64>
+where
+    F : Fn(usize) -> f64
+{
+    assert!(eps > 0.0, "quantization step must be positive");
+    (0..size).map(|u| (f(u) / eps).round() as i64).collect()
+}
+
 impl Colouring {
     
     /// Create ne new uniform colouring of a graph.
@@ -54,23 +96,178 @@ impl Colouring {
         G : NodeCompactIndexable
     {
         let size = g.node_count();
-        let cell_0 = Cell { color: 0, members : HashSet::from_iter(0..size) };
+
+        // The empty graph has no cell at all, hence is already discrete.
+        if size == 0 {
+            return Colouring::from_labels::<usize>(&[]);
+        }
+
+        let cell_0 = Cell { color: 0, members : HashSet::from_iter(0..size), parent : None };
+
+        // Refinement may split the colouring up to one cell per node
+        let mut cells = Vec::with_capacity(size);
+        cells.push(cell_0);
+        let mut color_cell = HashMap::with_capacity(size);
+        color_cell.insert(0, 0);
         
         Colouring {
             size,
-            cells: vec![ cell_0 ],
-            color_cell: HashMap::from([ (0, 0) ]),
+            cells,
+            color_cell,
             node_cell : vec![ 0 ; size ],
             node_color : vec![ 0; size ],
         }
     }
 
+    /// Create a colouring of a graph grouping the nodes by degree.
+    pub fn new_by_degree<G>(g : G) -> Colouring
+    where 
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let degrees : Vec<usize> = (0..g.node_bound()).map(|u| g.neighbors(g.from_index(u)).count()).collect();
+        Colouring::new_with_degrees(g.node_count(), &degrees)
+    }
+
+    /// Create the colouring of a graph given by refining its uniform colouring,
+    /// the same as `Colouring::new(g)` followed by `refine(g)`, starting from
+    /// the colouring by degree.
+    ///
+    /// The first step of the refinement of the uniform colouring splits it by
+    /// degree, leaving every color pending but 0, the color of the lowest
+    /// degree. The refinement of the colouring by degree skips that color too,
+    /// so both give the same colors.
+    pub fn new_refined_by_degree<G>(g : G) -> Colouring
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let mut gc = Colouring::new_by_degree(g);
+        gc.refine_except(Neighbors(g), Some(0), WorklistOrder::Heap, &mut RefineScratch::default());
+        gc
+    }
+
+    /// Create a colouring of a graph grouping the nodes n by the bucket
+    /// `(f(n) / eps).round()` of their weight.
+    ///
+    /// `eps` is the width of the buckets: weights differing by noise much
+    /// smaller than eps share a cell unless they straddle a bucket boundary,
+    /// and weights further apart than eps never do. Cells are ordered by
+    /// increasing bucket. Panics if eps is not positive.
+    ///
+    /// The colouring only keeps the order of the buckets, see
+    /// `GraphKey::new_quantized` for keys holding their values.
+    pub fn new_quantized<G, F>(g : G, f : F, eps : f64) -> Colouring
+    where
+        G : NodeCompactIndexable,
+        F : Fn(usize) -> f64
+    {
+        Colouring::from_labels(&quantize(g.node_count(), f, eps))
+    }
+
+    /// Create the colouring grouping the nodes by degree from a precomputed
+    /// degree sequence, where degrees[n] is the degree of the node n.
+    ///
+    /// Panics if degrees does not have one entry per node.
+    pub fn new_with_degrees(size : usize, degrees : &[usize]) -> Colouring {
+        assert_eq!(degrees.len(), size, "degree sequence length does not match the node count");
+        Colouring::from_labels(degrees)
+    }
+
+    /// Create a colouring grouping the nodes by label, where labels[n] is the
+    /// label of the node n.
+    ///
+    /// Cells are ordered by increasing label, so the colouring only depends on
+    /// the labels and not on the node indices: a cell's color is the number of
+    /// nodes carrying a smaller label.
+    pub fn from_labels<L>(labels : &[L]) -> Colouring
+    where
+        L : Ord
+    {
+        let size = labels.len();
+        let mut order : Vec<usize> = (0..size).collect();
+        order.sort_by(|a, b| labels[*a].cmp(&labels[*b]));
+
+        let mut gc = Colouring {
+            size,
+            cells : Vec::with_capacity(size),
+            color_cell : HashMap::with_capacity(size),
+            node_cell : vec![ 0 ; size ],
+            node_color : vec![ 0 ; size ],
+        };
+
+        let mut start = 0;
+        while start < size {
+            let mut end = start + 1;
+            while end < size && labels[order[end]] == labels[order[start]] {
+                end += 1;
+            }
+
+            let cell_idx = gc.cells.len();
+            for u in order[start..end].iter() {
+                gc.node_cell[*u] = cell_idx;
+                gc.node_color[*u] = start;
+            }
+            gc.cells.push(Cell { color : start, members : order[start..end].iter().copied().collect(), parent : None });
+            gc.color_cell.insert(start, cell_idx);
+
+            start = end;
+        }
+
+        gc
+    }
+
+    /// Create the coarsest common refinement of two colourings of the same
+    /// nodes: two nodes share a cell iff they share a cell in both colourings.
+    ///
+    /// Cells are ordered by the color in self, then by the color in other.
+    pub fn intersect(&self, other : &Colouring) -> Colouring {
+        assert_eq!(self.size, other.size, "cannot intersect colourings of different sizes");
+
+        let labels : Vec<(usize, usize)> = (0..self.size).map(|u| (self.node_color[u], other.node_color[u])).collect();
+        Colouring::from_labels(&labels)
+    }
+
+    /// Sizes of the cells, in increasing order of color
+    pub fn get_cell_sizes(&self) -> Vec<usize> {
+        let mut cells : Vec<&Cell> = self.cells.iter().collect();
+        cells.sort_by_key(|c| c.color);
+        cells.iter().map(|c| c.members.len()).collect()
+    }
+
+    /// Number of cells of each size, by increasing size.
+    ///
+    /// After refining the uniform colouring, the counts are an isomorphism
+    /// invariant of the graph.
+    pub fn cell_size_counts(&self) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::new();
+        for c in self.cells.iter() {
+            *counts.entry(c.members.len()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Iterate over the cells in increasing order of color, as pairs of the
+    /// color and the members of the cell, sorted.
+    pub fn cells_iter(&self) -> impl Iterator<Item = (usize, Vec<usize>)> + '_ {
+        let mut cells : Vec<&Cell> = self.cells.iter().collect();
+        cells.sort_by_key(|c| c.color);
+        cells.into_iter().map(|c| {
+            let mut members : Vec<usize> = c.members.iter().copied().collect();
+            members.sort();
+            (c.color, members)
+        })
+    }
+
     /// Checks if the colouring is discrete, i.e. each color is associated to
     /// a single node
     pub fn is_discrete(&self) -> bool {
         self.cells.len() == self.size
     }
 
+    /// Number of nodes coloured.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
     pub fn get_cell_count(&self) -> usize {
         self.cells.len()
     }
@@ -79,6 +276,43 @@ impl Colouring {
         self.cells[idx].members.iter().copied().collect()
     }
 
+    /// Number of neighbors in each cell of the members of the cell cell_idx.
+    ///
+    /// Returns a pair per cell, the cell itself included, in increasing order
+    /// of color: the color of the cell, and the number of neighbors each member
+    /// of cell_idx has in it, the members being sorted. The cell is equitable
+    /// iff every vector is constant.
+    pub fn degree_profile<G>(&self, cell_idx : usize, g : G) -> Vec<(usize, Vec<usize>)>
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let mut members = self.get_cell_members(cell_idx);
+        members.sort();
+
+        let mut colors : Vec<usize> = self.color_cell.keys().copied().collect();
+        colors.sort();
+        let mut profile : Vec<(usize, Vec<usize>)> = colors.into_iter().map(|color| (color, vec![0; members.len()])).collect();
+        let position : HashMap<usize, usize> = profile.iter().enumerate().map(|(i, (color, _))| (*color, i)).collect();
+        for (i, u) in members.iter().enumerate() {
+            for v in g.neighbors(g.from_index(*u)) {
+                let degree = &mut profile[position[&self.color_of(g.to_index(v))]].1[i];
+                *degree = color_add(*degree, 1);
+            }
+        }
+
+        profile
+    }
+
+    /// Returns the current color of the node
+    pub fn color_of(&self, node : usize) -> usize {
+        self.node_color[node]
+    }
+
+    /// Returns the index of the cell containing the node
+    pub fn cell_of(&self, node : usize) -> usize {
+        self.node_cell[node]
+    }
+
     /// TODO : delete
     pub fn print_cells(&self) {
         for i in 0..self.cells.len() { 
@@ -115,27 +349,32 @@ impl Colouring {
 
     /// Individualize the node n in the cell of index cell_idx
     /// 
-    /// Returns the color of the newly created cell
-    pub fn individualize(&mut self, cell_idx : usize, node : usize) -> usize {
+    /// Returns the color of the newly created cell, or None if the cell is
+    /// already a singleton, in which case the colouring is left unchanged.
+    pub fn individualize(&mut self, cell_idx : usize, node : usize) -> Option<usize> {
         
-        // check if the len of the cell is > 1
-        assert!(1 < self.cells[cell_idx].members.len());
+        // A singleton cell has nothing to split
+        if self.cells[cell_idx].members.len() == 1 {
+            return None;
+        }
 
         let new_cell_index = self.cells.len();
 
         let old_color = self.cells[cell_idx].color;
+        let next_color = color_add(old_color, 1);
         let new_cell = Cell{ 
             color : old_color, 
-            members : HashSet::from([node])
+            members : HashSet::from([node]),
+            parent : Some(cell_idx),
         };
 
         // Edit the old cell
         {
-            let mut old_cell = &mut self.cells[cell_idx];
+            let old_cell = &mut self.cells[cell_idx];
             old_cell.members.remove(&node);
-            old_cell.color = old_color+1;
+            old_cell.color = next_color;
             for u in old_cell.members.iter() {
-                self.node_color[*u] = old_color + 1;
+                self.node_color[*u] = next_color;
             }
         }
         
@@ -143,41 +382,120 @@ impl Colouring {
         self.cells.push(new_cell);
 
         // Edit self.color_cell
-        if let Some(old_cell_index) = self.color_cell.remove(&old_color) {
-            self.color_cell.insert(old_color+1, old_cell_index);
-        }
+        // old_color always points to cell_idx, which is now the cell of color old_color+1
+        self.color_cell.insert(next_color, cell_idx);
         self.color_cell.insert(old_color, new_cell_index);
 
         // Edit self.node_cell
         self.node_cell[node] = new_cell_index;
 
-        old_color + 1
+        debug_assert!(self.color_cell_is_consistent());
+        debug_assert!(self.colors_are_contiguous());
+
+        Some(next_color)
+
+    }
+
+    /// Individualize the node n in the cell of index cell_idx, then refine the
+    /// colouring according to the graph g.
+    ///
+    /// Returns the color of the new cell followed by the trace of the refinement.
+    /// Custom searches should prefer it to calling `individualize` alone, since
+    /// an unrefined colouring is not isomorphism-invariant.
+    pub fn individualize_and_refine<G>(&mut self, cell_idx : usize, node : usize, g : G) -> Vec<usize>
+    where 
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let mut trace : Vec<usize> = self.individualize(cell_idx, node).into_iter().collect();
+        trace.extend(self.refine(g));
+        trace
+    }
+
+    /// Same as `individualize_and_refine` on a refined colouring, using the
+    /// buffers of scratch.
+    ///
+    /// Only the two cells made by the individualization are seeded, see
+    /// `refine_from`.
+    pub(crate) fn individualize_and_refine_into<N>(&mut self, cell_idx : usize, node : usize, g : N, scratch : &mut RefineScratch) -> Vec<usize>
+    where 
+        N : RefineGraph
+    {
+        let Some(next_color) = self.individualize(cell_idx, node) else {
+            return vec![];
+        };
+
+        let mut trace = vec![next_color];
+        trace.extend(self.refine_from_into(g, &[next_color - 1, next_color], scratch));
+        trace
+    }
+
+    /// Compute the key of the subgraph of g induced by the members of the cell
+    /// of index cell_idx.
+    pub fn induced_key<G>(&self, cell_idx : usize, g : G) -> GraphKey
+    where 
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let mut members = self.get_cell_members(cell_idx);
+        members.sort();
+        GraphKey::new(&induced_subgraph(g, &members))
+    }
 
+    /// Checks that color_cell maps the color of every cell to this cell, and
+    /// holds no other entry.
+    fn color_cell_is_consistent(&self) -> bool {
+        self.color_cell.len() == self.cells.len()
+            && self.cells.iter().enumerate().all(|(i, c)| self.color_cell.get(&c.color) == Some(&i))
+    }
+
+    /// Checks that the cells tile the positions 0..size: sorted by color, each
+    /// cell's color is the color of the previous one plus its size, and every
+    /// member of a cell has its color.
+    ///
+    /// Colors are positions rather than ranks, so they form exactly the range
+    /// 0..get_cell_count() only once the colouring is discrete, which is what
+    /// `compute_graph_from_discrete` relies on.
+    pub fn colors_are_contiguous(&self) -> bool {
+        let mut cells : Vec<&Cell> = self.cells.iter().collect();
+        cells.sort_by_key(|c| c.color);
+
+        let mut next = 0;
+        for c in cells {
+            if c.color != next || c.members.iter().any(|u| self.node_color[*u] != c.color) {
+                return false;
+            }
+            next += c.members.len();
+        }
+
+        next == self.size
     }
 
     /// Split the cell into two cells, such that the first one contains
     /// the nodes in new_members
+    ///
+    /// Returns the color of the old cell, the position following the new
+    /// one. Colors are positions, so they stay below `size` however many
+    /// splits are made and need no renumbering.
     pub fn split_cell(&mut self, cell_idx : usize, new_members : Vec<usize>) -> usize {
         
         let old_color = self.cells[cell_idx].color;
-        let new_color = old_color + new_members.len();
+        let new_color = color_add(old_color, new_members.len());
         let new_cell_index = self.cells.len();
 
         // Generate the new cell
         let new_cell = Cell{ 
             color : old_color, 
-            members : HashSet::from_iter(new_members.clone())
+            members : HashSet::from_iter(new_members.clone()),
+            parent : Some(cell_idx),
         };
 
         // Edit the old cell
         {
-            let mut old_cell = &mut self.cells[cell_idx];
+            let old_cell = &mut self.cells[cell_idx];
 
             for u in new_members.iter() {
                 old_cell.members.remove(u);
             }
 
-            let new_color = old_cell.color + new_members.len();
             old_cell.color = new_color; 
 
             for u in old_cell.members.iter() {
@@ -189,9 +507,8 @@ impl Colouring {
         self.cells.push(new_cell);
 
         // Edit self.cell_color
-        if let Some(v) = self.color_cell.remove(&old_color) {
-            self.color_cell.insert(new_color, v);
-        }
+        // old_color always points to cell_idx, which is now the cell of color new_color
+        self.color_cell.insert(new_color, cell_idx);
         self.color_cell.insert(old_color, new_cell_index);
 
         // Edit self.node_cell
@@ -202,6 +519,34 @@ impl Colouring {
         new_color
     }
 
+    /// Undo the splits made since the colouring had `cell_count` cells, merging
+    /// the newest cells back into the cells they were split from.
+    ///
+    /// Individualizations and refinements only add cells at the end, so this
+    /// restores the colouring exactly as it was, cell indices included.
+    pub(crate) fn undo_splits(&mut self, cell_count : usize) {
+        while self.cells.len() > cell_count {
+            let cell = self.cells.pop().unwrap();
+            let parent = cell.parent.expect("initial cells cannot be merged");
+
+            // The split cell kept the color of the parent, which took a new one
+            self.color_cell.remove(&self.cells[parent].color);
+            self.color_cell.insert(cell.color, parent);
+
+            for u in cell.members {
+                self.node_cell[u] = parent;
+                self.cells[parent].members.insert(u);
+            }
+
+            self.cells[parent].color = cell.color;
+            for u in self.cells[parent].members.iter() {
+                self.node_color[*u] = cell.color;
+            }
+        }
+
+        debug_assert!(self.color_cell_is_consistent());
+    }
+
     /// Refine a Colouring according to the graph g.
     /// 
     /// This function is implemented in an isomorhpic-invariant way, i.e. for
@@ -214,129 +559,315 @@ impl Colouring {
     where 
         G : NodeCompactIndexable + IntoNeighbors
     {
+        self.refine_with(g, WorklistOrder::Heap)
+    }
+
+    /// Refine a Colouring like `refine`, studying at first only the cells of
+    /// seed_colors instead of all of them.
+    ///
+    /// The colouring must already be equitable with respect to the other
+    /// cells, e.g. a refined colouring in which the seeds were split since.
+    /// The other colors are then known to split nothing, but they still count
+    /// as pending when one of their cells is split, so the partition and the
+    /// trace are the same as with `refine`.
+    pub fn refine_from<G>(&mut self, g : G, seed_colors : &[usize]) -> Vec<usize>
+    where 
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        self.refine_from_into(Neighbors(g), seed_colors, &mut RefineScratch::default())
+    }
+
+    /// Refine a Colouring like `refine`, and report how much work was needed.
+    pub fn refine_report<G>(&mut self, g : G) -> RefineReport
+    where 
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        // Every split creates exactly one new cell, whose color is recorded in
+        // the trace: no color is reused since colors are cell start positions.
+        let cell_count = self.get_cell_count();
+        let trace = self.refine(g);
+
+        let mut new_colors = trace.clone();
+        new_colors.sort();
+
+        RefineReport {
+            splits : self.get_cell_count() - cell_count,
+            max_color : self.color_cell.keys().copied().max().unwrap_or(0),
+            new_colors,
+            trace,
+        }
+    }
+
+    /// Refine a Colouring to the coarsest equitable partition, when only the
+    /// partition matters (plain Weisfeiler-Leman colour refinement).
+    ///
+    /// The resulting partition is the same as with `refine`, but the colors are
+    /// studied in FIFO order, which is cheaper than maintaining the heap.
+    pub fn wl_refine<G>(&mut self, g : G)
+    where 
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        self.refine_with(g, WorklistOrder::Fifo);
+    }
+
+    /// Refine a Colouring according to the graph g, studying the colors in the
+    /// given order.
+    ///
+    /// The final partition does not depend on the order, but the trace does:
+    /// only `WorklistOrder::Heap` gives the trace used to compare tree nodes.
+    pub fn refine_with<G>(&mut self, g : G, order : WorklistOrder) -> Vec<usize>
+    where 
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        self.refine_into(Neighbors(g), order, &mut RefineScratch::default())
+    }
+
+    /// Refine a Colouring like `refine`, also splitting the nodes of a cell by
+    /// sig(node).
+    ///
+    /// The signature must be isomorphism-invariant, e.g. the number of
+    /// triangles through the node, for the result to be. The cells are first
+    /// split by signature, then refined: the signature of a node does not
+    /// change, so this is the same as splitting on the signature and the
+    /// neighbor counts together.
+    ///
+    /// Returns the signature of each cell after the split, by increasing color,
+    /// as its high and low 32 bits, followed by the trace of the refinement:
+    /// two colourings only get the same trace if they split on the same values.
+    pub fn refine_with_signature<G, S>(&mut self, g : G, sig : S) -> Vec<usize>
+    where
+        G : NodeCompactIndexable + IntoNeighbors,
+        S : Fn(usize) -> u64
+    {
+        let signatures : Vec<u64> = (0..self.size).map(sig).collect();
+        *self = self.intersect(&Colouring::from_labels(&signatures));
+
+        let mut colors : Vec<usize> = self.color_cell.keys().copied().collect();
+        colors.sort();
+        let mut trace = Vec::with_capacity(color_mul(2, colors.len()));
+        for c in colors {
+            let u = *self.cells[self.color_cell[&c]].members.iter().next().expect("cells are never empty");
+            trace.push((signatures[u] >> 32) as usize);
+            trace.push((signatures[u] & 0xffff_ffff) as usize);
+        }
+        trace.extend(self.refine(g));
+        trace
+    }
+
+    /// Refine a Colouring according to the directed graph g: the nodes of a
+    /// cell are split by their number of successors and of predecessors in
+    /// every cell.
+    pub fn refine_directed<G>(&mut self, g : G) -> Vec<usize>
+    where 
+        G : NodeCompactIndexable + IntoNeighborsDirected
+    {
+        self.refine_into(DirectedNeighbors(g), WorklistOrder::Heap, &mut RefineScratch::default())
+    }
+
+    /// Refine a Colouring like `refine_with`, using the buffers of scratch
+    /// instead of allocating new ones.
+    pub(crate) fn refine_into<N>(&mut self, g : N, order : WorklistOrder, scratch : &mut RefineScratch) -> Vec<usize>
+    where 
+        N : RefineGraph
+    {
+        self.refine_except(g, None, order, scratch)
+    }
+
+    /// Same as `refine_from`, using the buffers of scratch.
+    ///
+    /// A limited refinement counts the colors that split nothing as rounds,
+    /// so it studies all of them as `refine` does.
+    pub(crate) fn refine_from_into<N>(&mut self, g : N, seed_colors : &[usize], scratch : &mut RefineScratch) -> Vec<usize>
+    where 
+        N : RefineGraph
+    {
+        if scratch.max_rounds.is_some() {
+            return self.refine_into(g, WorklistOrder::Heap, scratch);
+        }
         if self.is_discrete() {
             return vec![];
         }
 
-        let mut trace = Vec::new();
+        scratch.colors.clear();
+        scratch.colors.extend_from_slice(seed_colors);
+        scratch.colors.sort();
+        self.refine_pending(g, WorklistOrder::Heap, scratch, true)
+    }
 
-        // Uncounted_colors = set of colors to handle, updated during the main loop.
-        // A heap is used so that the colors are explored in a deterministic order.
-        // The elements in the heap are in reversed order in order to minimize the Trace
-        // TODO : For now, all cells are added. Later, start only with the newly generated color, passed as argument
-        // CANDO : benchmark with non-reversed elements
-        let mut uncounted_colors = BinaryHeap::new();
-        for (k, _) in self.color_cell.iter() {
-            uncounted_colors.push(Reverse(*k));
+    /// Same as `refine_into`, the color `skipped` being only studied if a split
+    /// makes it pending.
+    fn refine_except<N>(&mut self, g : N, skipped : Option<usize>, order : WorklistOrder, scratch : &mut RefineScratch) -> Vec<usize>
+    where 
+        N : RefineGraph
+    {
+        if self.is_discrete() {
+            return vec![];
         }
 
+        // Uncounted_colors = set of colors to handle, updated during the main loop.
+        scratch.colors.clear();
+        scratch.colors.extend(self.color_cell.keys().copied().filter(|c| Some(*c) != skipped));
+        scratch.colors.sort();
+        self.refine_pending(g, order, scratch, false)
+    }
+
+    /// Study the colors of scratch.colors, then the ones made pending by the
+    /// splits, until none is left.
+    ///
+    /// When seeded, the colors of the initial cells that are not in
+    /// scratch.colors are pending too, but are only studied once one of their
+    /// cells is split: studying them before would split nothing.
+    fn refine_pending<N>(&mut self, g : N, order : WorklistOrder, scratch : &mut RefineScratch, seeded : bool) -> Vec<usize>
+    where 
+        N : RefineGraph
+    {
+        let mut trace = Vec::new();
+        scratch.pending.reset(order, &scratch.colors);
+
+        // The colors are studied in an order that only depends on the colouring,
+        // so stopping early still gives an isomorphism-invariant colouring.
+        let mut rounds = 0;
+        let mut max_studied = 0;
+
         loop {
             
-            let studied_color = uncounted_colors.pop();
+            if scratch.max_rounds.is_some_and(|max| rounds == max) { break; }
+            rounds += 1;
+
+            let studied_color = scratch.pending.pop();
 
             // break condition            
             if studied_color.is_none() { break; }
-            let Reverse(studied_color) = studied_color.unwrap();
-            
-            // remove potential duplicates
-            while let Some(_next) = uncounted_colors.peek() {
-                if _next.0 == studied_color {
-                    uncounted_colors.pop();
-                } else {
-                    break;
-                }
-            } 
+            let studied_color = studied_color.unwrap();
 
-            // degrees[n] = # of connections between node n and studied_cell
-            // visited_cells keeps the set of cells visited while iteration 
-            let mut degrees : HashMap<usize, usize> = HashMap::new();       
-            let mut visited_cells : HashSet<usize> = HashSet::new();        
+            // The heap pops the colors in increasing order, so the initial colors
+            // above max_studied would not have been studied yet by `refine`
+            max_studied = max_studied.max(studied_color);
+            self.study_color(g, studied_color, scratch, &mut trace, None, seeded.then_some(max_studied));
+        }
 
-            // Fill the degree map
-            // In brackets in order to drom the Cell after iteration
-            {
-                let studied_cell = &self.cells[*self.color_cell.get(&studied_color).unwrap()];
-                for u in studied_cell.members.iter() {
-                    for v in g.neighbors( g.from_index(*u) ) {
-                        degrees.entry( g.to_index(v)  ).and_modify(|counter| *counter += 1).or_insert(1);
-                        visited_cells.insert(self.node_color[g.to_index(v)]);
+        debug_assert!(self.colors_are_contiguous());
+
+        trace
+    }
+
+    /// Split the cells according to the number of neighbors of their nodes in
+    /// the cell of `studied_color`, which is one step of `refine`.
+    ///
+    /// The new colors are pushed on trace and on the pending colors of scratch,
+    /// and the colors of the split cells on split_cells if given. If
+    /// pending_above is given, the split cells of greater colors are pushed
+    /// too: their first part keeps the color, which was pending.
+    fn study_color<N>(&mut self, g : N, studied_color : usize, scratch : &mut RefineScratch, trace : &mut Vec<usize>, mut split_cells : Option<&mut Vec<usize>>, pending_above : Option<usize>)
+    where 
+        N : RefineGraph
+    {
+        let RefineScratch { pending : uncounted_colors, degrees, visited_cells, sorted_cells, splits, .. } = scratch;
+
+        // degrees[n][k] = # of connections of kind k between node n and studied_cell
+        // visited_cells keeps the set of cells visited while iteration 
+        degrees.clear();
+        visited_cells.clear();
+
+        // Fill the degree map
+        // In brackets in order to drom the Cell after iteration
+        {
+            let studied_cell = &self.cells[*self.color_cell.get(&studied_color).unwrap()];
+            for u in studied_cell.members.iter() {
+                g.for_each_neighbor(*u, |v, kind| {
+
+                    // Singleton cells cannot be split: skip them
+                    if self.cells[self.node_cell[v]].members.len() == 1 {
+                        return;
                     }
-                }
+
+                    let degree = &mut degrees.entry(v).or_insert([0; 2])[kind];
+                    *degree = color_add(*degree, 1);
+                    visited_cells.insert(self.node_color[v]);
+                });
             }
+        }
+        
+        // For each visited cell (iter in order of color)
+        // Cells without any neighbor in the studied cell are not visited: all their
+        // members (isolated nodes included) have degree 0, so they cannot be split.
+        sorted_cells.clear();
+        sorted_cells.extend(visited_cells.iter().copied());
+        sorted_cells.sort();
+
+        for _color in sorted_cells.iter() {
             
-            // For each visited cell (iter in order of color)
-            let mut visited_cells : Vec<usize> = visited_cells.into_iter().collect();
-            visited_cells.sort();
-
-            for _color in visited_cells {
-                
-                let _cell_idx = *self.color_cell.get(&_color).unwrap();
+            let _cell_idx = *self.color_cell.get(_color).unwrap();
 
-                // Do not process if cell is singleton                
-                if self.cells[_cell_idx].members.len() == 1 {
-                    continue;
-                }
+            // Do not process if cell is singleton                
+            if self.cells[_cell_idx].members.len() == 1 {
+                continue;
+            }
 
-                // Get cell subset according to degree
+            // Get cell subset according to degree
 
 
-                let mut splits : HashMap<usize, Vec<usize>> = HashMap::new();
+            splits.clear();
 
-                {
-                    let c1 = &self.cells[_cell_idx];
+            {
+                let c1 = &self.cells[_cell_idx];
+                
+                for u in c1.members.iter() {
                     
-                    for u in c1.members.iter() {
-                        
-                        let _d = match degrees.get(u) {
-                            None => { 0 },
-                            Some(n) => { *n }
-                        };
-
-                        if let Some(m) = splits.get_mut(&_d) { 
-                            m.push(*u);
-                        } else {
-                            splits.insert(_d, vec![*u] );
-                        }
+                    let _d = match degrees.get(u) {
+                        None => { [0; 2] },
+                        Some(n) => { *n }
+                    };
+
+                    if let Some(m) = splits.get_mut(&_d) { 
+                        m.push(*u);
+                    } else {
+                        splits.insert(_d, vec![*u] );
                     }
                 }
-                
+            }
+            
 
-                // Do not split the cell if no degree difference
-                if splits.len() == 1 { continue; }
+            // Do not split the cell if no degree difference
+            if splits.len() == 1 { continue; }
 
-                // Get the list of different degrees                
-                let mut splits_degrees : Vec<usize> = Vec::with_capacity(splits.len());
-                for (_d, _) in splits.iter() { splits_degrees.push(*_d); }
-                splits_degrees.sort();
-                let last_degree = splits_degrees.pop().unwrap();
+            if let Some(split_cells) = split_cells.as_mut() {
+                split_cells.push(*_color);
+            }
+            if pending_above.is_some_and(|max_studied| *_color > max_studied) {
+                uncounted_colors.push(*_color);
+            }
 
-                // Split cell according to degree (splits are made with increasing degrees)
-                for _d in splits_degrees {
-    
-                    // Split cell
-                    let h = splits.remove(&_d).unwrap();
-                    // let h_len = h.len();
-                    let new_color = self.split_cell(_cell_idx, h);
-                    
-                    // Add new cell to uncounted
-                    uncounted_colors.push(Reverse(new_color));    
-                    
-                    // update trace
-                    trace.push(new_color);
-                }
+            // Get the list of different degrees                
+            let mut splits_degrees : Vec<[usize; 2]> = Vec::with_capacity(splits.len());
+            for (_d, _) in splits.iter() { splits_degrees.push(*_d); }
+            splits_degrees.sort();
+            let last_degree = splits_degrees.pop().unwrap();
 
-                // Add the last cell to uncounted
-                {
-                    let h = splits.remove(&last_degree).unwrap();
-                    if h.len() > 1 {
-                        let new_c = self.cells[_cell_idx].color;
-                        uncounted_colors.push(Reverse(new_c));    
-                    }
+            // Split cell according to degree (splits are made with increasing degrees)
+            for _d in splits_degrees {
+
+                // Split cell
+                let h = splits.remove(&_d).unwrap();
+                // let h_len = h.len();
+                let new_color = self.split_cell(_cell_idx, h);
+                
+                // Add new cell to uncounted
+                uncounted_colors.push(new_color);
+                
+                // update trace
+                trace.push(new_color);
+            }
+
+            // Add the last cell to uncounted
+            {
+                let h = splits.remove(&last_degree).unwrap();
+                if h.len() > 1 {
+                    let new_c = self.cells[_cell_idx].color;
+                    uncounted_colors.push(new_c);
                 }
-            } 
+            }
         }
-
-        trace
     }
 
     //
@@ -354,19 +885,40 @@ impl Colouring {
         panic!("select_cell called on a discrete coloring");
     }
 
+    /// Select the first cell of smallest size among the non-singleton cells,
+    /// i.e. the one of lowest color (Traces / nauty default target cell rule).
+    ///
+    /// Returns the index of the selected cell.
+    pub fn select_cell_smallest_first(&self) -> usize {
+
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.members.len() > 1)
+            .min_by_key(|(_, c)| (c.members.len(), c.color))
+            .map(|(i, _)| i)
+            .expect("select_cell called on a discrete coloring")
+    }
+
     /// Generate the desriptor associated to the colouring
     pub fn compute_graph_from_discrete<G>(&self, g : G) -> Graph<usize, (), Undirected>
     where
         G : NodeCompactIndexable + IntoNeighbors +  IntoEdges
     {
-        // assert!(self.is_discrete());
+        // With two nodes of the same color, their edges would be merged silently
+        // and the descriptor would not describe g.
+        assert!(self.is_discrete(), "compute_graph_from_discrete called on a non-discrete colouring");
 
-        let edges : Vec<(usize, usize)> = g
+        // Edges are sorted so that the canonical graph does not depend on the
+        // order in which g lists its edges.
+        let mut edges : Vec<(usize, usize)> = g
             .edge_references()
             .map(|e| { 
-                ( self.node_color[g.to_index(e.source())], self.node_color[g.to_index(e.target())] ) 
+                let (u, v) = ( self.node_color[g.to_index(e.source())], self.node_color[g.to_index(e.target())] );
+                (u.min(v), u.max(v))
             })
             .collect();
+        edges.sort();
         
         let mut _g = UnGraph::<usize, ()>::new_undirected();
          
@@ -392,6 +944,236 @@ impl Colouring {
  
 
 
+/// Statistics of a call to `refine`, see `Colouring::refine_report`.
+///
+/// trace : trace returned by `refine`
+/// splits : number of cell splits performed
+/// max_color : largest color of the refined colouring
+/// new_colors : colors that did not exist before the call, in increasing order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefineReport {
+    pub trace : Vec<usize>,
+    pub splits : usize,
+    pub max_color : usize,
+    pub new_colors : Vec<usize>,
+}
+
+/// One step of a refinement, see `RefinementStepper`.
+///
+/// color : color studied by the step
+/// split_cells : colors of the cells split by the step, in increasing order
+/// new_colors : colors created by the step, in the order of the trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    pub color : usize,
+    pub split_cells : Vec<usize>,
+    pub new_colors : Vec<usize>,
+}
+
+/// Runs `Colouring::refine` one studied color at a time.
+///
+/// The steps are the iterations of `refine`, in the same order: once the
+/// stepper returns None, its colouring is the colouring `refine` computes, and
+/// the new colors of the steps make up its trace.
+pub struct RefinementStepper<G> {
+    g : Neighbors<G>,
+    colouring : Colouring,
+    scratch : RefineScratch,
+}
+
+impl<G> RefinementStepper<G>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    /// Prepare the refinement of `colouring` according to the graph g.
+    pub fn new(g : G, colouring : Colouring) -> RefinementStepper<G> {
+        let mut scratch = RefineScratch::default();
+        scratch.colors.extend(colouring.color_cell.keys().copied());
+        scratch.colors.sort();
+        scratch.pending.reset(WorklistOrder::Heap, &scratch.colors);
+
+        RefinementStepper { g : Neighbors(g), colouring, scratch }
+    }
+
+    /// Study the next pending color, or return None at the fixed point.
+    pub fn next_step(&mut self) -> Option<StepInfo> {
+        let color = self.scratch.pending.pop()?;
+        let mut split_cells = Vec::new();
+        let mut new_colors = Vec::new();
+        self.colouring.study_color(self.g, color, &mut self.scratch, &mut new_colors, Some(&mut split_cells), None);
+
+        Some(StepInfo { color, split_cells, new_colors })
+    }
+
+    /// Colouring after the steps taken so far.
+    pub fn colouring(&self) -> &Colouring {
+        &self.colouring
+    }
+
+    pub fn into_colouring(self) -> Colouring {
+        self.colouring
+    }
+}
+
+impl<G> Iterator for RefinementStepper<G>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    type Item = StepInfo;
+
+    fn next(&mut self) -> Option<StepInfo> {
+        self.next_step()
+    }
+}
+
+/// Order in which `refine` studies the pending colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorklistOrder {
+    /// Smallest color first, giving a canonical trace
+    Heap,
+    /// First in, first out : faster when only the partition matters
+    Fifo,
+}
+
+/// Graph as seen by the refinement: the neighbors of a node, each one tagged
+/// with the kind of connection (0 or 1). Nodes are split according to their
+/// number of connections of each kind to the studied cell.
+pub(crate) trait RefineGraph : Copy {
+    fn for_each_neighbor<F>(&self, u : usize, f : F)
+    where
+        F : FnMut(usize, usize);
+}
+
+/// Neighbors of an undirected graph, all of the same kind.
+#[derive(Clone, Copy)]
+pub(crate) struct Neighbors<G>(pub(crate) G);
+
+impl<G> RefineGraph for Neighbors<G>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    fn for_each_neighbor<F>(&self, u : usize, mut f : F)
+    where
+        F : FnMut(usize, usize)
+    {
+        let g = self.0;
+        for v in g.neighbors(g.from_index(u)) {
+            f(g.to_index(v), 0);
+        }
+    }
+}
+
+/// Neighbors of a directed graph: the successors of u are of kind 0 and its
+/// predecessors of kind 1. A self-loop makes u both.
+#[derive(Clone, Copy)]
+pub(crate) struct DirectedNeighbors<G>(pub(crate) G);
+
+impl<G> RefineGraph for DirectedNeighbors<G>
+where
+    G : NodeCompactIndexable + IntoNeighborsDirected
+{
+    fn for_each_neighbor<F>(&self, u : usize, mut f : F)
+    where
+        F : FnMut(usize, usize)
+    {
+        let g = self.0;
+        for v in g.neighbors_directed(g.from_index(u), Direction::Outgoing) {
+            f(g.to_index(v), 0);
+        }
+        for v in g.neighbors_directed(g.from_index(u), Direction::Incoming) {
+            f(g.to_index(v), 1);
+        }
+    }
+}
+
+/// Buffers used by `refine`, kept between calls to avoid allocating them again
+/// for every refinement of the search.
+pub(crate) struct RefineScratch {
+    colors : Vec<usize>,
+    pending : PendingColors,
+    degrees : HashMap<usize, [usize; 2]>,
+    visited_cells : HashSet<usize>,
+    sorted_cells : Vec<usize>,
+    splits : HashMap<[usize; 2], Vec<usize>>,
+    /// Maximal number of colors studied by each refinement, unlimited if None
+    pub(crate) max_rounds : Option<usize>,
+}
+
+impl Default for RefineScratch {
+    fn default() -> RefineScratch {
+        RefineScratch {
+            colors : Vec::new(),
+            pending : PendingColors::Heap(BinaryHeap::new()),
+            degrees : HashMap::new(),
+            visited_cells : HashSet::new(),
+            sorted_cells : Vec::new(),
+            splits : HashMap::new(),
+            max_rounds : None,
+        }
+    }
+}
+
+/// Set of colors still to be studied by `refine`.
+enum PendingColors {
+    /// The elements in the heap are in reversed order in order to minimize the Trace
+    Heap(BinaryHeap<Reverse<usize>>),
+    /// Queue of colors, together with the set of queued colors
+    Fifo(VecDeque<usize>, HashSet<usize>),
+}
+
+impl PendingColors {
+
+    /// Empty the set and fill it with colors, reusing the buffers if the order
+    /// is unchanged.
+    fn reset(&mut self, order : WorklistOrder, colors : &[usize]) {
+        match (order, &mut *self) {
+            (WorklistOrder::Heap, PendingColors::Heap(heap)) => heap.clear(),
+            (WorklistOrder::Fifo, PendingColors::Fifo(queue, queued)) => {
+                queue.clear();
+                queued.clear();
+            }
+            (WorklistOrder::Heap, _) => *self = PendingColors::Heap(BinaryHeap::new()),
+            (WorklistOrder::Fifo, _) => *self = PendingColors::Fifo(VecDeque::new(), HashSet::new()),
+        }
+
+        for color in colors {
+            self.push(*color);
+        }
+    }
+
+    fn push(&mut self, color : usize) {
+        match self {
+            PendingColors::Heap(heap) => heap.push(Reverse(color)),
+            PendingColors::Fifo(queue, queued) => {
+                if queued.insert(color) {
+                    queue.push_back(color);
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        match self {
+            PendingColors::Heap(heap) => {
+                let Reverse(color) = heap.pop()?;
+
+                // remove potential duplicates
+                while heap.peek() == Some(&Reverse(color)) {
+                    heap.pop();
+                }
+
+                Some(color)
+            }
+            PendingColors::Fifo(queue, queued) => {
+                let color = queue.pop_front()?;
+                queued.remove(&color);
+                Some(color)
+            }
+        }
+    }
+}
+
+
 /// K-dim coloring (see TODO)
 #[derive(Debug, Eq, Clone)]
 pub struct Kdim (usize, Vec<usize>);
@@ -427,7 +1209,726 @@ impl PartialEq for Kdim {
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that all members of a cell have the same number of neighbors in
+    /// every cell, i.e. that no further refinement is possible.
+    fn is_equitable(gc : &Colouring, g : &Graph<usize, (), Undirected>) -> bool {
+        gc.cells.iter().all(|c| {
+            let profiles : HashSet<Vec<usize>> = c.members.iter().map(|u| {
+                let mut profile = vec![0; gc.size];
+                for v in g.neighbors(NodeIndex::new(*u)) {
+                    profile[gc.node_color[v.index()]] += 1;
+                }
+                profile
+            }).collect();
+            profiles.len() == 1
+        })
+    }
+
+    /// Coarsest equitable refinement of the partition given by labels, computed
+    /// by naive colour refinement. Returns the partition as a set of sorted cells.
+    fn naive_refinement(g : &Graph<usize, (), Undirected>, labels : &[usize]) -> HashSet<Vec<usize>> {
+
+        let n = g.node_count();
+        let mut colors = labels.to_vec();
+
+        loop {
+            let signatures : Vec<(usize, Vec<usize>)> = (0..n).map(|u| {
+                let mut neigh : Vec<usize> = g.neighbors(NodeIndex::new(u)).map(|v| colors[v.index()]).collect();
+                neigh.sort();
+                (colors[u], neigh)
+            }).collect();
 
+            let mut distinct = signatures.clone();
+            distinct.sort();
+            distinct.dedup();
 
+            let new_colors : Vec<usize> = signatures.iter().map(|s| distinct.binary_search(s).unwrap()).collect();
+            let stable = distinct.len() == colors.iter().collect::<HashSet<_>>().len();
+            colors = new_colors;
 
+            if stable { break; }
+        }
+
+        let mut cells : HashMap<usize, Vec<usize>> = HashMap::new();
+        for (u, c) in colors.iter().enumerate() {
+            cells.entry(*c).or_default().push(u);
+        }
+        cells.into_values().collect()
+    }
+
+    fn partition(gc : &Colouring) -> HashSet<Vec<usize>> {
+        gc.cells.iter().map(|c| {
+            let mut members : Vec<usize> = c.members.iter().copied().collect();
+            members.sort();
+            members
+        }).collect()
+    }
+
+    #[test]
+    fn refine_matches_naive_refinement() {
+
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+
+        for n in [10, 30, 60] {
+            for p in [0.05, 0.1, 0.3] {
+                let mut g = UnGraph::<usize, ()>::new_undirected();
+                (0..n).for_each(|i| { g.add_node(i); });
+                for u in 0..n {
+                    for v in (u+1)..n {
+                        if rng.gen_range((0.)..1.) < p {
+                            g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+                        }
+                    }
+                }
+
+                let labels : Vec<usize> = (0..n).map(|_| rng.gen_range(0..3)).collect();
+
+                let mut gc = Colouring::new(&g);
+                gc.refine(&g);
+                assert!(is_equitable(&gc, &g));
+                assert_eq!(partition(&gc), naive_refinement(&g, &vec![0; n]));
+
+                let mut gc = Colouring::from_labels(&labels);
+                gc.refine(&g);
+                assert!(is_equitable(&gc, &g));
+                assert_eq!(partition(&gc), naive_refinement(&g, &labels));
+            }
+        }
+    }
+
+    #[test]
+    fn refine_from_matches_refine() {
+
+        use rand::Rng;
+        use crate::generators::{random_gnp, random_regular};
+        let mut rng = rand::thread_rng();
+        let mut scratch = RefineScratch::default();
+
+        // Sparse, dense and regular graphs, the latter needing many individualizations
+        let cycle = UnGraph::<usize, ()>::from_edges((0..40u32).map(|u| (u, (u + 1) % 40)));
+        let graphs = [random_gnp(60, 0.05, 1), random_gnp(60, 0.3, 2), random_regular(60, 3, 3), random_regular(60, 4, 4), cycle];
+
+        for g in graphs.iter() {
+            for _ in 0..5 {
+                let (mut full, mut seeded) = (Colouring::new(g), Colouring::new(g));
+                full.refine(g);
+                seeded.refine(g);
+
+                // Individualize random nodes until the colouring is discrete
+                while !full.is_discrete() {
+                    let cell_idx = full.select_cell_smallest_first();
+                    let members = full.get_cell_members(cell_idx);
+                    let node = members[rng.gen_range(0..members.len())];
+
+                    let mut full_trace : Vec<usize> = full.individualize(cell_idx, node).into_iter().collect();
+                    full_trace.extend(full.refine(g));
+                    assert_eq!(seeded.individualize_and_refine_into(cell_idx, node, Neighbors(g), &mut scratch), full_trace);
+                    assert_eq!(seeded.node_color, full.node_color);
+                    assert_eq!(seeded.node_cell, full.node_cell);
+                }
+            }
+        }
+
+        // Splitting a cell by hand and seeding both parts
+        let g = &graphs[2];
+        let (mut full, mut seeded) = (Colouring::new(g), Colouring::new(g));
+        for gc in [&mut full, &mut seeded] {
+            gc.refine(g);
+            gc.split_cell(0, (0..30).collect());
+        }
+        assert_eq!(seeded.refine_from(g, &[0, 30]), full.refine(g));
+        assert_eq!(seeded.node_color, full.node_color);
+    }
+
+    #[test]
+    fn worklist_orders_reach_same_partition() {
+
+        let g = UnGraph::<usize, ()>::from_edges([
+            (0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0), (0, 6), (6, 7), (7, 8), (8, 2), (3, 9)
+        ]);
+
+        let mut heap = Colouring::new(&g);
+        let heap_trace = heap.refine(&g);
+
+        let mut fifo = Colouring::new(&g);
+        let fifo_trace = fifo.refine_with(&g, WorklistOrder::Fifo);
+
+        let mut wl = Colouring::new(&g);
+        wl.wl_refine(&g);
+
+        assert_eq!(partition(&heap), partition(&fifo));
+        assert_eq!(partition(&heap), partition(&wl));
+        assert_eq!(partition(&heap), naive_refinement(&g, &[0; 10]));
+
+        // Only the heap order gives the canonical trace
+        assert_ne!(heap_trace, fifo_trace);
+
+        let mut again = Colouring::new(&g);
+        assert_eq!(again.refine_with(&g, WorklistOrder::Heap), heap_trace);
+    }
+
+    #[test]
+    fn refine_report_counts_splits() {
+
+        // A star only separates its center from the leaves
+        let star = UnGraph::<usize, ()>::from_edges((1..10).map(|v| (0, v)));
+        let report = Colouring::new(&star).refine_report(&star);
+        assert_eq!(report.splits, 1);
+        assert_eq!(report.max_color, 9);
+
+        // A dense graph with irregular degrees needs more splits
+        let mut dense = UnGraph::<usize, ()>::new_undirected();
+        (0..10).for_each(|i| { dense.add_node(i); });
+        for u in 0..10 {
+            for v in (u + 1)..10 {
+                if (u + 2 * v) % 5 != 0 && v != u + 1 {
+                    dense.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+                }
+            }
+        }
+        let mut gc = Colouring::new(&dense);
+        let report = gc.refine_report(&dense);
+        assert!(report.splits > 1);
+        assert_eq!(report.splits + 1, gc.get_cell_count());
+
+        let mut again = Colouring::new(&dense);
+        assert_eq!(again.refine(&dense), report.trace);
+    }
+
+    #[test]
+    fn refine_report_new_colors() {
+
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..20 {
+            let n = 25;
+            let mut g = UnGraph::<usize, ()>::new_undirected();
+            (0..n).for_each(|i| { g.add_node(i); });
+            for u in 0..n {
+                for v in (u+1)..n {
+                    if rng.gen_range((0.)..1.) < 0.15 {
+                        g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+                    }
+                }
+            }
+
+            let mut gc = Colouring::new(&g);
+            gc.refine(&g);
+            if gc.is_discrete() { continue; }
+
+            let cell_idx = gc.select_cell_smallest_first();
+            let node = *gc.cells[cell_idx].members.iter().min().unwrap();
+            gc.individualize(cell_idx, node);
+
+            let before : HashSet<usize> = gc.color_cell.keys().copied().collect();
+            let cell_count = gc.cells.len();
+            let report = gc.refine_report(&g);
+            let after : HashSet<usize> = gc.color_cell.keys().copied().collect();
+
+            let mut created : Vec<usize> = after.difference(&before).copied().collect();
+            created.sort();
+            assert_eq!(report.new_colors, created);
+            assert_eq!(report.new_colors.len(), gc.cells.len() - cell_count);
+        }
+    }
+
+    #[test]
+    fn degree_hint_matches_computed_degrees() {
+
+        let g = UnGraph::<usize, ()>::from_edges([
+            (0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (3, 4), (4, 5)
+        ]);
+
+        let hinted = Colouring::new_with_degrees(6, &[3, 2, 3, 3, 2, 1]);
+        let computed = Colouring::new_by_degree(&g);
+        assert_eq!(partition(&hinted), partition(&computed));
+        assert_eq!(hinted.node_color, computed.node_color);
+
+        // Starting from the degrees does not change the equitable partition
+        let (mut hinted, mut uniform) = (hinted, Colouring::new(&g));
+        hinted.refine(&g);
+        uniform.refine(&g);
+        assert_eq!(partition(&hinted), partition(&uniform));
+    }
+
+    #[test]
+    fn degree_start_refines_to_the_uniform_refinement() {
+        use crate::common::{gen_test_graph, generate_random_graph};
+
+        let mut path_and_triangle = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (3, 4), (4, 5), (5, 3), (5, 6)]);
+        path_and_triangle.add_node(7);
+        for g in [gen_test_graph(), generate_random_graph(30, 0.1), path_and_triangle, UnGraph::default()] {
+            let mut uniform = Colouring::new(&g);
+            uniform.refine(&g);
+            assert_eq!(Colouring::new_refined_by_degree(&g).node_color, uniform.node_color);
+        }
+    }
 
+    #[test]
+    #[should_panic]
+    fn degree_hint_checks_length() {
+        Colouring::new_with_degrees(4, &[1, 1, 2]);
+    }
+
+    #[test]
+    fn intersect_degree_and_distance() {
+
+        // Path 0 - 1 - 2 - 3 - 4 with a pendant node 5 on 2
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (2, 5)]);
+        let by_degree = Colouring::new_by_degree(&g);
+
+        // Distances from the node 2
+        let by_distance = Colouring::from_labels(&[2, 1, 0, 1, 2, 1]);
+
+        // The leaf 5 is separated from the leaves 0 and 4
+        let gc = by_degree.intersect(&by_distance);
+        let expected : HashSet<Vec<usize>> = HashSet::from([vec![5], vec![0, 4], vec![1, 3], vec![2]]);
+        assert_eq!(partition(&gc), expected);
+        assert_eq!(gc.node_color, vec![1, 3, 5, 3, 1, 0]);
+
+        // Intersecting with a coarser colouring changes nothing
+        assert_eq!(gc.intersect(&by_degree).node_color, gc.node_color);
+    }
+
+    #[test]
+    fn individualize_and_refine_in_one_call() {
+
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0), (1, 4)]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        let mut split = gc.clone();
+        let cell_idx = split.cell_of(0);
+        let mut trace = vec![split.individualize(cell_idx, 0).unwrap()];
+        trace.extend(split.refine(&g));
+
+        let mut combined = gc.clone();
+        assert_eq!(combined.individualize_and_refine(cell_idx, 0, &g), trace);
+        assert_eq!(combined.node_color, split.node_color);
+        assert_eq!(partition(&combined), partition(&split));
+    }
+
+    #[test]
+    fn cell_size_histogram() {
+        use crate::common::{gen_test_graph, generate_permutated_graph};
+
+        let gc = Colouring::from_labels(&[0, 1, 1, 2, 2, 3, 3, 3, 4]);
+        assert_eq!(gc.cell_size_counts(), BTreeMap::from([(1, 2), (2, 2), (3, 1)]));
+        assert!(Colouring::from_labels::<usize>(&[]).cell_size_counts().is_empty());
+
+        // Refined colourings of relabelled graphs have the same histogram
+        let g = gen_test_graph();
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+        for _ in 0..5 {
+            let h = generate_permutated_graph(&g);
+            let mut hc = Colouring::new(&h);
+            hc.refine(&h);
+            assert_eq!(hc.cell_size_counts(), gc.cell_size_counts());
+        }
+    }
+
+    #[test]
+    fn quantized_weights() {
+        use crate::common::gen_test_graph;
+
+        let g = gen_test_graph();
+        let weights = [0.1, 0.52, 0.1, 0.52, 1.3, 0.1, 0.52, 0.1, 0.52, 1.3];
+        let gc = Colouring::new_quantized(&g, |u| weights[u], 0.1);
+        assert_eq!(gc.get_cell_sizes(), vec![4, 4, 2]);
+
+        // Noise below eps keeps the buckets, a shift of several eps does not
+        let key = GraphKey::new_quantized(&g, |u| weights[u], 0.1);
+        assert_eq!(GraphKey::new_quantized(&g, |u| weights[u] + 0.001 * (u % 3) as f64, 0.1), key);
+        assert_ne!(GraphKey::new_quantized(&g, |u| if u == 4 { 0.52 } else { weights[u] }, 0.1), key);
+
+        // Shifting every weight keeps the cells but not the bucket values
+        let uniform = Colouring::new_quantized(&g, |u| weights[u] + 1.0, 0.1);
+        assert_eq!(uniform.get_cell_sizes(), gc.get_cell_sizes());
+        assert_ne!(GraphKey::new_quantized(&g, |u| weights[u] + 1.0, 0.1), key);
+    }
+
+    #[test]
+    fn degree_profiles() {
+
+        // Star with center 0 and leaves 1, 2, 3, plus an edge 3 - 4
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (3, 4)]);
+        let gc = Colouring::from_labels(&[1, 0, 0, 0, 1]);
+        let leaves = gc.cell_of(1);
+
+        // Leaves 1, 2, 3 : no neighbor among the leaves, one each in {0, 4}
+        // except 3 which has both
+        assert_eq!(gc.degree_profile(leaves, &g), vec![(0, vec![0, 0, 0]), (3, vec![1, 1, 2])]);
+
+        // Manual counts for the other cell, members 0 and 4
+        let manual = |u : usize, color : usize| g.neighbors(NodeIndex::new(u)).filter(|v| gc.color_of(v.index()) == color).count();
+        let profile = gc.degree_profile(gc.cell_of(0), &g);
+        assert_eq!(profile.len(), 2);
+        for (color, degrees) in profile {
+            assert_eq!(degrees, vec![manual(0, color), manual(4, color)]);
+        }
+
+        // The refined colouring is equitable : every profile is constant
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+        for cell in 0..gc.get_cell_count() {
+            assert!(gc.degree_profile(cell, &g).iter().all(|(_, d)| d.iter().all(|x| *x == d[0])));
+        }
+    }
+
+    #[test]
+    fn colouring_preallocates_cells() {
+
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        for gc in [Colouring::new(&g), Colouring::from_labels(&[0, 1, 0, 1, 0])] {
+            assert!(gc.cells.capacity() >= 5);
+            assert!(gc.color_cell.capacity() >= 5);
+        }
+    }
+
+    #[test]
+    fn induced_keys_of_cells() {
+
+        // Two triangles, with one pendant node per node of the first triangle
+        // and two per node of the second one
+        let g = UnGraph::<usize, ()>::from_edges([
+            (0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3),
+            (0, 6), (1, 7), (2, 8),
+            (3, 9), (3, 10), (4, 11), (4, 12), (5, 13), (5, 14)
+        ]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        let (first, second) = (gc.cell_of(0), gc.cell_of(3));
+        assert_ne!(first, second);
+        assert_eq!(gc.induced_key(first, &g), gc.induced_key(second, &g));
+
+        let triangle = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        assert_eq!(gc.induced_key(first, &g), GraphKey::new(&triangle));
+        assert_ne!(gc.induced_key(gc.cell_of(6), &g), gc.induced_key(gc.cell_of(9), &g));
+    }
+
+    #[test]
+    fn cells_iter_rebuilds_partition() {
+
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (2, 5), (5, 6)]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        let colors : Vec<usize> = gc.cells_iter().map(|(c, _)| c).collect();
+        assert!(colors.windows(2).all(|w| w[0] < w[1]));
+
+        let mut node_color = vec![usize::MAX; 7];
+        let rebuilt : HashSet<Vec<usize>> = gc.cells_iter().map(|(c, members)| {
+            assert!(members.windows(2).all(|w| w[0] < w[1]));
+            members.iter().for_each(|u| node_color[*u] = c);
+            members
+        }).collect();
+
+        assert_eq!(rebuilt, partition(&gc));
+        assert_eq!(node_color, gc.node_color);
+    }
+
+    #[test]
+    fn buffered_refine_matches_refine() {
+
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let mut scratch = RefineScratch::default();
+
+        // The same buffers are reused across graphs, orders and refinements
+        for _ in 0..20 {
+            let n = 30;
+            let mut g = UnGraph::<usize, ()>::new_undirected();
+            (0..n).for_each(|i| { g.add_node(i); });
+            for u in 0..n {
+                for v in (u+1)..n {
+                    if rng.gen_range((0.)..1.) < 0.1 {
+                        g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+                    }
+                }
+            }
+
+            for order in [WorklistOrder::Heap, WorklistOrder::Fifo] {
+                let (mut plain, mut buffered) = (Colouring::new(&g), Colouring::new(&g));
+                assert_eq!(buffered.refine_into(Neighbors(&g), order, &mut scratch), plain.refine_with(&g, order));
+                assert_eq!(buffered.node_color, plain.node_color);
+
+                while !plain.is_discrete() {
+                    let cell_idx = plain.select_cell_smallest_first();
+                    let node = *plain.cells[cell_idx].members.iter().min().unwrap();
+                    assert_eq!(
+                        buffered.individualize_and_refine_into(cell_idx, node, Neighbors(&g), &mut scratch),
+                        plain.individualize_and_refine(cell_idx, node, &g)
+                    );
+                    assert_eq!(partition(&buffered), partition(&plain));
+                    assert_eq!(buffered.node_color, plain.node_color);
+                }
+            }
+        }
+    }
+
+    /// Allocator counting the allocations of the current thread, so that the
+    /// tests running in parallel do not add to each other's counts.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS : std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout : std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr : *mut u8, layout : std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR : CountingAllocator = CountingAllocator;
+
+    /// Number of allocations made by f on the current thread.
+    fn allocations<T>(f : impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(|count| count.get());
+        let result = f();
+        (result, ALLOCATIONS.with(|count| count.get()) - before)
+    }
+
+    #[test]
+    fn buffered_refine_allocates_less() {
+
+        // Individualize every node of a 3-regular graph in turn, as the first
+        // level of the search does, with fresh buffers and with one scratch
+        let g = crate::generators::random_regular(200, 3, 0);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+        let cell_idx = gc.select_cell_smallest_first();
+        let mut scratch = RefineScratch::default();
+
+        let (mut plain_total, mut buffered_total) = (0, 0);
+        for node in gc.get_cell_members(cell_idx) {
+            let (mut plain, mut buffered) = (gc.clone(), gc.clone());
+            let (plain_trace, plain_count) = allocations(|| plain.individualize_and_refine(cell_idx, node, &g));
+            let (buffered_trace, buffered_count) = allocations(|| buffered.individualize_and_refine_into(cell_idx, node, Neighbors(&g), &mut scratch));
+            assert_eq!(buffered_trace, plain_trace);
+            plain_total += plain_count;
+            buffered_total += buffered_count;
+        }
+
+        println!("{} allocations with fresh buffers, {} with a scratch", plain_total, buffered_total);
+        assert!(buffered_total < plain_total, "{} allocations with a scratch, against {}", buffered_total, plain_total);
+    }
+
+    #[test]
+    fn refine_separates_isolated_nodes() {
+
+        // K2 (0, 1), path 2 - 3 - 4, isolated nodes 5 and 6
+        let mut g = UnGraph::<usize, ()>::from_edges([(0, 1), (2, 3), (3, 4)]);
+        g.add_node(5);
+        g.add_node(6);
+
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        assert!(is_equitable(&gc, &g));
+        assert_eq!(gc.get_cell_count(), 4);
+        let mut isolated = gc.get_cell_members(gc.cell_of(5));
+        isolated.sort();
+        assert_eq!(isolated, vec![5, 6]);
+
+        // Isolated nodes sharing their initial cells with connected nodes
+        for labels in [[0, 1, 0, 1, 0, 1, 0], [1, 1, 1, 0, 1, 0, 1], [0, 0, 0, 0, 0, 1, 0]] {
+            let mut gc = Colouring::from_labels(&labels);
+            gc.refine(&g);
+
+            assert!(is_equitable(&gc, &g));
+            for u in 0..5 {
+                assert_ne!(gc.color_of(u), gc.color_of(5));
+                assert_ne!(gc.color_of(u), gc.color_of(6));
+            }
+        }
+    }
+
+    #[test]
+    fn stacked_individualizations_keep_color_cell_consistent() {
+
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        while !gc.is_discrete() {
+            let target = gc.select_cell_v1();
+            let node = *gc.cells[target].members.iter().max().unwrap();
+            gc.individualize(target, node);
+            assert!(gc.color_cell_is_consistent());
+
+            gc.refine(&g);
+            assert!(gc.color_cell_is_consistent());
+        }
+
+        // Individualize within a cell which is not the first one
+        let mut gc = Colouring::from_labels(&[0, 0, 1, 1, 1, 2]);
+        for node in [2, 3] {
+            gc.individualize(gc.cell_of(node), node);
+            assert!(gc.color_cell_is_consistent());
+        }
+        assert_eq!(gc.get_cell_sizes(), vec![2, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn colors_stay_below_size_in_deep_searches() {
+
+        // Hypercube of dimension 6 : 6 individualizations are needed
+        let g = UnGraph::<usize, ()>::from_edges((0..64u32).flat_map(|u| (0..6).map(move |b| (u, u ^ (1 << b)))).filter(|(u, v)| u < v));
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        let mut depth = 0;
+        while !gc.is_discrete() {
+            let cell = gc.select_cell_smallest_first();
+            let node = (0..64).filter(|u| gc.cell_of(*u) == cell).max().unwrap();
+            let new_colors = gc.individualize_and_refine(cell, node, &g);
+            depth += 1;
+
+            assert!(new_colors.iter().all(|c| *c < 64));
+            assert!((0..64).all(|u| gc.color_of(u) < 64));
+            assert!(gc.colors_are_contiguous());
+        }
+        assert_eq!(depth, 6);
+    }
+
+    #[test]
+    fn refine_with_triangle_counts() {
+
+        // 6-cycle next to two triangles : every node has degree 2, so the
+        // refinement keeps a single cell
+        let g = UnGraph::<usize, ()>::from_edges([
+            (0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0),
+            (6, 7), (7, 8), (8, 6), (9, 10), (10, 11), (11, 9)
+        ]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+        assert_eq!(gc.get_cell_count(), 1);
+
+        let triangles = |u : usize| {
+            let neigh : Vec<NodeIndex> = g.neighbors(NodeIndex::new(u)).collect();
+            neigh.iter().enumerate().map(|(i, a)| neigh[i + 1..].iter().filter(|b| g.contains_edge(*a, **b)).count()).sum::<usize>() as u64
+        };
+        let mut gc = Colouring::new(&g);
+        let trace = gc.refine_with_signature(&g, triangles);
+        assert_eq!(gc.get_cell_sizes(), vec![6, 6]);
+        assert!((0..6).all(|u| gc.color_of(u) == 0) && (6..12).all(|u| gc.color_of(u) == 6));
+        assert!(is_equitable(&gc, &g));
+
+        // The signature values are in the trace : 0 triangles, then 1
+        assert_eq!(trace[..4], [0, 0, 0, 1]);
+        let mut shifted = Colouring::new(&g);
+        let shifted_trace = shifted.refine_with_signature(&g, |u| triangles(u) + (1 << 40));
+        assert_eq!(shifted.get_cell_sizes(), gc.get_cell_sizes());
+        assert_ne!(shifted_trace, trace);
+    }
+
+    #[test]
+    fn individualize_singleton_is_a_no_op() {
+
+        let mut gc = Colouring::from_labels(&[0, 0, 1]);
+        let colors = gc.node_color.clone();
+
+        assert_eq!(gc.individualize(gc.cell_of(2), 2), None);
+        assert_eq!(gc.node_color, colors);
+        assert_eq!(gc.get_cell_count(), 2);
+
+        assert_eq!(gc.individualize(gc.cell_of(0), 0), Some(1));
+        assert_eq!(gc.individualize(gc.cell_of(1), 1), None);
+        assert!(gc.is_discrete());
+    }
+
+    #[test]
+    #[should_panic(expected = "non-discrete colouring")]
+    fn graph_from_non_discrete_colouring() {
+
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        gc.compute_graph_from_discrete(&g);
+    }
+
+    #[test]
+    fn color_of_after_refine() {
+
+        // Path 0 - 1 - 2 - 3 - 4 : the orbits are {0, 4}, {1, 3} and {2}
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+
+        assert_eq!(gc.color_of(0), gc.color_of(4));
+        assert_eq!(gc.color_of(1), gc.color_of(3));
+        assert_ne!(gc.color_of(0), gc.color_of(1));
+        assert_ne!(gc.color_of(1), gc.color_of(2));
+
+        assert_eq!(gc.cell_of(0), gc.cell_of(4));
+        assert_ne!(gc.cell_of(2), gc.cell_of(3));
+    }
+
+    #[test]
+    fn colors_stay_contiguous() {
+        use crate::common::{gen_test_graph, generate_random_graph};
+
+        for g in [gen_test_graph(), generate_random_graph(30, 0.2)] {
+            let mut gc = Colouring::from_labels(&[0, 1, 0, 1, 2, 0, 1, 0, 1, 2].repeat(3)[..g.node_count()]);
+            assert!(gc.colors_are_contiguous());
+            gc.refine(&g);
+            assert!(gc.colors_are_contiguous());
+
+            while !gc.is_discrete() {
+                let target = gc.select_cell_smallest_first();
+                let node = *gc.cells[target].members.iter().min().unwrap();
+                gc.individualize_and_refine(target, node, &g);
+                assert!(gc.colors_are_contiguous());
+            }
+
+            let mut colors = gc.node_color.clone();
+            colors.sort();
+            assert_eq!(colors, (0..gc.get_cell_count()).collect::<Vec<usize>>());
+        }
+
+        // A colour skipping a position
+        let mut gc = Colouring::from_labels(&[0, 0, 1]);
+        gc.cells[1].color = 3;
+        gc.node_color[2] = 3;
+        assert!(!gc.colors_are_contiguous());
+    }
+
+    #[test]
+    fn stepper_reaches_the_refined_colouring() {
+        use crate::common::{gen_test_graph, generate_random_graph};
+
+        for g in [gen_test_graph(), generate_random_graph(30, 0.2)] {
+            let start = Colouring::from_labels(&[0, 1, 0, 1, 2, 0, 1, 0, 1, 2].repeat(3)[..g.node_count()]);
+            let mut batch = start.clone();
+            let trace = batch.refine(&g);
+
+            let mut stepper = RefinementStepper::new(&g, start);
+            let mut steps_trace = Vec::new();
+            while let Some(step) = stepper.next_step() {
+                assert_eq!(step.split_cells.is_empty(), step.new_colors.is_empty());
+                assert!(step.split_cells.windows(2).all(|w| w[0] < w[1]));
+                steps_trace.extend(step.new_colors);
+            }
+
+            assert_eq!(steps_trace, trace);
+            assert_eq!(stepper.colouring().node_color, batch.node_color);
+            assert_eq!(stepper.next(), None);
+        }
+    }
+}
@@ -0,0 +1,1811 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::Undirected;
+use petgraph::graph::{NodeIndex, UnGraph, Graph};
+use petgraph::visit::EdgeRef;
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges, GraphProp};
+
+use crate::{GraphKey, Label, MatrixError};
+
+//
+// Keys of graphs derived from the input graph
+//
+
+impl GraphKey {
+
+    /// Compute the key of the k-core of `g`, i.e. the subgraph left after
+    /// iteratively removing the nodes of degree lower than k.
+    ///
+    /// With k = 2, pendant trees are stripped: graphs differing only by their
+    /// pendant trees share the same core key.
+    pub fn of_core<G>(g : G, k : usize) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = g.node_count();
+        let mut degrees : Vec<usize> = (0..n).map(|u| g.neighbors(g.from_index(u)).count()).collect();
+        let mut removed = vec![false; n];
+        let mut to_remove : Vec<usize> = (0..n).filter(|u| degrees[*u] < k).collect();
+
+        while let Some(u) = to_remove.pop() {
+            if removed[u] { continue; }
+            removed[u] = true;
+
+            for v in g.neighbors(g.from_index(u)) {
+                let v = g.to_index(v);
+                degrees[v] = degrees[v].saturating_sub(1);
+                if !removed[v] && degrees[v] < k {
+                    to_remove.push(v);
+                }
+            }
+        }
+
+        let core : Vec<usize> = (0..n).filter(|u| !removed[*u]).collect();
+        GraphKey::new(&induced_subgraph(g, &core))
+    }
+
+    /// Compute the key of `g` with its nodes of degree 2 smoothed: the two edges
+    /// u - a and u - b of such a node u are replaced by a single edge a - b.
+    ///
+    /// Subdividing edges, uniformly or not, does not change the key: two graphs
+    /// get the same key iff they are homeomorphic. Smoothing can create
+    /// parallel edges and self-loops, which are kept, and every cycle
+    /// component ends as a single node with a self-loop.
+    pub fn of_topological_minor_core<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let n = g.node_count();
+        let mut edges : Vec<Option<(usize, usize)>> = Vec::new();
+        let mut incident = vec![Vec::new(); n];
+        for e in g.edge_references() {
+            let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+            incident[u].push(edges.len());
+            if u != v {
+                incident[v].push(edges.len());
+            }
+            edges.push(Some((u, v)));
+        }
+
+        // Smoothing keeps the degrees of the other nodes, so the candidates are
+        // known from the start; a self-loop makes a node of degree 2 permanent
+        let mut removed = vec![false; n];
+        for u in 0..n {
+            if incident[u].len() != 2 {
+                continue;
+            }
+            let ends : Vec<usize> = incident[u].iter().map(|e| {
+                let (a, b) = edges[*e].unwrap();
+                if a == u { b } else { a }
+            }).collect();
+            if ends.contains(&u) {
+                continue;
+            }
+
+            for e in std::mem::take(&mut incident[u]) {
+                edges[e] = None;
+                ends.iter().for_each(|a| incident[*a].retain(|f| *f != e));
+            }
+            incident[ends[0]].push(edges.len());
+            if ends[0] != ends[1] {
+                incident[ends[1]].push(edges.len());
+            }
+            edges.push(Some((ends[0], ends[1])));
+            removed[u] = true;
+        }
+
+        let mut position = vec![0; n];
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        for u in (0..n).filter(|u| !removed[*u]) {
+            position[u] = _g.add_node(u).index();
+        }
+        for (u, v) in edges.into_iter().flatten() {
+            _g.add_edge(NodeIndex::new(position[u]), NodeIndex::new(position[v]), ());
+        }
+
+        GraphKey::new(&_g)
+    }
+
+    /// Compute the key of `g` without its isolated nodes.
+    ///
+    /// This changes the equivalence relation: graphs that only differ by their
+    /// number of isolated nodes get the same key, e.g. a graph and its padding
+    /// to a fixed node count.
+    pub fn new_ignoring_isolated<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let connected : Vec<usize> = (0..g.node_count()).filter(|u| g.neighbors(g.from_index(*u)).next().is_some()).collect();
+        GraphKey::new(&induced_subgraph(g, &connected))
+    }
+
+    /// Compute the key of the complement of `g`, whose edges are the pairs of
+    /// distinct nodes that are not adjacent in g.
+    ///
+    /// Two graphs are isomorphic iff their complements are, so complement keys
+    /// classify graphs as well as their direct keys, but the two kinds of keys
+    /// must not be compared with each other.
+    pub fn of_complement<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let n = g.node_count();
+        let edges : HashSet<(usize, usize)> = g.edge_references().map(|e| {
+            let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+            (u.min(v), u.max(v))
+        }).collect();
+
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|i| { _g.add_node(i); });
+        for u in 0..n {
+            for v in (u + 1)..n {
+                if !edges.contains(&(u, v)) {
+                    _g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+                }
+            }
+        }
+
+        GraphKey::new(&_g)
+    }
+
+    /// Compute the key of the underlying undirected graph of `g`: u and v are
+    /// adjacent iff g has an edge between them, in either direction.
+    ///
+    /// Antiparallel and parallel edges are merged into a single undirected
+    /// edge, self-loops are kept once. Unlike `GraphKey::new` on a directed
+    /// graph, which only follows outgoing edges, the result does not depend on
+    /// the edge directions.
+    pub fn underlying_undirected<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        GraphKey::new(&simple_projection(g))
+    }
+
+    /// Compute the key of `g` with each class of twins collapsed to a single
+    /// node.
+    ///
+    /// True twins share their closed neighborhood (they are adjacent), false
+    /// twins their open neighborhood (they are not). Each class of at least two
+    /// twins is replaced by one of its members, labelled by the kind of twins
+    /// it stands for, and the graph induced by the remaining nodes is
+    /// canonicalized with the kinds as node labels, see `Label`. The size of
+    /// the classes is not kept: graphs that only differ by how many twins each
+    /// class holds get the same key.
+    pub fn modulo_twins<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = g.node_count();
+        let open : Vec<Vec<usize>> = (0..n).map(|u| {
+            let mut neigh : Vec<usize> = g.neighbors(g.from_index(u)).map(|v| g.to_index(v)).filter(|v| *v != u).collect();
+            neigh.sort();
+            neigh.dedup();
+            neigh
+        }).collect();
+
+        // 0 : no twin, 1 : true twins, 2 : false twins
+        let mut kind = vec![0u8; n];
+        let mut representatives = Vec::new();
+
+        let mut true_twins : HashMap<Vec<usize>, Vec<usize>> = HashMap::new();
+        for (u, neigh) in open.iter().enumerate() {
+            let mut closed = neigh.clone();
+            closed.insert(closed.partition_point(|v| *v < u), u);
+            true_twins.entry(closed).or_default().push(u);
+        }
+
+        let mut false_twins : HashMap<&Vec<usize>, Vec<usize>> = HashMap::new();
+        for class in true_twins.into_values() {
+            if class.len() > 1 {
+                class.iter().for_each(|u| kind[*u] = 1);
+                representatives.push(class[0]);
+            } else {
+                false_twins.entry(&open[class[0]]).or_default().push(class[0]);
+            }
+        }
+        for class in false_twins.into_values() {
+            if class.len() > 1 {
+                class.iter().for_each(|u| kind[*u] = 2);
+            }
+            representatives.push(class[0]);
+        }
+
+        representatives.sort();
+        let labels : Vec<u8> = representatives.iter().map(|u| kind[*u]).collect();
+        GraphKey::new_with_labels(&induced_subgraph(g, &representatives), &labels)
+    }
+
+    /// Compute the key of `g` with the edges of `excluded` removed, all the
+    /// edges between u and v being removed if it holds (u, v) or (v, u).
+    ///
+    /// Graphs that only differ by excluded edges get the same key.
+    pub fn new_excluding_edges<G>(g : G, excluded : &HashSet<(usize, usize)>) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let excluded : HashSet<(usize, usize)> = excluded.iter().map(|(u, v)| (*u.min(v), *u.max(v))).collect();
+
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..g.node_count()).for_each(|u| { _g.add_node(u); });
+        for e in g.edge_references() {
+            let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+            if !excluded.contains(&(u.min(v), u.max(v))) {
+                _g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+            }
+        }
+
+        GraphKey::new(&_g)
+    }
+
+    /// Compute the key of `g` with its parallel edges merged.
+    ///
+    /// Graphs that only differ by the multiplicity of their edges get the same
+    /// key. Self-loops are kept once. This is `GraphKey::underlying_undirected`,
+    /// which also merges the antiparallel edges of a directed graph.
+    pub fn simple<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        GraphKey::underlying_undirected(g)
+    }
+
+    /// Compute the key of the line graph of `g`, whose nodes are the edges of g,
+    /// two of them being adjacent when they share an endpoint.
+    ///
+    /// Non-isomorphic graphs may share the same line graph: by Whitney's theorem
+    /// this only happens for connected graphs with the triangle K3 and the star
+    /// K1,3, which both have K3 as line graph.
+    pub fn of_line_graph<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let mut incident_edges = vec![Vec::new(); g.node_count()];
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+
+        for e in g.edge_references() {
+            let edge = _g.add_node(_g.node_count()).index();
+            incident_edges[g.to_index(e.source())].push(edge);
+            if e.source() != e.target() {
+                incident_edges[g.to_index(e.target())].push(edge);
+            }
+        }
+
+        // Parallel edges share both endpoints but are only linked once
+        let mut linked = HashSet::new();
+        for edges in incident_edges.iter() {
+            for (i, e) in edges.iter().enumerate() {
+                for f in edges[(i + 1)..].iter() {
+                    if linked.insert((*e, *f)) {
+                        _g.add_edge(NodeIndex::new(*e), NodeIndex::new(*f), ());
+                    }
+                }
+            }
+        }
+
+        GraphKey::new(&_g)
+    }
+
+    /// Compute the key of the square of `g`, where u and v are adjacent iff
+    /// they are distinct and at distance 1 or 2 in g.
+    ///
+    /// The square is simple: self-loops and parallel edges of g are ignored.
+    pub fn of_square<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let n = g.node_count();
+        let neighbors : Vec<HashSet<usize>> = (0..n)
+            .map(|u| g.neighbors(g.from_index(u)).map(|v| g.to_index(v)).filter(|v| *v != u).collect())
+            .collect();
+
+        GraphKey::from_fn(n, |u, v| u != v && (neighbors[u].contains(&v) || !neighbors[u].is_disjoint(&neighbors[v])))
+    }
+
+    /// Compute the key of the bipartite double cover of `g`, its tensor product
+    /// with K2.
+    ///
+    /// Each node u is split into u and u', and each edge u - v into u - v' and
+    /// v - u'. The double cover of a bipartite graph is two copies of it.
+    pub fn of_double_cover<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let n = g.node_count();
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..(2 * n)).for_each(|u| { _g.add_node(u); });
+
+        for e in g.edge_references() {
+            let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+            _g.add_edge(NodeIndex::new(u), NodeIndex::new(n + v), ());
+            if u != v {
+                _g.add_edge(NodeIndex::new(v), NodeIndex::new(n + u), ());
+            }
+        }
+
+        GraphKey::new(&_g)
+    }
+
+    /// Compute the key of the block-cut tree of `g`, with a node per block, i.e.
+    /// biconnected component, and per cut vertex, linking each cut vertex to
+    /// the blocks containing it. Blocks and cut vertices are in distinct cells.
+    ///
+    /// Only the tree is described: graphs whose blocks are arranged the same way
+    /// get the same key, whatever the blocks are. An isolated node is a block,
+    /// self-loops and parallel edges are ignored. A disconnected graph gives
+    /// its block-cut forest.
+    pub fn of_block_cut_tree<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let blocks = biconnected_components(g);
+
+        let mut block_count = vec![0; g.node_count()];
+        blocks.iter().flatten().for_each(|u| block_count[*u] += 1);
+
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        let mut is_cut = vec![false; blocks.len()];
+        (0..blocks.len()).for_each(|b| { _g.add_node(b); });
+
+        let mut cut_node = HashMap::new();
+        for (b, block) in blocks.iter().enumerate() {
+            for u in block.iter().filter(|u| block_count[**u] > 1) {
+                let c = *cut_node.entry(*u).or_insert_with(|| {
+                    is_cut.push(true);
+                    _g.add_node(*u)
+                });
+                _g.add_edge(NodeIndex::new(b), c, ());
+            }
+        }
+
+        GraphKey::new_with_labels(&_g, &is_cut)
+    }
+
+    /// Compute the key of the modular decomposition tree of `g`: the root is
+    /// the node set of g, the children of a module are its maximal strong
+    /// submodules, and the leaves are the nodes of g.
+    ///
+    /// A module is parallel if g restricted to it is disconnected, series if its
+    /// complement is, and prime otherwise. Internal nodes are labelled by their
+    /// kind, written into the key, and the root is marked in its label, so
+    /// graphs whose decomposition trees are isomorphic get the same key; the
+    /// quotient graphs of the prime modules are not described. Self-loops and
+    /// parallel edges are ignored.
+    ///
+    /// The leaves of a module are chained below it rather than attached to it,
+    /// so that the search does not go through all their permutations.
+    /// The modules are found by closing pairs of nodes under splitters, which
+    /// suits graphs of moderate size.
+    pub fn of_modular_decomposition<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let n = g.node_count();
+        let mut adjacent = vec![vec![false; n]; n];
+        for (u, v) in (0..n).flat_map(|u| g.neighbors(g.from_index(u)).map(move |v| (u, g.to_index(v)))) {
+            if u != v {
+                adjacent[u][v] = true;
+                adjacent[v][u] = true;
+            }
+        }
+
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        let mut kinds = Vec::new();
+        if n > 0 {
+            decompose(&adjacent, (0..n).collect(), &mut _g, &mut kinds);
+        }
+
+        // The root is the first node of the tree
+        let labels : Vec<(bool, ModuleKind)> = kinds.into_iter().enumerate().map(|(i, kind)| (i == 0, kind)).collect();
+        GraphKey::new_with_labels(&_g, &labels)
+    }
+
+    /// Compute, for every node u of `g`, the key of the subgraph induced by the
+    /// nodes at distance at most k from u.
+    ///
+    /// The keys of isomorphic graphs are the same multiset, so they can serve
+    /// as local features. The center of a ball is not distinguished.
+    pub fn neighborhood_keys<G>(g : G, k : usize) -> Vec<GraphKey>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = g.node_count();
+        let mut distance = vec![usize::MAX; n];
+
+        (0..n).map(|u| {
+            // Breadth-first search stopped at depth k
+            let mut ball = vec![u];
+            distance[u] = 0;
+            let mut i = 0;
+            while i < ball.len() {
+                let v = ball[i];
+                i += 1;
+                if distance[v] == k { continue; }
+                for w in g.neighbors(g.from_index(v)) {
+                    let w = g.to_index(w);
+                    if distance[w] == usize::MAX {
+                        distance[w] = distance[v] + 1;
+                        ball.push(w);
+                    }
+                }
+            }
+
+            ball.iter().for_each(|v| distance[*v] = usize::MAX);
+            ball.sort();
+            GraphKey::new(&induced_subgraph(g, &ball))
+        }).collect()
+    }
+
+    /// Compute the key of the distance matrix of `g`, as given to
+    /// `from_weighted_matrix`: the entry u, v is the length of a shortest path
+    /// between u and v, or -1 if there is none, and the diagonal is 0.
+    ///
+    /// Two graphs get the same key iff their shortest-path metrics are
+    /// isometric, the distances and the unreachable -1 being written into the
+    /// key. Distances are computed by a breadth-first search per node.
+    pub fn of_distance_matrix<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let n = g.node_count();
+        let m : Vec<Vec<i64>> = (0..n).map(|u| {
+            let mut distance = vec![-1; n];
+            let mut queue = vec![u];
+            distance[u] = 0;
+            let mut i = 0;
+            while i < queue.len() {
+                let v = queue[i];
+                i += 1;
+                for w in g.neighbors(g.from_index(v)) {
+                    let w = g.to_index(w);
+                    if distance[w] == -1 {
+                        distance[w] = distance[v] + 1;
+                        queue.push(w);
+                    }
+                }
+            }
+            distance
+        }).collect();
+
+        GraphKey::from_weighted_matrix(&m)
+    }
+
+    /// Compute the key of `g` clustered by `cluster`, the node n belonging to
+    /// the cluster cluster[n].
+    ///
+    /// Unlike `GraphKey::new_with_classes`, cluster ids are arbitrary: two
+    /// clustered graphs get the same key iff some isomorphism maps the clusters
+    /// of one onto the clusters of the other, whatever their ids. Each cluster
+    /// becomes a node linked to its members, kept in its own initial cell.
+    pub fn new_with_clusters<G>(g : G, cluster : &[usize]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let n = g.node_count();
+        assert_eq!(cluster.len(), n, "cluster must have one entry per node");
+
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|u| { _g.add_node(u); });
+        for e in g.edge_references() {
+            _g.add_edge(NodeIndex::new(g.to_index(e.source())), NodeIndex::new(g.to_index(e.target())), ());
+        }
+
+        let mut cluster_nodes = HashMap::new();
+        for (u, c) in cluster.iter().enumerate() {
+            let node = *cluster_nodes.entry(*c).or_insert_with(|| _g.add_node(n + u));
+            _g.add_edge(NodeIndex::new(u), node, ());
+        }
+
+        let is_cluster : Vec<bool> = (0.._g.node_count()).map(|u| u >= n).collect();
+        GraphKey::new_with_labels(&_g, &is_cluster)
+    }
+}
+
+impl GraphKey {
+
+    /// Compute the key of the hypergraph on the vertices 0..n_vertices with the
+    /// given hyperedges, through its incidence graph.
+    ///
+    /// The incidence graph links every vertex to the hyperedges containing it,
+    /// vertices and hyperedges being kept in distinct initial cells, whose
+    /// sides are written into the key even when one of them is empty. Two
+    /// hypergraphs get the same key iff they are equal up to a relabelling of
+    /// the vertices and a reordering of the hyperedges.
+    pub fn from_hypergraph(n_vertices : usize, hyperedges : &[Vec<usize>]) -> GraphKey {
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..(n_vertices + hyperedges.len())).for_each(|i| { _g.add_node(i); });
+
+        for (i, members) in hyperedges.iter().enumerate() {
+            let members : HashSet<usize> = members.iter().copied().collect();
+            for u in members {
+                assert!(u < n_vertices, "hyperedge member {u} is not a vertex");
+                _g.add_edge(NodeIndex::new(u), NodeIndex::new(n_vertices + i), ());
+            }
+        }
+
+        let is_hyperedge : Vec<bool> = (0.._g.node_count()).map(|u| u >= n_vertices).collect();
+        GraphKey::new_with_labels(&_g, &is_hyperedge)
+    }
+
+    /// Compute the key of the graph on the nodes 0..n where u and v are adjacent
+    /// iff adjacent(u, v), a self-loop on u iff adjacent(u, u).
+    ///
+    /// The predicate must be symmetric and is only called for u <= v. Every
+    /// edge ends in the descriptor, so all the pairs are tested once, and the
+    /// graph is stored with its edges only.
+    pub fn from_fn<F>(n : usize, adjacent : F) -> GraphKey
+    where
+        F : Fn(usize, usize) -> bool
+    {
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|u| { _g.add_node(u); });
+
+        for u in 0..n {
+            for v in (u..n).filter(|v| adjacent(u, *v)) {
+                _g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+            }
+        }
+
+        GraphKey::new(&_g)
+    }
+
+    /// Compute the key of the weighted graph given by the symmetric matrix m,
+    /// where m[u][v] is the weight of the edge between u and v, 0 meaning no
+    /// edge, and m[u][u] is the weight of the node u.
+    ///
+    /// Each weight acts as a color, whose value is written into the key: two
+    /// matrices get the same key iff one is obtained from the other by
+    /// permuting rows and columns simultaneously. Panics if m is not square and
+    /// symmetric.
+    pub fn from_weighted_matrix(m : &[Vec<i64>]) -> GraphKey {
+        let n = m.len();
+        assert!(m.iter().all(|row| row.len() == n), "weighted matrix must be square");
+        assert!((0..n).all(|u| (0..u).all(|v| m[u][v] == m[v][u])), "weighted matrix must be symmetric");
+
+        // Each edge becomes a node carrying its weight, linked to both endpoints
+        let mut labels : Vec<(bool, i64)> = (0..n).map(|u| (false, m[u][u])).collect();
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|u| { _g.add_node(u); });
+
+        for (u, row) in m.iter().enumerate() {
+            for (v, w) in row.iter().enumerate().skip(u + 1) {
+                if *w != 0 {
+                    let e = _g.add_node(labels.len());
+                    labels.push((true, *w));
+                    _g.add_edge(NodeIndex::new(u), e, ());
+                    _g.add_edge(NodeIndex::new(v), e, ());
+                }
+            }
+        }
+
+        GraphKey::new_with_labels(&_g, &labels)
+    }
+
+    /// Compute the key of the graph whose node u has the color vertex_colors[u],
+    /// and whose edge u - v has the color edge_colors[u][v], 0 meaning no edge.
+    /// A non-zero edge_colors[u][u] is a coloured self-loop on u.
+    ///
+    /// Two inputs get the same key iff one is obtained from the other by
+    /// permuting the vertex colors, and the rows and columns of the edge
+    /// matrix, simultaneously. The color values are written into the key, so
+    /// that recolouring every vertex or every edge the same way gives another
+    /// key. Fails if the edge matrix is not square and
+    /// symmetric, or does not have one row per vertex.
+    pub fn from_colored_matrix(vertex_colors : &[usize], edge_colors : &[Vec<usize>]) -> Result<GraphKey, MatrixError> {
+        let n = edge_colors.len();
+        if vertex_colors.len() != n {
+            return Err(MatrixError::LengthMismatch { vertices : vertex_colors.len(), rows : n });
+        }
+        if let Some(row) = edge_colors.iter().position(|row| row.len() != n) {
+            return Err(MatrixError::NotSquare { row });
+        }
+        if let Some((u, v)) = (0..n).flat_map(|u| (0..u).map(move |v| (u, v))).find(|(u, v)| edge_colors[*u][*v] != edge_colors[*v][*u]) {
+            return Err(MatrixError::NotSymmetric { u, v });
+        }
+
+        // Each edge becomes a node carrying its color, linked to both endpoints
+        let mut labels : Vec<(bool, usize, usize)> = (0..n).map(|u| (false, vertex_colors[u], edge_colors[u][u])).collect();
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|u| { _g.add_node(u); });
+
+        for (u, row) in edge_colors.iter().enumerate() {
+            for (v, c) in row.iter().enumerate().skip(u + 1) {
+                if *c != 0 {
+                    let e = _g.add_node(labels.len());
+                    labels.push((true, *c, 0));
+                    _g.add_edge(NodeIndex::new(u), e, ());
+                    _g.add_edge(NodeIndex::new(v), e, ());
+                }
+            }
+        }
+
+        Ok(GraphKey::new_with_labels(&_g, &labels))
+    }
+
+    /// Compute the key of `g` where the node n carries the label node_label(n)
+    /// and each edge e the label edge_label(e). `g` may be directed or not.
+    ///
+    /// Each undirected edge becomes a node carrying its label, linked to both
+    /// endpoints. A directed edge u -> v becomes a path u - t - h - v, where t
+    /// carries the label and h marks the head. Two graphs get the same key iff
+    /// they are isomorphic through a map preserving the labels and directions;
+    /// a directed graph never shares its key with an undirected one. The label
+    /// values are written into the key, so relabelling every atom of a molecule
+    /// the same way gives another key.
+    pub fn attributed<G, NF, EF, NL, EL>(g : G, node_label : NF, edge_label : EF) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges + GraphProp,
+        NF : Fn(usize) -> NL,
+        EF : Fn(G::EdgeRef) -> EL,
+        NL : Label,
+        EL : Label
+    {
+        let (_g, labels) = attributed_graph(g, node_label, edge_label);
+        GraphKey::new_with_labels(&_g, &labels)
+    }
+
+    /// Compute the key of `g` where each edge e has the color edge_color(e),
+    /// the same as `GraphKey::attributed` with unlabelled nodes.
+    ///
+    /// Each edge is subdivided by a node carrying its color, kept apart from
+    /// the nodes of g, so that the refinement of the subdivided graph tells the
+    /// edge colors apart however many there are. Two graphs get the same key
+    /// iff they are isomorphic through a map preserving the edge colors, whose
+    /// values are written into the key.
+    pub fn edge_colored<G, F, C>(g : G, edge_color : F) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges + GraphProp,
+        F : Fn(G::EdgeRef) -> C,
+        C : Label
+    {
+        GraphKey::attributed(g, |_| (), edge_color)
+    }
+
+    /// Compute the key of the signed graph `g`, where sign(e) tells whether the
+    /// edge e is positive, the same as `GraphKey::edge_colored` with two colors.
+    ///
+    /// Two signed graphs get the same key iff they are isomorphic through a map
+    /// preserving the signs, which are written into the key: a graph whose
+    /// edges are all positive differs from the same graph with negative edges.
+    pub fn signed<G, F>(g : G, sign : F) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges + GraphProp,
+        F : Fn(G::EdgeRef) -> bool
+    {
+        GraphKey::edge_colored(g, sign)
+    }
+
+    /// Compute the key of the multiplex network whose layer i is the graph
+    /// `layers[i]`, all the layers sharing the same nodes.
+    ///
+    /// Each edge of the layer i becomes a node labelled i, linked to both
+    /// endpoints, and each layer has an isolated node labelled i so that empty
+    /// layers still count. Two networks get the same key iff some permutation
+    /// of the nodes maps each layer onto the layer of the same index. Panics
+    /// if the layers do not have the same node count.
+    pub fn multiplex<G>(layers : &[G]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let n = layers.first().map_or(0, |g| g.node_count());
+        assert!(layers.iter().all(|g| g.node_count() == n), "layers must share the same nodes");
+
+        let mut labels : Vec<Option<usize>> = vec![None; n];
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|u| { _g.add_node(u); });
+
+        for (i, g) in layers.iter().enumerate() {
+            _g.add_node(labels.len());
+            labels.push(Some(i));
+            for e in g.edge_references() {
+                let s = _g.add_node(labels.len());
+                labels.push(Some(i));
+                _g.add_edge(NodeIndex::new(g.to_index(e.source())), s, ());
+                _g.add_edge(s, NodeIndex::new(g.to_index(e.target())), ());
+            }
+        }
+
+        GraphKey::new_with_labels(&_g, &labels)
+    }
+
+    /// Compute the key of the netlist `g`, where port_of(e) gives the ports of
+    /// the edge e at its source and at its target, in that order.
+    ///
+    /// Each edge u - v becomes a path u - s - t - v, where s carries the port
+    /// of e at u and t its port at v. Two netlists get the same key iff they
+    /// are isomorphic through a map preserving the ports at both ends of every
+    /// edge. Edges are taken as undirected. The port numbers are written into
+    /// the key, as node labels are, so renumbering every port the same way
+    /// gives another key.
+    pub fn with_ports<G, F, P>(g : G, port_of : F) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges,
+        F : Fn(G::EdgeRef) -> (P, P),
+        P : Label
+    {
+        let n = g.node_count();
+        let mut labels : Vec<Option<P>> = (0..n).map(|_| None).collect();
+        let mut _g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|u| { _g.add_node(u); });
+
+        for e in g.edge_references() {
+            let (u, v) = (NodeIndex::new(g.to_index(e.source())), NodeIndex::new(g.to_index(e.target())));
+            let (port_u, port_v) = port_of(e);
+            let s = _g.add_node(labels.len());
+            labels.push(Some(port_u));
+            let t = _g.add_node(labels.len());
+            labels.push(Some(port_v));
+            _g.add_edge(u, s, ());
+            _g.add_edge(s, t, ());
+            _g.add_edge(t, v, ());
+        }
+
+        GraphKey::new_with_labels(&_g, &labels)
+    }
+
+    /// Compute the key of the quotient of `g` by the partition giving the node
+    /// n the cell partition[n]: each cell becomes a node labelled by its size,
+    /// and the edges of g between two cells, or inside a cell, become a single
+    /// edge or self-loop labelled by their number.
+    ///
+    /// Only the partition matters, not the values naming its cells: two pairs
+    /// (graph, partition) related by an isomorphism mapping cells to cells get
+    /// the same key. The sizes and the numbers of edges are written into the
+    /// key, so that two quotients of the same shape with other sizes or other
+    /// numbers of edges differ. Panics if partition does not have a cell for
+    /// each node.
+    pub fn quotient<G>(g : G, partition : &[usize]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        assert_eq!(partition.len(), g.node_count(), "partition must give a cell to every node");
+
+        // Index of each cell, in order of first appearance
+        let mut cell_index = HashMap::new();
+        let mut sizes = Vec::new();
+        for c in partition.iter() {
+            let i = *cell_index.entry(*c).or_insert(sizes.len());
+            if i == sizes.len() {
+                sizes.push(0);
+            }
+            sizes[i] += 1;
+        }
+
+        let mut multiplicities = HashMap::new();
+        for e in g.edge_references() {
+            let (a, b) = (cell_index[&partition[g.to_index(e.source())]], cell_index[&partition[g.to_index(e.target())]]);
+            *multiplicities.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+
+        let mut _g = UnGraph::<usize, usize>::new_undirected();
+        (0..sizes.len()).for_each(|c| { _g.add_node(c); });
+        for ((a, b), m) in multiplicities {
+            _g.add_edge(NodeIndex::new(a), NodeIndex::new(b), m);
+        }
+
+        GraphKey::attributed(&_g, |c| sizes[c], |e| *e.weight())
+    }
+}
+
+/// Label of a node of the graph built by `attributed_graph`: (kind, node
+/// label, edge label), with kinds 0 : node, 1 : undirected edge, 2 : tail of a
+/// directed edge, 3 : head of a directed edge.
+pub(crate) type AttributedLabel<NL, EL> = (u8, Option<NL>, Option<EL>);
+
+/// Build the undirected graph of `GraphKey::attributed`, where edges become
+/// labelled nodes, together with the labels of its nodes.
+pub(crate) fn attributed_graph<G, NF, EF, NL, EL>(g : G, node_label : NF, edge_label : EF) -> (Graph<usize, (), Undirected>, Vec<AttributedLabel<NL, EL>>)
+where
+    G : NodeCompactIndexable + IntoEdges + GraphProp,
+    NF : Fn(usize) -> NL,
+    EF : Fn(G::EdgeRef) -> EL
+{
+    let n = g.node_count();
+
+    let mut labels : Vec<AttributedLabel<NL, EL>> = (0..n).map(|u| (0, Some(node_label(u)), None)).collect();
+    let mut _g = UnGraph::<usize, ()>::new_undirected();
+    (0..n).for_each(|u| { _g.add_node(u); });
+
+    for e in g.edge_references() {
+        let (u, v) = (NodeIndex::new(g.to_index(e.source())), NodeIndex::new(g.to_index(e.target())));
+
+        if g.is_directed() {
+            let t = _g.add_node(labels.len());
+            labels.push((2, None, Some(edge_label(e))));
+            let h = _g.add_node(labels.len());
+            labels.push((3, None, None));
+            _g.add_edge(u, t, ());
+            _g.add_edge(t, h, ());
+            _g.add_edge(h, v, ());
+        } else {
+            let s = _g.add_node(labels.len());
+            labels.push((1, None, Some(edge_label(e))));
+            _g.add_edge(u, s, ());
+            _g.add_edge(s, v, ());
+        }
+    }
+
+    (_g, labels)
+}
+
+/// Build the undirected graph with an edge u - v iff `g` has at least one edge
+/// between u and v, in either direction.
+fn simple_projection<G>(g : G) -> Graph<usize, (), Undirected>
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let mut edges : Vec<(usize, usize)> = g.edge_references().map(|e| {
+        let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+        (u.min(v), u.max(v))
+    }).collect::<HashSet<_>>().into_iter().collect();
+    edges.sort();
+
+    let mut _g = UnGraph::<usize, ()>::new_undirected();
+    (0..g.node_count()).for_each(|i| { _g.add_node(i); });
+    for (u, v) in edges {
+        _g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+    }
+
+    _g
+}
+
+/// Kind of a node of the modular decomposition tree.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ModuleKind {
+    Leaf,
+    Parallel,
+    Series,
+    Prime,
+}
+
+impl Label for ModuleKind {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        bytes.push(*self as u8);
+    }
+}
+
+/// Add the decomposition tree of the module `module` of the graph given by its
+/// adjacency matrix to `tree`, returning its root. labels[i] is the kind of
+/// the node i of the tree. The singleton children of a module form a path
+/// hanging from it.
+fn decompose(adjacent : &[Vec<bool>], module : Vec<usize>, tree : &mut Graph<usize, (), Undirected>, labels : &mut Vec<ModuleKind>) -> NodeIndex {
+    let node = tree.add_node(labels.len());
+    if module.len() == 1 {
+        labels.push(ModuleKind::Leaf);
+        return node;
+    }
+
+    let (kind, children) = match split(&module, |u, v| adjacent[u][v]) {
+        parts if parts.len() > 1 => (ModuleKind::Parallel, parts),
+        _ => match split(&module, |u, v| u != v && !adjacent[u][v]) {
+            parts if parts.len() > 1 => (ModuleKind::Series, parts),
+            _ => (ModuleKind::Prime, maximal_modules(adjacent, &module)),
+        },
+    };
+    labels.push(kind);
+
+    let mut last_leaf = node;
+    for child in children {
+        if child.len() == 1 {
+            let leaf = tree.add_node(labels.len());
+            labels.push(ModuleKind::Leaf);
+            tree.add_edge(last_leaf, leaf, ());
+            last_leaf = leaf;
+        } else {
+            let child = decompose(adjacent, child, tree, labels);
+            tree.add_edge(node, child, ());
+        }
+    }
+    node
+}
+
+/// Connected components of the graph on `nodes` where u - v iff adjacent(u, v).
+fn split<F>(nodes : &[usize], adjacent : F) -> Vec<Vec<usize>>
+where
+    F : Fn(usize, usize) -> bool
+{
+    let mut seen = HashSet::new();
+    let mut parts = Vec::new();
+
+    for u in nodes {
+        if !seen.insert(*u) { continue; }
+        let mut part = vec![*u];
+        let mut i = 0;
+        while i < part.len() {
+            let v = part[i];
+            i += 1;
+            for w in nodes {
+                if adjacent(v, *w) && seen.insert(*w) {
+                    part.push(*w);
+                }
+            }
+        }
+        parts.push(part);
+    }
+
+    parts
+}
+
+/// Maximal strong submodules of the prime module `module`, which partition it.
+///
+/// Every other module of a prime module lies in one of them, so the one
+/// containing u is the union of the smallest modules containing u and another
+/// node, except those equal to the whole module.
+fn maximal_modules(adjacent : &[Vec<bool>], module : &[usize]) -> Vec<Vec<usize>> {
+    let mut assigned = HashSet::new();
+    let mut modules = Vec::new();
+
+    for u in module {
+        if assigned.contains(u) { continue; }
+        let mut maximal = HashSet::from([*u]);
+        for v in module {
+            if maximal.contains(v) { continue; }
+            let closure = smallest_module(adjacent, module, *u, *v);
+            if closure.len() < module.len() {
+                maximal.extend(closure);
+            }
+        }
+
+        let mut maximal : Vec<usize> = maximal.into_iter().collect();
+        maximal.sort();
+        assigned.extend(maximal.iter().copied());
+        modules.push(maximal);
+    }
+
+    modules
+}
+
+/// Smallest module of the graph restricted to `module` containing u and v:
+/// nodes adjacent to some but not all of its members are added until there
+/// are none.
+fn smallest_module(adjacent : &[Vec<bool>], module : &[usize], u : usize, v : usize) -> Vec<usize> {
+    let mut members = vec![u, v];
+    let mut inside : HashSet<usize> = HashSet::from([u, v]);
+
+    loop {
+        let splitter = module.iter().find(|x| {
+            !inside.contains(*x) && {
+                let first = adjacent[**x][members[0]];
+                members.iter().any(|m| adjacent[**x][*m] != first)
+            }
+        });
+        match splitter {
+            Some(x) => {
+                inside.insert(*x);
+                members.push(*x);
+            }
+            None => return members,
+        }
+    }
+}
+
+/// Biconnected components of `g`, as sets of nodes, with the Hopcroft-Tarjan
+/// algorithm. Each isolated node is a component of its own.
+fn biconnected_components<G>(g : G) -> Vec<Vec<usize>>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    let n = g.node_count();
+    let adjacency : Vec<Vec<usize>> = (0..n).map(|u| {
+        let mut neigh : Vec<usize> = g.neighbors(g.from_index(u)).map(|v| g.to_index(v)).filter(|v| *v != u).collect();
+        neigh.sort();
+        neigh.dedup();
+        neigh
+    }).collect();
+
+    let mut discovery = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut components = Vec::new();
+    let mut visited = Vec::new();
+    let mut time = 0;
+
+    for root in 0..n {
+        if discovery[root] != usize::MAX { continue; }
+        if adjacency[root].is_empty() {
+            discovery[root] = time;
+            time += 1;
+            components.push(vec![root]);
+            continue;
+        }
+
+        // Depth-first search, with the next neighbor to visit of each node on
+        // the path
+        discovery[root] = time;
+        low[root] = time;
+        time += 1;
+        visited.push(root);
+        let mut path = vec![(root, 0)];
+
+        while let Some((u, i)) = path.last_mut() {
+            let u = *u;
+            if let Some(v) = adjacency[u].get(*i).copied() {
+                *i += 1;
+                if discovery[v] == usize::MAX {
+                    discovery[v] = time;
+                    low[v] = time;
+                    time += 1;
+                    visited.push(v);
+                    path.push((v, 0));
+                } else {
+                    low[u] = low[u].min(discovery[v]);
+                }
+                continue;
+            }
+
+            // u is done : it closes a component with its parent if nothing
+            // below it reaches above the parent
+            path.pop();
+            if let Some((parent, _)) = path.last() {
+                let parent = *parent;
+                low[parent] = low[parent].min(low[u]);
+                if low[u] >= discovery[parent] {
+                    let start = visited.iter().rposition(|w| *w == u).unwrap();
+                    let mut component = visited.split_off(start);
+                    component.push(parent);
+                    component.sort();
+                    components.push(component);
+                }
+            }
+        }
+        visited.clear();
+    }
+
+    components
+}
+
+/// Build the subgraph of `g` induced by `nodes`, where the node nodes[i] of g
+/// becomes the node i.
+pub(crate) fn induced_subgraph<G>(g : G, nodes : &[usize]) -> Graph<usize, (), Undirected>
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let mut position = vec![usize::MAX; g.node_count()];
+    for (i, u) in nodes.iter().enumerate() {
+        position[*u] = i;
+    }
+
+    let mut _g = UnGraph::<usize, ()>::new_undirected();
+
+    _g.reserve_nodes(nodes.len());
+    (0..nodes.len()).for_each(|i| { _g.add_node(i); });
+
+    for e in g.edge_references() {
+        let (u, v) = (position[g.to_index(e.source())], position[g.to_index(e.target())]);
+        if u != usize::MAX && v != usize::MAX {
+            _g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+        }
+    }
+
+    _g
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::{GraphKey, MatrixError};
+    use crate::common::{gen_test_graph, generate_random_graph, generate_permutated_graph};
+    use petgraph::graph::{DiGraph, NodeIndex, UnGraph};
+    use rand::seq::SliceRandom;
+    use rand::thread_rng;
+
+    #[test]
+    fn core_keys_ignore_pendant_trees() {
+
+        // 5-cycle with a pendant path 0 - 5 - 6
+        let g1 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 0), (0, 5), (5, 6)]);
+
+        // 5-cycle with a pendant star on 2 and a pendant node on 3
+        let g2 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 0), (2, 5), (5, 6), (5, 7), (3, 8)]);
+
+        assert_ne!(GraphKey::new(&g1), GraphKey::new(&g2));
+        assert_eq!(GraphKey::of_core(&g1, 2), GraphKey::of_core(&generate_permutated_graph(&g2), 2));
+
+        let c5 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)]);
+        assert_eq!(GraphKey::of_core(&g1, 2), GraphKey::new(&c5));
+
+        // The 3-core of a cycle is empty
+        assert_eq!(GraphKey::of_core(&g1, 3), GraphKey::default());
+    }
+
+    #[test]
+    fn hypergraph_keys() {
+
+        let hyperedges = vec![vec![0, 1, 2], vec![2, 3], vec![3, 4, 0], vec![1]];
+        let key = GraphKey::from_hypergraph(5, &hyperedges);
+
+        // Vertices relabelled by u -> p[u], hyperedges and members reordered
+        let p = [3, 0, 4, 1, 2];
+        let mut permuted : Vec<Vec<usize>> = hyperedges.iter().rev().map(|e| e.iter().rev().map(|u| p[*u]).collect()).collect();
+        assert_eq!(GraphKey::from_hypergraph(5, &permuted), key);
+
+        // Replacing the hyperedge {1} by {2} changes the vertex degrees
+        permuted[0] = vec![p[2]];
+        assert_ne!(GraphKey::from_hypergraph(5, &permuted), key);
+
+        // Vertices and hyperedges cannot be exchanged : the graph 0 - 1 as a
+        // hypergraph is not its dual
+        assert_ne!(GraphKey::from_hypergraph(2, &[vec![0, 1]]), GraphKey::from_hypergraph(1, &[vec![0], vec![0]]));
+
+        // Nor when one side is empty : 3 vertices differ from 3 empty hyperedges
+        assert_ne!(GraphKey::from_hypergraph(3, &[]), GraphKey::from_hypergraph(0, &[vec![], vec![], vec![]]));
+        assert_ne!(GraphKey::from_hypergraph(0, &[]), GraphKey::from_hypergraph(0, &[vec![]]));
+    }
+
+    #[test]
+    fn weighted_matrix_keys() {
+
+        let m = vec![
+            vec![0, 3, 0, 1],
+            vec![3, 2, 1, 0],
+            vec![0, 1, 0, 3],
+            vec![1, 0, 3, 0],
+        ];
+
+        // Rows and columns permuted by p, i.e. node u becomes node p[u]
+        let p = [2, 0, 3, 1];
+        let mut permuted = vec![vec![0; 4]; 4];
+        for (u, row) in m.iter().enumerate() {
+            for (v, w) in row.iter().enumerate() {
+                permuted[p[u]][p[v]] = *w;
+            }
+        }
+        assert_eq!(GraphKey::from_weighted_matrix(&permuted), GraphKey::from_weighted_matrix(&m));
+
+        // Swapping two weights keeps the structure of the cycle but not the key
+        let mut reweighted = m.clone();
+        (reweighted[0][1], reweighted[1][0]) = (1, 1);
+        (reweighted[0][3], reweighted[3][0]) = (3, 3);
+        assert_ne!(GraphKey::from_weighted_matrix(&reweighted), GraphKey::from_weighted_matrix(&m));
+
+        // Same for the weight of a node
+        let mut reweighted = m.clone();
+        reweighted[1][1] = 0;
+        reweighted[2][2] = 2;
+        assert_eq!(GraphKey::from_weighted_matrix(&reweighted), GraphKey::from_weighted_matrix(&m));
+        reweighted[0][0] = 2;
+        assert_ne!(GraphKey::from_weighted_matrix(&reweighted), GraphKey::from_weighted_matrix(&m));
+
+        // Uniform matrices only differ by the value of their weight
+        let uniform = |w : i64| GraphKey::from_weighted_matrix(&vec![vec![w; 3]; 3]);
+        assert_ne!(uniform(5), uniform(7));
+        assert_ne!(uniform(5), uniform(-1));
+        assert_eq!(uniform(5), uniform(5));
+    }
+
+    #[test]
+    #[should_panic]
+    fn weighted_matrix_must_be_symmetric() {
+        GraphKey::from_weighted_matrix(&[vec![0, 1], vec![2, 0]]);
+    }
+
+    #[test]
+    fn colored_matrix_keys() {
+
+        // Path 0 - 1 - 2 - 3 with coloured edges, and a loop on 3
+        let vertex_colors = [0, 1, 1, 2];
+        let m = vec![
+            vec![0, 1, 0, 0],
+            vec![1, 0, 2, 0],
+            vec![0, 2, 0, 1],
+            vec![0, 0, 1, 3],
+        ];
+        let key = GraphKey::from_colored_matrix(&vertex_colors, &m).unwrap();
+
+        // Both permuted by p, i.e. node u becomes node p[u]
+        let p = [2, 0, 3, 1];
+        let mut permuted_colors = [0; 4];
+        let mut permuted = vec![vec![0; 4]; 4];
+        for (u, row) in m.iter().enumerate() {
+            permuted_colors[p[u]] = vertex_colors[u];
+            for (v, c) in row.iter().enumerate() {
+                permuted[p[u]][p[v]] = *c;
+            }
+        }
+        assert_eq!(GraphKey::from_colored_matrix(&permuted_colors, &permuted), Ok(key.clone()));
+
+        // Only the matrix permuted
+        assert_ne!(GraphKey::from_colored_matrix(&vertex_colors, &permuted), Ok(key.clone()));
+        assert_ne!(GraphKey::from_colored_matrix(&[0, 1, 2, 2], &m), Ok(key.clone()));
+
+        // Recolouring every vertex, or every edge, the same way
+        let shifted : Vec<Vec<usize>> = m.iter().map(|row| row.iter().map(|c| if *c == 0 { 0 } else { c + 1 }).collect()).collect();
+        assert_ne!(GraphKey::from_colored_matrix(&vertex_colors.map(|c| c + 1), &m), Ok(key.clone()));
+        assert_ne!(GraphKey::from_colored_matrix(&vertex_colors, &shifted), Ok(key));
+        let c4 = |c| vec![vec![0, c, 0, c], vec![c, 0, c, 0], vec![0, c, 0, c], vec![c, 0, c, 0]];
+        assert_ne!(GraphKey::from_colored_matrix(&[0; 4], &c4(1)), GraphKey::from_colored_matrix(&[0; 4], &c4(2)));
+        assert_ne!(GraphKey::from_colored_matrix(&[0; 4], &c4(1)), GraphKey::from_colored_matrix(&[1; 4], &c4(1)));
+
+        assert_eq!(GraphKey::from_colored_matrix(&[0, 0], &[vec![0, 1], vec![2, 0]]), Err(MatrixError::NotSymmetric { u : 1, v : 0 }));
+        assert_eq!(GraphKey::from_colored_matrix(&[0, 0], &[vec![0, 1], vec![1]]), Err(MatrixError::NotSquare { row : 1 }));
+        assert_eq!(GraphKey::from_colored_matrix(&[0], &[vec![0, 1], vec![1, 0]]), Err(MatrixError::LengthMismatch { vertices : 1, rows : 2 }));
+    }
+
+    #[test]
+    fn keys_ignoring_isolated_nodes() {
+
+        let g = gen_test_graph();
+        let mut padded = generate_permutated_graph(&g);
+        (0..5).for_each(|i| { padded.add_node(10 + i); });
+
+        assert_ne!(GraphKey::new(&padded), GraphKey::new(&g));
+        assert_eq!(GraphKey::new_ignoring_isolated(&padded), GraphKey::new_ignoring_isolated(&g));
+        assert_eq!(GraphKey::new_ignoring_isolated(&padded), GraphKey::new(&g));
+
+        let mut empty = UnGraph::<usize, ()>::new_undirected();
+        empty.add_node(0);
+        assert_eq!(GraphKey::new_ignoring_isolated(&empty), GraphKey::default());
+    }
+
+    #[test]
+    fn complement_keys() {
+
+        for g in [gen_test_graph(), generate_random_graph(20, 0.6)] {
+            let key = GraphKey::of_complement(&g);
+            assert_eq!(GraphKey::of_complement(&generate_permutated_graph(&g)), key);
+            assert_ne!(key, GraphKey::new(&g));
+        }
+
+        // The complement of a triangle plus an isolated node is a claw
+        let mut g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        g.add_node(3);
+        let claw = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3)]);
+        assert_eq!(GraphKey::of_complement(&g), GraphKey::new(&claw));
+
+        // The path on 4 nodes is its own complement
+        let p4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(GraphKey::of_complement(&p4), GraphKey::new(&p4));
+    }
+
+    #[test]
+    fn line_graph_keys() {
+
+        for _ in 0..5 {
+            let g = generate_random_graph(12, 0.3);
+            assert_eq!(GraphKey::of_line_graph(&g), GraphKey::of_line_graph(&generate_permutated_graph(&g)));
+        }
+
+        // The line graph of a path is a shorter path
+        let p4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        let p3 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        assert_eq!(GraphKey::of_line_graph(&p4), GraphKey::new(&p3));
+
+        // Whitney's exception : the triangle and the claw share their line graph
+        let triangle = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        let claw = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3)]);
+        assert_ne!(GraphKey::new(&triangle), GraphKey::new(&claw));
+        assert_eq!(GraphKey::of_line_graph(&triangle), GraphKey::of_line_graph(&claw));
+    }
+
+    #[test]
+    fn attributed_keys() {
+
+        // Atoms as node labels, bond orders as edge labels
+        let atoms = ['C', 'C', 'O', 'H'];
+        let bonds : [(usize, usize, u8); 3] = [(0, 1, 1), (1, 2, 2), (0, 3, 1)];
+        let g = UnGraph::<char, u8>::from_edges(bonds.map(|(u, v, w)| (u as u32, v as u32, w)));
+
+        // Same molecule with the nodes relabelled by u -> p[u]
+        let p = [2, 3, 0, 1];
+        let h = UnGraph::<char, u8>::from_edges(bonds.map(|(u, v, w)| (p[v] as u32, p[u] as u32, w)));
+        let mut h_atoms = ['?'; 4];
+        (0..4).for_each(|u| h_atoms[p[u]] = atoms[u]);
+
+        let key = GraphKey::attributed(&g, |u| atoms[u], |e| *e.weight());
+        assert_eq!(GraphKey::attributed(&h, |u| h_atoms[u], |e| *e.weight()), key);
+
+        // Moving the double bond, or an atom, changes the key
+        let moved = UnGraph::<char, u8>::from_edges([(0, 1, 2), (1, 2, 1), (0, 3, 1)]);
+        assert_ne!(GraphKey::attributed(&moved, |u| atoms[u], |e| *e.weight()), key);
+        assert_ne!(GraphKey::attributed(&g, |u| ['C', 'O', 'C', 'H'][u], |e| *e.weight()), key);
+
+        // Uniform labels are written too : all-C differs from all-N, and all
+        // single bonds from all double bonds
+        assert_ne!(GraphKey::attributed(&g, |_| 'C', |_| 1u8), GraphKey::attributed(&g, |_| 'N', |_| 1u8));
+        assert_ne!(GraphKey::attributed(&g, |_| 'C', |_| 1u8), GraphKey::attributed(&g, |_| 'C', |_| 2u8));
+
+        // Directed edges : relabelling the nodes of a labelled path keeps the
+        // key, reversing its edges does not
+        let path = DiGraph::<(), u8>::from_edges([(0, 1, 1), (1, 2, 2)]);
+        let reversed = DiGraph::<(), u8>::from_edges([(2, 1, 1), (1, 0, 2)]);
+        let swapped = DiGraph::<(), u8>::from_edges([(1, 0, 1), (2, 1, 2)]);
+        let directed_key = GraphKey::attributed(&path, |_| 0, |e| *e.weight());
+        assert_eq!(GraphKey::attributed(&reversed, |_| 0, |e| *e.weight()), directed_key);
+        assert_ne!(GraphKey::attributed(&swapped, |_| 0, |e| *e.weight()), directed_key);
+
+        let undirected = UnGraph::<(), u8>::from_edges([(0, 1, 1), (1, 2, 2)]);
+        assert_ne!(GraphKey::attributed(&undirected, |_| 0, |e| *e.weight()), directed_key);
+    }
+
+    #[test]
+    fn edge_colored_keys() {
+
+        // Random graph with edges colored among 5 colors
+        let g = generate_random_graph(20, 0.3);
+        let colors : Vec<(u32, u32, usize)> = g.edge_indices().map(|e| {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            (u.index() as u32, v.index() as u32, (u.index() * v.index()) % 5)
+        }).collect();
+        let build = |edges : Vec<(u32, u32, usize)>| {
+            let mut h = UnGraph::<(), usize>::default();
+            (0..20).for_each(|_| { h.add_node(()); });
+            h.extend_with_edges(edges);
+            h
+        };
+        let colored = build(colors.clone());
+
+        let mut p : Vec<u32> = (0..20).collect();
+        p.shuffle(&mut thread_rng());
+        let permuted = build(colors.iter().map(|(u, v, c)| (p[*v as usize], p[*u as usize], *c)).collect());
+
+        let key = GraphKey::edge_colored(&colored, |e| *e.weight());
+        assert_eq!(GraphKey::edge_colored(&permuted, |e| *e.weight()), key);
+        assert_eq!(GraphKey::attributed(&colored, |_| (), |e| *e.weight()), key);
+
+        // Swapping two colors of a path changes the key
+        let path = UnGraph::<(), usize>::from_edges([(0, 1, 0), (1, 2, 0), (2, 3, 1)]);
+        let recolored = UnGraph::<(), usize>::from_edges([(0, 1, 0), (1, 2, 1), (2, 3, 0)]);
+        assert_ne!(GraphKey::edge_colored(&recolored, |e| *e.weight()), GraphKey::edge_colored(&path, |e| *e.weight()));
+
+        // So does recoloring every edge the same way
+        assert_ne!(GraphKey::edge_colored(&path, |_| 0usize), GraphKey::edge_colored(&path, |_| 1usize));
+    }
+
+    #[test]
+    fn signed_keys() {
+
+        let g = generate_random_graph(20, 0.3);
+        let signs : Vec<(u32, u32, bool)> = g.edge_indices().map(|e| {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            (u.index() as u32, v.index() as u32, (u.index() + v.index()) % 3 == 0)
+        }).collect();
+        let build = |edges : Vec<(u32, u32, bool)>| {
+            let mut h = UnGraph::<(), bool>::default();
+            (0..20).for_each(|_| { h.add_node(()); });
+            h.extend_with_edges(edges);
+            h
+        };
+        let signed = build(signs.clone());
+
+        let mut p : Vec<u32> = (0..20).collect();
+        p.shuffle(&mut thread_rng());
+        let permuted = build(signs.iter().map(|(u, v, s)| (p[*u as usize], p[*v as usize], *s)).collect());
+
+        let key = GraphKey::signed(&signed, |e| *e.weight());
+        assert_eq!(GraphKey::signed(&permuted, |e| *e.weight()), key);
+
+        // Flipping the sign of a single edge
+        let mut flipped = signs;
+        flipped[0].2 = !flipped[0].2;
+        assert_ne!(GraphKey::signed(&build(flipped), |e| *e.weight()), key);
+
+        // A single positive edge and a single negative one
+        let positive = UnGraph::<(), bool>::from_edges([(0, 1, true)]);
+        let negative = UnGraph::<(), bool>::from_edges([(0, 1, false)]);
+        assert_ne!(GraphKey::signed(&positive, |e| *e.weight()), GraphKey::signed(&negative, |e| *e.weight()));
+    }
+
+    #[test]
+    fn underlying_undirected_keys() {
+
+        // Directed 4-cycle with a chord in both directions and a doubled edge
+        let g = DiGraph::<(), ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (2, 0), (0, 1)]);
+        let projection = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0), (0, 2)]);
+
+        let key = GraphKey::underlying_undirected(&g);
+        assert_eq!(key, GraphKey::new(&generate_permutated_graph(&projection)));
+        assert_eq!(GraphKey::underlying_undirected(&projection), key);
+
+        // Reversing edges does not change the underlying graph
+        let reversed = DiGraph::<(), ()>::from_edges([(1, 0), (2, 1), (3, 2), (0, 3), (2, 0)]);
+        assert_eq!(GraphKey::underlying_undirected(&reversed), key);
+    }
+
+    #[test]
+    fn simple_keys_ignore_multiplicity() {
+
+        let g = gen_test_graph();
+        let mut doubled = generate_permutated_graph(&g);
+        let mut tripled = g.clone();
+        for e in g.edge_indices().take(5) {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            tripled.add_edge(u, v, ());
+            tripled.add_edge(v, u, ());
+        }
+        let e = doubled.edge_indices().next().unwrap();
+        let (u, v) = doubled.edge_endpoints(e).unwrap();
+        doubled.add_edge(u, v, ());
+
+        assert_ne!(GraphKey::new(&doubled), GraphKey::new(&tripled));
+        assert_eq!(GraphKey::simple(&doubled), GraphKey::simple(&tripled));
+        assert_eq!(GraphKey::simple(&tripled), GraphKey::new(&g));
+
+        // Presence still matters
+        let mut missing = g.clone();
+        missing.remove_edge(g.edge_indices().next().unwrap());
+        assert_ne!(GraphKey::simple(&missing), GraphKey::simple(&g));
+    }
+
+    #[test]
+    fn keys_modulo_twins() {
+
+        // Stars : the leaves are false twins
+        let star = |leaves : usize| UnGraph::<usize, ()>::from_edges((1..=leaves).map(|v| (0, v as u32)));
+        assert_eq!(GraphKey::modulo_twins(&star(3)), GraphKey::modulo_twins(&star(5)));
+        assert_ne!(GraphKey::modulo_twins(&star(1)), GraphKey::modulo_twins(&star(5)));
+
+        // Triangle 0 - 1 - 2 with a pendant path on 2, where 0 and 1 are true
+        // twins, then with 4 such twins forming a clique
+        let mut edges = vec![(0, 1), (0, 2), (1, 2), (2, 3), (3, 4)];
+        let g = UnGraph::<usize, ()>::from_edges(edges.clone());
+        edges.extend([(5, 0), (5, 1), (5, 2), (6, 0), (6, 1), (6, 2), (6, 5)]);
+        let h = UnGraph::<usize, ()>::from_edges(edges);
+        assert_eq!(GraphKey::modulo_twins(&g), GraphKey::modulo_twins(&generate_permutated_graph(&h)));
+
+        // Same quotient, but made of false twins
+        let p = UnGraph::<usize, ()>::from_edges([(0, 2), (1, 2), (2, 3), (3, 4)]);
+        assert_ne!(GraphKey::modulo_twins(&p), GraphKey::modulo_twins(&g));
+        assert_ne!(GraphKey::new(&g), GraphKey::new(&h));
+
+        // A clique collapses to a node standing for true twins
+        let mut single = UnGraph::<usize, ()>::new_undirected();
+        single.add_node(0);
+        let k4 = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+        assert_ne!(GraphKey::modulo_twins(&k4), GraphKey::modulo_twins(&single));
+        assert_eq!(GraphKey::modulo_twins(&k4), GraphKey::new_with_labels(&single, &[1u8]));
+        let mut pair = single.clone();
+        pair.add_node(1);
+        assert_eq!(GraphKey::modulo_twins(&pair), GraphKey::new_with_labels(&single, &[2u8]));
+    }
+
+    #[test]
+    fn quotient_keys() {
+
+        // Quotients by the orbits, computed on each graph
+        let orbit_partition = |g : &UnGraph<usize, ()>| {
+            let mut partition = vec![0; g.node_count()];
+            for (i, orbit) in GraphKey::certificate(g).orbits.iter().enumerate() {
+                orbit.iter().for_each(|u| partition[*u] = i);
+            }
+            partition
+        };
+        for g in [gen_test_graph(), UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (3, 4)])] {
+            let h = generate_permutated_graph(&g);
+            assert_eq!(GraphKey::quotient(&g, &orbit_partition(&g)), GraphKey::quotient(&h, &orbit_partition(&h)));
+        }
+
+        // Cells are named by arbitrary values
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(GraphKey::quotient(&path, &[5, 5, 7, 7]), GraphKey::quotient(&path, &[1, 1, 0, 0]));
+
+        // The 4-cycle has two edges between {0, 1} and {2, 3}, the path one
+        let c4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0)]);
+        assert_ne!(GraphKey::quotient(&c4, &[0, 0, 1, 1]), GraphKey::quotient(&path, &[0, 0, 1, 1]));
+        assert_eq!(GraphKey::quotient(&c4, &[0, 0, 1, 1]), GraphKey::quotient(&c4, &[0, 1, 1, 0]));
+        assert_ne!(GraphKey::quotient(&c4, &[0, 0, 1, 1]), GraphKey::quotient(&c4, &[0, 1, 0, 1]));
+
+        // The sizes are written too, even when all the cells have the same
+        let isolated = |n| {
+            let mut g = UnGraph::<usize, ()>::new_undirected();
+            (0..n).for_each(|u| { g.add_node(u); });
+            g
+        };
+        assert_ne!(GraphKey::quotient(&isolated(4), &[0, 0, 1, 1]), GraphKey::quotient(&isolated(2), &[0, 1]));
+        let pair = UnGraph::<usize, ()>::from_edges([(0, 1)]);
+        assert_ne!(GraphKey::quotient(&c4, &[0, 0, 1, 1]), GraphKey::quotient(&pair, &[0, 1]));
+    }
+
+    #[test]
+    fn double_covers() {
+
+        let cycle = |n : u32| UnGraph::<usize, ()>::from_edges((0..n).map(|u| (u, (u + 1) % n)));
+        let twice = |n : u32| UnGraph::<usize, ()>::from_edges((0..n).flat_map(|u| [(u, (u + 1) % n), (n + u, n + (u + 1) % n)]));
+
+        // The even cycle is bipartite, the odd one is covered by a cycle twice as long
+        assert_eq!(GraphKey::of_double_cover(&cycle(6)), GraphKey::new(&twice(6)));
+        assert_eq!(GraphKey::of_double_cover(&cycle(5)), GraphKey::new(&cycle(10)));
+        assert_ne!(GraphKey::of_double_cover(&cycle(5)), GraphKey::new(&twice(5)));
+
+        let g = generate_random_graph(15, 0.3);
+        assert_eq!(GraphKey::of_double_cover(&generate_permutated_graph(&g)), GraphKey::of_double_cover(&g));
+    }
+
+    #[test]
+    fn distance_matrix_keys() {
+
+        // The star K1,4 and the square with an isolated node are cospectral
+        let star = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (0, 4)]);
+        let mut square = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0)]);
+        square.add_node(4);
+        assert_ne!(GraphKey::of_distance_matrix(&star), GraphKey::of_distance_matrix(&square));
+
+        // Path 0 - 1 - 2 with an isolated node
+        let mut path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        path.add_node(3);
+        let m = vec![vec![0, 1, 2, -1], vec![1, 0, 1, -1], vec![2, 1, 0, -1], vec![-1, -1, -1, 0]];
+        assert_eq!(GraphKey::of_distance_matrix(&path), GraphKey::from_weighted_matrix(&m));
+
+        // C4 has distances 1 and 2, 2K2 has 1 and the unreachable -1, on the same pairs
+        let c4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 0)]);
+        let two_k2 = UnGraph::<usize, ()>::from_edges([(0, 1), (2, 3)]);
+        assert_ne!(GraphKey::of_distance_matrix(&c4), GraphKey::of_distance_matrix(&two_k2));
+
+        let g = generate_random_graph(20, 0.2);
+        assert_eq!(GraphKey::of_distance_matrix(&generate_permutated_graph(&g)), GraphKey::of_distance_matrix(&g));
+    }
+
+    #[test]
+    fn clustered_keys() {
+
+        let g = generate_random_graph(24, 0.2);
+        let cluster : Vec<usize> = (0..24).map(|u| u % 5).collect();
+        let key = GraphKey::new_with_clusters(&g, &cluster);
+
+        // Relabel the nodes, their clusters following them
+        let mut perm : Vec<usize> = (0..24).collect();
+        perm.shuffle(&mut thread_rng());
+        let h = UnGraph::<usize, ()>::from_edges(g.edge_indices().map(|e| {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            (perm[u.index()] as u32, perm[v.index()] as u32)
+        }));
+        let mut moved = vec![0; 24];
+        perm.iter().enumerate().for_each(|(u, p)| moved[*p] = cluster[u]);
+        assert_eq!(GraphKey::new_with_clusters(&h, &moved), key);
+
+        // Cluster ids do not matter, membership does
+        let renamed : Vec<usize> = moved.iter().map(|c| 100 - 7 * c).collect();
+        assert_eq!(GraphKey::new_with_clusters(&h, &renamed), key);
+        let merged : Vec<usize> = cluster.iter().map(|c| (*c).min(3)).collect();
+        assert_ne!(GraphKey::new_with_clusters(&g, &merged), key);
+
+        // Path 0 - 1 - 2 - 3 split in the middle or at an end
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(GraphKey::new_with_clusters(&path, &[0, 0, 1, 1]), GraphKey::new_with_clusters(&path, &[1, 1, 0, 0]));
+        assert_ne!(GraphKey::new_with_clusters(&path, &[0, 0, 0, 1]), GraphKey::new_with_clusters(&path, &[0, 0, 1, 1]));
+    }
+
+    #[test]
+    fn topological_minor_cores() {
+
+        let path = |n : u32| UnGraph::<usize, ()>::from_edges((1..n).map(|u| (u - 1, u)));
+        let cycle = |n : u32| UnGraph::<usize, ()>::from_edges((0..n).map(|u| (u, (u + 1) % n)));
+
+        // Paths smooth to a single edge, cycles to a self-loop
+        let edge = GraphKey::new(&path(2));
+        assert_eq!(GraphKey::of_topological_minor_core(&path(3)), edge);
+        assert_eq!(GraphKey::of_topological_minor_core(&path(5)), edge);
+        assert_eq!(GraphKey::of_topological_minor_core(&cycle(3)), GraphKey::of_topological_minor_core(&cycle(8)));
+        assert_eq!(GraphKey::of_topological_minor_core(&cycle(4)), GraphKey::new(&UnGraph::<usize, ()>::from_edges([(0, 0)])));
+
+        // K4 with each edge subdivided twice, the nodes 4.. being added on the edges
+        let k4 = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+        let mut subdivided = UnGraph::<usize, ()>::default();
+        (0..16).for_each(|u| { subdivided.add_node(u); });
+        for (i, e) in k4.edge_indices().enumerate() {
+            let (u, v) = k4.edge_endpoints(e).unwrap();
+            let (a, b) = (NodeIndex::new(4 + 2 * i), NodeIndex::new(5 + 2 * i));
+            subdivided.extend_with_edges([(u, a), (a, b), (b, v)]);
+        }
+        let key = GraphKey::of_topological_minor_core(&k4);
+        assert_eq!(key, GraphKey::new(&k4));
+        assert_eq!(GraphKey::of_topological_minor_core(&subdivided), key);
+        assert_eq!(GraphKey::of_topological_minor_core(&generate_permutated_graph(&subdivided)), key);
+
+        // Triangle with a pendant edge: the pendant node has degree 1
+        let paw = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3)]);
+        assert_ne!(GraphKey::of_topological_minor_core(&paw), GraphKey::of_topological_minor_core(&cycle(3)));
+    }
+
+    #[test]
+    fn netlist_keys() {
+
+        // Gate 0 wired to 1, 2 and 3 by its ports 0, 1 and 2; 1 also drives 4
+        let wires = [(0, 1, 0, 0), (0, 2, 1, 0), (0, 3, 2, 0), (1, 4, 1, 0), (2, 3, 1, 1)];
+        let netlist = |perm : &[usize], wires : &[(usize, usize, u8, u8)]| {
+            let mut g = UnGraph::<(), (u8, u8)>::default();
+            (0..5).for_each(|_| { g.add_node(()); });
+            for (i, (u, v, p, q)) in wires.iter().enumerate() {
+                // Every other wire is listed from its other end
+                if i % 2 == 0 {
+                    g.add_edge(NodeIndex::new(perm[*u]), NodeIndex::new(perm[*v]), (*p, *q));
+                } else {
+                    g.add_edge(NodeIndex::new(perm[*v]), NodeIndex::new(perm[*u]), (*q, *p));
+                }
+            }
+            g
+        };
+
+        let g = netlist(&[0, 1, 2, 3, 4], &wires);
+        let key = GraphKey::with_ports(&g, |e| *e.weight());
+        let h = netlist(&[3, 0, 4, 2, 1], &wires);
+        assert_eq!(GraphKey::with_ports(&h, |e| *e.weight()), key);
+        assert_eq!(GraphKey::new(&h), GraphKey::new(&g));
+
+        // Swapping the ports 0 and 1 of the gate changes the netlist
+        let swapped = [(0, 1, 1, 0), (0, 2, 0, 0), (0, 3, 2, 0), (1, 4, 1, 0), (2, 3, 1, 1)];
+        assert_ne!(GraphKey::with_ports(&netlist(&[0, 1, 2, 3, 4], &swapped), |e| *e.weight()), key);
+
+        // Shifting every port number keeps their order, not the netlist
+        assert_ne!(GraphKey::with_ports(&g, |e| (e.weight().0 + 1, e.weight().1 + 1)), key);
+    }
+
+    #[test]
+    fn square_keys() {
+
+        // The square of the path 0 - 1 - 2 - 3 adds 0 - 2 and 1 - 3
+        let p4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        let square = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (0, 2), (1, 3)]);
+        assert_eq!(GraphKey::of_square(&p4), GraphKey::new(&square));
+        assert_ne!(GraphKey::of_square(&p4), GraphKey::new(&p4));
+        assert_eq!(GraphKey::of_square(&generate_permutated_graph(&p4)), GraphKey::of_square(&p4));
+
+        // Self-loops are ignored
+        let looped = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 1), (1, 2), (2, 3)]);
+        assert_eq!(GraphKey::of_square(&looped), GraphKey::new(&square));
+
+        let g = generate_random_graph(20, 0.1);
+        assert_eq!(GraphKey::of_square(&generate_permutated_graph(&g)), GraphKey::of_square(&g));
+    }
+
+    #[test]
+    fn multiplex_keys() {
+
+        // The same permutation applied to every layer
+        let relabel = |g : &UnGraph<usize, ()>, perm : &[usize]| {
+            let mut h = UnGraph::<usize, ()>::default();
+            (0..perm.len()).for_each(|u| { h.add_node(u); });
+            for e in g.edge_indices() {
+                let (u, v) = g.edge_endpoints(e).unwrap();
+                h.add_edge(NodeIndex::new(perm[u.index()]), NodeIndex::new(perm[v.index()]), ());
+            }
+            h
+        };
+
+        let layers = [generate_random_graph(12, 0.2), generate_random_graph(12, 0.3), generate_random_graph(12, 0.1)];
+        let key = GraphKey::multiplex(&layers.iter().collect::<Vec<_>>());
+        let mut perm : Vec<usize> = (0..12).collect();
+        perm.shuffle(&mut thread_rng());
+        let permuted : Vec<UnGraph<usize, ()>> = layers.iter().map(|g| relabel(g, &perm)).collect();
+        assert_eq!(GraphKey::multiplex(&permuted.iter().collect::<Vec<_>>()), key);
+
+        // Swapping two layers only keeps the key if they are equal
+        assert_ne!(GraphKey::multiplex(&[&layers[1], &layers[0], &layers[2]]), key);
+        let copy = relabel(&layers[0], &(0..12).collect::<Vec<usize>>());
+        assert_eq!(GraphKey::multiplex(&[&copy, &layers[0], &layers[2]]), GraphKey::multiplex(&[&layers[0], &copy, &layers[2]]));
+        let empty = relabel(&UnGraph::default(), &perm);
+        assert_ne!(GraphKey::multiplex(&[&layers[0], &empty]), GraphKey::multiplex(&[&empty, &layers[0]]));
+    }
+
+    #[test]
+    fn block_cut_trees() {
+
+        // Triangle 0 1 2 and square 2 3 4 5 sharing 2, with a pendant edge 5 - 6
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 5), (5, 2), (5, 6)]);
+        let mut blocks = super::biconnected_components(&g);
+        blocks.sort();
+        assert_eq!(blocks, vec![vec![0, 1, 2], vec![2, 3, 4, 5], vec![5, 6]]);
+
+        // Block - cut - block - cut - block
+        let key = GraphKey::of_block_cut_tree(&g);
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert_eq!(key, GraphKey::new_with_labels(&path, &[false, true, false, true, false]));
+        assert_eq!(GraphKey::of_block_cut_tree(&generate_permutated_graph(&g)), key);
+
+        // Other blocks arranged the same way : the square becomes a K4 with a
+        // doubled edge, attached through other nodes
+        let h = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 5), (5, 2), (2, 4), (3, 5), (3, 5), (4, 6)]);
+        assert_ne!(GraphKey::new(&h), GraphKey::new(&g));
+        assert_eq!(GraphKey::of_block_cut_tree(&h), key);
+
+        // Attaching the pendant edge to the triangle makes 2 a cut vertex of 3 blocks
+        let star = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 5), (5, 2), (2, 6)]);
+        assert_ne!(GraphKey::of_block_cut_tree(&star), key);
+
+        // Isolated nodes are blocks
+        let mut isolated = UnGraph::<usize, ()>::from_edges([(0, 1)]);
+        isolated.add_node(2);
+        let mut two_blocks = UnGraph::<usize, ()>::new_undirected();
+        two_blocks.add_node(0);
+        two_blocks.add_node(1);
+        assert_eq!(GraphKey::of_block_cut_tree(&isolated), GraphKey::new_with_labels(&two_blocks, &[false, false]));
+    }
+
+    #[test]
+    fn modular_decompositions() {
+
+        // K2,3 and the cograph (K1 + K1) x (K1 + K1 + K1) built otherwise
+        let k23 = UnGraph::<usize, ()>::from_edges([(0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4)]);
+        let relabelled = UnGraph::<usize, ()>::from_edges([(4, 0), (4, 1), (4, 3), (2, 0), (2, 1), (2, 3)]);
+        let key = GraphKey::of_modular_decomposition(&k23);
+        assert_eq!(GraphKey::of_modular_decomposition(&relabelled), key);
+        assert_eq!(GraphKey::of_modular_decomposition(&generate_permutated_graph(&k23)), key);
+
+        // Series root with two parallel children, chaining 2 and 3 leaves
+        use super::ModuleKind::{Leaf, Parallel, Series};
+        let tree = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (1, 3), (3, 4), (2, 5), (5, 6), (6, 7)]);
+        let labels = [(true, Series), (false, Parallel), (false, Parallel), (false, Leaf), (false, Leaf), (false, Leaf), (false, Leaf), (false, Leaf)];
+        assert_eq!(key, GraphKey::new_with_labels(&tree, &labels));
+
+        // The complement swaps series and parallel modules
+        let complement = UnGraph::<usize, ()>::from_edges([(0, 1), (2, 3), (2, 4), (3, 4)]);
+        assert_ne!(GraphKey::of_modular_decomposition(&complement), key);
+
+        // The 5-cycle and the bull are prime, with 5 leaves : only the tree is
+        // described. Neither matches a cograph
+        let c5 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)]);
+        let bull = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (1, 3), (2, 4)]);
+        assert_eq!(GraphKey::of_modular_decomposition(&c5), GraphKey::of_modular_decomposition(&bull));
+        assert_ne!(GraphKey::of_modular_decomposition(&c5), key);
+
+        // A path 0 - 1 - 2 - 3 whose ends are replaced by modules : a prime
+        // root, whose children are two leaves and two modules
+        let inflated = UnGraph::<usize, ()>::from_edges([(0, 2), (1, 2), (2, 3), (3, 4), (3, 5), (4, 5)]);
+        let adjacent : Vec<Vec<bool>> = (0..6u32).map(|u| (0..6u32).map(|v| inflated.contains_edge(u.into(), v.into())).collect()).collect();
+        let mut modules = super::maximal_modules(&adjacent, &[0, 1, 2, 3, 4, 5]);
+        modules.sort();
+        assert_eq!(modules, vec![vec![0, 1], vec![2], vec![3], vec![4, 5]]);
+
+        let g = generate_random_graph(15, 0.5);
+        assert_eq!(GraphKey::of_modular_decomposition(&generate_permutated_graph(&g)), GraphKey::of_modular_decomposition(&g));
+    }
+
+    #[test]
+    fn neighborhoods() {
+
+        // Every ball of radius 2 of the 8-cycle is a path on 5 nodes
+        let c8 = UnGraph::<usize, ()>::from_edges((0..8).map(|u| (u, (u + 1) % 8)));
+        let p5 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert!(GraphKey::neighborhood_keys(&c8, 2).iter().all(|key| *key == GraphKey::new(&p5)));
+        assert!(GraphKey::neighborhood_keys(&c8, 0).iter().all(|key| key.get_descriptor() == &vec![1]));
+
+        let g = generate_random_graph(30, 0.15);
+        let sorted = |mut keys : Vec<GraphKey>| { keys.sort_by(|a, b| a.get_descriptor().cmp(b.get_descriptor())); keys };
+        for k in [1, 2] {
+            let keys = sorted(GraphKey::neighborhood_keys(&g, k));
+            assert_eq!(sorted(GraphKey::neighborhood_keys(&generate_permutated_graph(&g), k)), keys);
+        }
+    }
+
+    #[test]
+    fn excluded_edges() {
+
+        // 5-cycle, with a chord in g1 and a different chord in g2
+        let cycle = [(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)];
+        let g1 = UnGraph::<usize, ()>::from_edges(cycle.iter().copied().chain([(0, 2)]));
+        let g2 = UnGraph::<usize, ()>::from_edges(cycle.iter().copied().chain([(3, 0), (1, 4)]));
+
+        assert_ne!(GraphKey::new(&g1), GraphKey::new(&g2));
+        let key = GraphKey::new_excluding_edges(&g1, &HashSet::from([(2, 0)]));
+        assert_eq!(GraphKey::new_excluding_edges(&g2, &HashSet::from([(0, 3), (1, 4)])), key);
+        assert_eq!(key, GraphKey::new(&UnGraph::<usize, ()>::from_edges(cycle)));
+
+        // Excluding a missing edge changes nothing
+        assert_eq!(GraphKey::new_excluding_edges(&g1, &HashSet::from([(1, 3)])), GraphKey::new(&g1));
+    }
+
+    #[test]
+    fn keys_from_predicates() {
+
+        let predicates : [fn(usize, usize) -> bool; 3] = [
+            |i, j| (i + j) % 3 == 0,
+            |i, j| i != j && (i ^ j).count_ones() == 1,
+            |i, j| i.abs_diff(j) % 5 == 1 || (i * j) % 7 == 3,
+        ];
+
+        for adjacent in predicates {
+            let n = 10;
+            let edges = (0..n).flat_map(|u| (u..n).map(move |v| (u as u32, v as u32))).filter(|(u, v)| adjacent(*u as usize, *v as usize));
+            let mut g = UnGraph::<usize, ()>::from_edges(edges);
+            while g.node_count() < n {
+                g.add_node(0);
+            }
+
+            assert_eq!(GraphKey::from_fn(n, adjacent), GraphKey::new(&g));
+        }
+
+        // Relabelling the nodes through the predicate keeps the key
+        let key = GraphKey::from_fn(8, |i, j| (i + j) % 3 == 0);
+        assert_eq!(GraphKey::from_fn(8, |i, j| ((7 - i) + (7 - j)) % 3 == 0), key);
+        assert_eq!(GraphKey::from_fn(0, |_, _| true), GraphKey::new(&UnGraph::<usize, ()>::default()));
+    }
+}
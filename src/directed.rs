@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use petgraph::{Directed, Direction};
+use petgraph::visit::{NodeCompactIndexable, IntoNeighborsDirected};
+
+use crate::{GraphKey, SearchGraph, SEPARATOR};
+use crate::coloring::{color_add, color_mul, Colouring, DirectedNeighbors};
+
+//
+// Keys of directed graphs
+//
+
+impl<G> SearchGraph for DirectedNeighbors<G>
+where
+    G : NodeCompactIndexable + IntoNeighborsDirected
+{
+    /// Describe the graph relabelled by the leaf as [n, successors of 0,
+    /// SEPARATOR, successors of 1, SEPARATOR, ...], where the successors of
+    /// each node are sorted.
+    /// Self-loops and parallel edges are kept.
+    fn describe(&self, leaf : &Colouring) -> Vec<usize> {
+        let g = self.0;
+        let n = g.node_count();
+
+        let mut successors = vec![Vec::new(); n];
+        for u in 0..n {
+            let row = &mut successors[leaf.color_of(u)];
+            row.extend(g.neighbors_directed(g.from_index(u), Direction::Outgoing).map(|v| leaf.color_of(g.to_index(v))));
+        }
+
+        let mut descriptor = vec![n];
+        for mut row in successors {
+            row.sort();
+            descriptor.extend(row);
+            descriptor.push(SEPARATOR);
+        }
+
+        descriptor
+    }
+}
+
+/// The graph given to `GraphKey::of_dag` has a directed cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HasCycle;
+
+impl fmt::Display for HasCycle {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "graph has a directed cycle")
+    }
+}
+
+impl Error for HasCycle {}
+
+/// The graph given to `GraphKey::of_tournament` is not a tournament: some pair
+/// of distinct nodes is not linked by exactly one directed edge, or it has a
+/// self-loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotATournament;
+
+impl fmt::Display for NotATournament {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "graph is not a tournament")
+    }
+}
+
+impl Error for NotATournament {}
+
+impl GraphKey<Directed> {
+
+    /// Compute the key of the directed graph `g`.
+    ///
+    /// Refinement counts the successors and the predecessors of each node in
+    /// every cell separately, so a graph and its edge-reversal usually get
+    /// different keys. Self-loops and parallel edges are kept. The keys of
+    /// undirected graphs are left to `GraphKey::new`.
+    pub fn new_directed<G>(g : G) -> GraphKey<Directed>
+    where
+        G : NodeCompactIndexable + IntoNeighborsDirected
+    {
+        GraphKey::from_labels(DirectedNeighbors(g), &vec![0u8; g.node_count()])
+    }
+
+    /// Compute the key of the directed acyclic graph `g`.
+    ///
+    /// The initial cells group the nodes by topological level, the length of
+    /// the longest path from a source to the node, which leaves less work to
+    /// the search. Fails if `g` has a cycle, self-loops included.
+    pub fn of_dag<G>(g : G) -> Result<GraphKey<Directed>, HasCycle>
+    where
+        G : NodeCompactIndexable + IntoNeighborsDirected
+    {
+        let n = g.node_count();
+        let mut in_degree : Vec<usize> = (0..n).map(|u| g.neighbors_directed(g.from_index(u), Direction::Incoming).count()).collect();
+        let mut levels = vec![0; n];
+
+        // Kahn's algorithm : a node is visited once all its predecessors are
+        let mut stack : Vec<usize> = (0..n).filter(|u| in_degree[*u] == 0).collect();
+        let mut visited = 0;
+        while let Some(u) = stack.pop() {
+            visited += 1;
+            for v in g.neighbors_directed(g.from_index(u), Direction::Outgoing) {
+                let v = g.to_index(v);
+                levels[v] = levels[v].max(levels[u] + 1);
+                in_degree[v] -= 1;
+                if in_degree[v] == 0 {
+                    stack.push(v);
+                }
+            }
+        }
+
+        if visited < n {
+            return Err(HasCycle);
+        }
+        Ok(GraphKey::from_labels(DirectedNeighbors(g), &levels))
+    }
+
+    /// Compute the key of the tournament `g`, where each pair of distinct nodes
+    /// is linked by exactly one directed edge.
+    ///
+    /// The initial cells group the nodes by score, their out-degree, which
+    /// separates the nodes of most tournaments before the search starts. Fails
+    /// if `g` is not a tournament.
+    pub fn of_tournament<G>(g : G) -> Result<GraphKey<Directed>, NotATournament>
+    where
+        G : NodeCompactIndexable + IntoNeighborsDirected
+    {
+        let n = g.node_count();
+        let pair_count = color_mul(n, n.saturating_sub(1)) / 2;
+        let mut pairs = HashSet::with_capacity(pair_count);
+        let mut scores = vec![0; n];
+
+        for (u, score) in scores.iter_mut().enumerate() {
+            for v in g.neighbors_directed(g.from_index(u), Direction::Outgoing) {
+                let v = g.to_index(v);
+                // A self-loop, a parallel edge or an antiparallel one
+                if u == v || !pairs.insert((u.min(v), u.max(v))) {
+                    return Err(NotATournament);
+                }
+                *score = color_add(*score, 1);
+            }
+        }
+
+        if pairs.len() != pair_count {
+            return Err(NotATournament);
+        }
+        Ok(GraphKey::from_labels(DirectedNeighbors(g), &scores))
+    }
+
+    /// Compute the key of the transition graph of an automaton, where
+    /// accepting(n) tells if the state n is accepting.
+    ///
+    /// Edges are directed and self-loops are significant: two automata get the
+    /// same key iff they are isomorphic as directed graphs through a map
+    /// preserving the accepting states. Transition symbols are not taken into
+    /// account. The accepting flags are written into the key, so accepting every
+    /// state differs from rejecting every state.
+    pub fn for_automaton<G, F>(g : G, accepting : F) -> GraphKey<Directed>
+    where
+        G : NodeCompactIndexable + IntoNeighborsDirected,
+        F : Fn(usize) -> bool
+    {
+        // Initial cells : accepting flag, then number of self-loops
+        let labels : Vec<(bool, usize)> = (0..g.node_count()).map(|u| {
+            let node = g.from_index(u);
+            let loops = g.neighbors_directed(node, Direction::Outgoing).filter(|v| *v == node).count();
+            (accepting(u), loops)
+        }).collect();
+
+        GraphKey::from_labels(DirectedNeighbors(g), &labels)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{GraphKey, HasCycle, NotATournament};
+    use petgraph::graph::DiGraph;
+    use rand::{Rng, thread_rng};
+    use rand::seq::SliceRandom;
+
+    /// Transition graph of a DFA given as (state, successor) pairs, with the
+    /// states relabelled by perm. It has one node per entry of perm, states
+    /// without transitions included.
+    fn automaton(transitions : &[(usize, usize)], perm : &[usize]) -> DiGraph<(), ()> {
+        let mut g = DiGraph::with_capacity(perm.len(), transitions.len());
+        perm.iter().for_each(|_| { g.add_node(()); });
+        g.extend_with_edges(transitions.iter().map(|(u, v)| (perm[*u] as u32, perm[*v] as u32)));
+        g
+    }
+
+    #[test]
+    fn directed_keys() {
+
+        // Path 0 -> 1 -> 2 with a chord 0 -> 2 and a pendant edge 3 -> 1
+        let edges = [(0, 1), (1, 2), (0, 2), (3, 1)];
+        let identity = [0, 1, 2, 3];
+        let perm = [2, 3, 0, 1];
+
+        let key = GraphKey::new_directed(&automaton(&edges, &identity));
+        assert_eq!(GraphKey::new_directed(&automaton(&edges, &perm)), key);
+
+        let reversed : Vec<(usize, usize)> = edges.iter().map(|(u, v)| (*v, *u)).collect();
+        assert_ne!(GraphKey::new_directed(&automaton(&reversed, &identity)), key);
+
+        // A directed cycle is its own reversal, up to isomorphism
+        let cycle = automaton(&[(0, 1), (1, 2), (2, 3), (3, 0)], &identity);
+        assert_eq!(GraphKey::new_directed(&cycle), GraphKey::new_directed(&automaton(&[(1, 0), (2, 1), (3, 2), (0, 3)], &perm)));
+
+        let mut rng = thread_rng();
+        for _ in 0..10 {
+            let n = 15;
+            let edges : Vec<(usize, usize)> = (0..40).map(|_| (rng.gen_range(0..n), rng.gen_range(0..n))).collect();
+            let identity : Vec<usize> = (0..n).collect();
+            let mut perm = identity.clone();
+            perm.shuffle(&mut rng);
+            assert_eq!(GraphKey::new_directed(&automaton(&edges, &perm)), GraphKey::new_directed(&automaton(&edges, &identity)));
+        }
+    }
+
+    #[test]
+    fn automaton_keys() {
+
+        // Words over {a, b} whose number of a is a multiple of 3: state i counts
+        // the a's modulo 3, b loops on every state.
+        let transitions = [(0, 1), (1, 2), (2, 0), (0, 0), (1, 1), (2, 2)];
+        let identity = [0, 1, 2];
+        let perm = [2, 0, 1];
+
+        let dfa = automaton(&transitions, &identity);
+        let relabelled = automaton(&transitions, &perm);
+        let key = GraphKey::for_automaton(&dfa, |u| u == 0);
+        assert_eq!(GraphKey::for_automaton(&relabelled, |u| u == perm[0]), key);
+
+        // Accepting a second state gives another automaton
+        assert_ne!(GraphKey::for_automaton(&dfa, |u| u != 2), key);
+
+        // Reversing the cycle is an isomorphism, dropping a self-loop is not
+        let reversed = automaton(&[(1, 0), (2, 1), (0, 2), (0, 0), (1, 1), (2, 2)], &identity);
+        assert_eq!(GraphKey::for_automaton(&reversed, |u| u == 0), key);
+        let no_loop = automaton(&transitions[..5], &identity);
+        assert_ne!(GraphKey::for_automaton(&no_loop, |u| u == 0), key);
+
+        // Direction matters : a -> b and b -> a from an accepting state differ
+        let forward = automaton(&[(0, 1), (1, 2)], &identity);
+        let backward = automaton(&[(1, 0), (1, 2)], &identity);
+        assert_ne!(GraphKey::for_automaton(&forward, |u| u == 0), GraphKey::for_automaton(&backward, |u| u == 0));
+
+        // Accepting every state differs from rejecting every state
+        assert_ne!(GraphKey::for_automaton(&dfa, |_| true), GraphKey::for_automaton(&dfa, |_| false));
+    }
+
+    #[test]
+    fn automaton_keys_of_random_digraphs() {
+
+        let mut rng = thread_rng();
+
+        for _ in 0..20 {
+            let n = 12;
+            let transitions : Vec<(usize, usize)> = (0..n).flat_map(|u| [(u, rng.gen_range(0..n)), (u, rng.gen_range(0..n))]).collect();
+            let accepting : Vec<bool> = (0..n).map(|_| rng.gen_bool(0.3)).collect();
+
+            let identity : Vec<usize> = (0..n).collect();
+            let mut perm = identity.clone();
+            perm.shuffle(&mut rng);
+            let mut inverse = vec![0; n];
+            perm.iter().enumerate().for_each(|(u, p)| inverse[*p] = u);
+
+            let key = GraphKey::for_automaton(&automaton(&transitions, &identity), |u| accepting[u]);
+            assert_eq!(GraphKey::for_automaton(&automaton(&transitions, &perm), |u| accepting[inverse[u]]), key);
+        }
+    }
+
+    #[test]
+    fn dag_keys() {
+
+        // Diamond 0 -> {1, 2} -> 3 with a shortcut 0 -> 3 and a tail 3 -> 4
+        let edges = [(0, 1), (0, 2), (1, 3), (2, 3), (0, 3), (3, 4)];
+        let identity = [0, 1, 2, 3, 4];
+        let perm = [3, 0, 4, 1, 2];
+
+        let key = GraphKey::of_dag(&automaton(&edges, &identity)).unwrap();
+        assert_eq!(GraphKey::of_dag(&automaton(&edges, &perm)), Ok(key.clone()));
+
+        // Same diamond built in another edge order
+        let shuffled = [(3, 4), (2, 3), (0, 3), (0, 2), (1, 3), (0, 1)];
+        assert_eq!(GraphKey::of_dag(&automaton(&shuffled, &identity)), Ok(key.clone()));
+
+        // Moving the tail to 1 keeps the levels but not the graph
+        let moved = [(0, 1), (0, 2), (1, 3), (2, 3), (0, 3), (1, 4)];
+        assert_ne!(GraphKey::of_dag(&automaton(&moved, &identity)), Ok(key.clone()));
+
+        // Reversing the edges gives another DAG
+        let reversed : Vec<(usize, usize)> = edges.iter().map(|(u, v)| (*v, *u)).collect();
+        assert_ne!(GraphKey::of_dag(&automaton(&reversed, &identity)), Ok(key));
+    }
+
+    #[test]
+    fn cyclic_graphs_are_not_dags() {
+
+        let identity = [0, 1, 2, 3];
+        assert_eq!(GraphKey::of_dag(&automaton(&[(0, 1), (1, 2), (2, 3), (3, 1)], &identity)), Err(HasCycle));
+        assert_eq!(GraphKey::of_dag(&automaton(&[(0, 1), (2, 2)], &identity[..3])), Err(HasCycle));
+        assert!(GraphKey::of_dag(&automaton(&[(0, 1), (1, 2), (0, 2)], &identity[..3])).is_ok());
+    }
+
+    #[test]
+    fn tournament_keys() {
+
+        // Rotational tournament on 7 nodes: i beats i + 1, i + 2 and i + 4
+        let edges : Vec<(usize, usize)> = (0..7).flat_map(|i| [1, 2, 4].map(|d| (i, (i + d) % 7))).collect();
+        let identity : Vec<usize> = (0..7).collect();
+        let perm = [5, 2, 6, 0, 3, 1, 4];
+
+        let key = GraphKey::of_tournament(&automaton(&edges, &identity)).unwrap();
+        assert_eq!(GraphKey::of_tournament(&automaton(&edges, &perm)), Ok(key.clone()));
+
+        // Beating i + 3 instead of i + 4 gives another regular tournament
+        let other : Vec<(usize, usize)> = (0..7).flat_map(|i| [1, 2, 3].map(|d| (i, (i + d) % 7))).collect();
+        assert_ne!(GraphKey::of_tournament(&automaton(&other, &identity)), Ok(key.clone()));
+
+        // Transitive tournaments, split by the scores alone
+        let transitive : Vec<(usize, usize)> = (0..7).flat_map(|u| ((u + 1)..7).map(move |v| (u, v))).collect();
+        let key = GraphKey::of_tournament(&automaton(&transitive, &identity)).unwrap();
+        assert_eq!(GraphKey::of_tournament(&automaton(&transitive, &perm)), Ok(key));
+    }
+
+    #[test]
+    fn non_tournaments() {
+
+        let identity = [0, 1, 2];
+        assert!(GraphKey::of_tournament(&automaton(&[(0, 1), (1, 2), (2, 0)], &identity)).is_ok());
+
+        // Missing pair, antiparallel edges, parallel edges and self-loop
+        assert_eq!(GraphKey::of_tournament(&automaton(&[(0, 1), (1, 2)], &identity)), Err(NotATournament));
+        assert_eq!(GraphKey::of_tournament(&automaton(&[(0, 1), (1, 2), (2, 0), (1, 0)], &identity)), Err(NotATournament));
+        assert_eq!(GraphKey::of_tournament(&automaton(&[(0, 1), (1, 2), (2, 0), (2, 0)], &identity)), Err(NotATournament));
+        assert_eq!(GraphKey::of_tournament(&automaton(&[(0, 1), (1, 2), (2, 0), (1, 1)], &identity)), Err(NotATournament));
+    }
+
+    /// Graph with n nodes and no edges, which never stores them
+    #[cfg(feature = "checked-arithmetic")]
+    struct Nodes(usize);
+
+    #[cfg(feature = "checked-arithmetic")]
+    mod nodes {
+        use petgraph::Direction;
+        use petgraph::visit::{GraphBase, IntoNeighbors, IntoNeighborsDirected, NodeCompactIndexable, NodeCount, NodeIndexable};
+        use super::Nodes;
+
+        impl GraphBase for Nodes {
+            type NodeId = usize;
+            type EdgeId = (usize, usize);
+        }
+
+        impl NodeCount for Nodes {
+            fn node_count(&self) -> usize { self.0 }
+        }
+
+        impl NodeIndexable for Nodes {
+            fn node_bound(&self) -> usize { self.0 }
+            fn to_index(&self, u : usize) -> usize { u }
+            fn from_index(&self, u : usize) -> usize { u }
+        }
+
+        impl NodeCompactIndexable for Nodes {}
+
+        impl IntoNeighbors for &Nodes {
+            type Neighbors = std::iter::Empty<usize>;
+            fn neighbors(self, _u : usize) -> Self::Neighbors { std::iter::empty() }
+        }
+
+        impl IntoNeighborsDirected for &Nodes {
+            type NeighborsDirected = std::iter::Empty<usize>;
+            fn neighbors_directed(self, _u : usize, _d : Direction) -> Self::NeighborsDirected { std::iter::empty() }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "checked-arithmetic")]
+    #[should_panic(expected = "overflows usize")]
+    fn pair_count_past_usize_max_panics() {
+
+        // The pairs of 2^(w/2 + 1) nodes outnumber usize::MAX, the count must
+        // not wrap around to a small capacity
+        let n = 1 << (usize::BITS / 2 + 1);
+        let _ = GraphKey::of_tournament(&Nodes(n));
+    }
+}
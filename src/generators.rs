@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+use petgraph::Undirected;
+use petgraph::graph::{NodeIndex, UnGraph, Graph};
+use petgraph::visit::{NodeCompactIndexable, IntoEdges, EdgeRef};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+//
+// Seeded graph generators
+//
+
+// The same seed gives the same graph with a given version of rand.
+
+/// Build the graph with nodes 0..n, each weighted by its index, and the given
+/// edges.
+fn from_edges(n : usize, edges : &[(usize, usize)]) -> Graph<usize, (), Undirected> {
+    let mut g = UnGraph::<usize, ()>::new_undirected();
+    g.reserve_nodes(n);
+    (0..n).for_each(|i| { g.add_node(i); });
+
+    g.reserve_edges(edges.len());
+    for (u, v) in edges {
+        g.add_edge(NodeIndex::new(*u), NodeIndex::new(*v), ());
+    }
+    g
+}
+
+/// Erdős–Rényi graph G(n, p): each pair of nodes is linked with probability p.
+pub fn random_gnp(n : usize, p : f64, seed : u64) -> Graph<usize, (), Undirected> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let edges : Vec<(usize, usize)> = (0..n)
+        .flat_map(|u| ((u + 1)..n).map(move |v| (u, v)))
+        .filter(|_| rng.gen_bool(p))
+        .collect();
+    from_edges(n, &edges)
+}
+
+/// Relabel the nodes of `g` by a random permutation, and shuffle its edges.
+///
+/// The result is isomorphic to g, so it has the same key.
+pub fn permute<G>(g : G, seed : u64) -> Graph<usize, (), Undirected>
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut perm : Vec<usize> = (0..g.node_count()).collect();
+    perm.shuffle(&mut rng);
+
+    let mut edges : Vec<(usize, usize)> = g.edge_references()
+        .map(|e| (perm[g.to_index(e.source())], perm[g.to_index(e.target())]))
+        .collect();
+    edges.shuffle(&mut rng);
+
+    from_edges(g.node_count(), &edges)
+}
+
+/// Random simple d-regular graph on n nodes.
+///
+/// Each node gets d stubs, paired at random while avoiding self-loops and
+/// parallel edges; the pairing restarts when it gets stuck. Panics if n * d is
+/// odd or d >= n, in which case no such graph exists.
+pub fn random_regular(n : usize, d : usize, seed : u64) -> Graph<usize, (), Undirected> {
+    assert!((n * d).is_multiple_of(2), "n * d must be even");
+    assert!(d < n || n * d == 0, "d must be smaller than n");
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    'pairing: loop {
+        let mut stubs : Vec<usize> = (0..n).flat_map(|u| std::iter::repeat_n(u, d)).collect();
+        let mut edges = HashSet::new();
+
+        while !stubs.is_empty() {
+            // Pick two stubs that can be paired, giving up after some attempts
+            let mut attempts = 0;
+            let (i, j) = loop {
+                let (i, j) = (rng.gen_range(0..stubs.len()), rng.gen_range(0..stubs.len()));
+                let (u, v) = (stubs[i].min(stubs[j]), stubs[i].max(stubs[j]));
+                if u != v && !edges.contains(&(u, v)) {
+                    break (i, j);
+                }
+                attempts += 1;
+                if attempts > 100 * stubs.len() {
+                    continue 'pairing;
+                }
+            };
+
+            let (u, v) = (stubs[i].min(stubs[j]), stubs[i].max(stubs[j]));
+            edges.insert((u, v));
+            stubs.swap_remove(i.max(j));
+            stubs.swap_remove(i.min(j));
+        }
+
+        let mut edges : Vec<(usize, usize)> = edges.into_iter().collect();
+        edges.sort();
+        return from_edges(n, &edges);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{random_gnp, permute, random_regular};
+    use crate::GraphKey;
+
+    /// Edges of g as pairs of node indices, in order.
+    fn edges(g : &petgraph::graph::UnGraph<usize, ()>) -> Vec<(usize, usize)> {
+        g.edge_indices().map(|e| {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            (u.index(), v.index())
+        }).collect()
+    }
+
+    #[test]
+    fn seeded_generators_are_reproducible() {
+
+        assert_eq!(edges(&random_gnp(30, 0.2, 7)), edges(&random_gnp(30, 0.2, 7)));
+        assert_ne!(edges(&random_gnp(30, 0.2, 7)), edges(&random_gnp(30, 0.2, 8)));
+
+        let g = random_gnp(30, 0.2, 7);
+        assert_eq!(edges(&permute(&g, 3)), edges(&permute(&g, 3)));
+        assert_eq!(edges(&random_regular(20, 3, 5)), edges(&random_regular(20, 3, 5)));
+    }
+
+    #[test]
+    fn generated_graphs() {
+
+        let g = random_gnp(30, 0.2, 11);
+        assert_eq!(g.node_count(), 30);
+        for seed in 0..5 {
+            assert_eq!(GraphKey::new(&permute(&g, seed)), GraphKey::new(&g));
+        }
+
+        // Simple and 4-regular
+        let r = random_regular(16, 4, 2);
+        assert_eq!(r.edge_count(), 32);
+        assert!(r.node_indices().all(|u| r.neighbors(u).count() == 4));
+        let mut pairs = edges(&r);
+        pairs.dedup();
+        assert!(pairs.len() == 32 && pairs.iter().all(|(u, v)| u < v));
+
+        assert_eq!(random_gnp(10, 0.0, 1).edge_count(), 0);
+        assert_eq!(random_gnp(10, 1.0, 1).edge_count(), 45);
+    }
+}
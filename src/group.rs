@@ -0,0 +1,216 @@
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges};
+
+use crate::{GraphKey, LeafSelection, search_pruned};
+use crate::coloring::{Colouring, Neighbors};
+
+//
+// Permutation groups
+//
+
+/// Group of permutations of 0..n, given by a base and strong generating set
+/// built with the Schreier–Sims algorithm.
+///
+/// A permutation p maps u to p[u].
+#[derive(Debug, Clone)]
+pub struct PermGroup {
+    n : usize,
+    levels : Vec<Level>,
+}
+
+/// Stabilizer of the base points of the previous levels.
+#[derive(Debug, Clone)]
+struct Level {
+    point : usize,
+    generators : Vec<Vec<usize>>,
+    /// transversal[u] maps point to u, for each u in the orbit of point
+    transversal : Vec<Option<Vec<usize>>>,
+}
+
+/// Permutation p followed by q.
+fn then(p : &[usize], q : &[usize]) -> Vec<usize> {
+    p.iter().map(|u| q[*u]).collect()
+}
+
+fn inverse(p : &[usize]) -> Vec<usize> {
+    let mut inverse = vec![0; p.len()];
+    p.iter().enumerate().for_each(|(u, v)| inverse[*v] = u);
+    inverse
+}
+
+impl PermGroup {
+
+    /// Build the group of permutations of 0..n generated by `generators`.
+    ///
+    /// Panics if a generator is not a permutation of 0..n.
+    pub fn new(n : usize, generators : &[Vec<usize>]) -> PermGroup {
+        let mut group = PermGroup { n, levels : vec![] };
+        for p in generators {
+            assert!(group.is_permutation(p), "generators must be permutations of 0..n");
+            group.insert(0, p.clone());
+        }
+        group
+    }
+
+    fn is_permutation(&self, p : &[usize]) -> bool {
+        let mut seen = vec![false; self.n];
+        p.len() == self.n && p.iter().all(|u| *u < self.n && !std::mem::replace(&mut seen[*u], true))
+    }
+
+    /// Divide p by the transversals of the levels from `level` down, until it
+    /// moves a base point out of its orbit. Returns what is left of p, which
+    /// is the identity iff p belongs to the stabilizer at `level`.
+    fn sift(&self, level : usize, mut p : Vec<usize>) -> Vec<usize> {
+        for l in &self.levels[level..] {
+            match &l.transversal[p[l.point]] {
+                Some(t) => p = then(&p, &inverse(t)),
+                None => break,
+            }
+        }
+        p
+    }
+
+    /// Add p, which fixes the base points of the levels above `level`, to the
+    /// generators of the stabilizer at `level`, then its Schreier generators to
+    /// the stabilizer below.
+    fn insert(&mut self, level : usize, p : Vec<usize>) {
+        if self.sift(level, p.clone()).iter().enumerate().all(|(u, v)| u == *v) {
+            return;
+        }
+
+        if level == self.levels.len() {
+            let point = (0..self.n).find(|u| p[*u] != *u).unwrap();
+            let mut transversal = vec![None; self.n];
+            transversal[point] = Some((0..self.n).collect());
+            self.levels.push(Level { point, generators : vec![], transversal });
+        }
+
+        // Extend the orbit of the base point with the new generator
+        let l = &mut self.levels[level];
+        l.generators.push(p);
+        let mut queue : Vec<usize> = (0..self.n).filter(|u| l.transversal[*u].is_some()).collect();
+        while let Some(u) = queue.pop() {
+            for s in l.generators.iter() {
+                if l.transversal[s[u]].is_none() {
+                    l.transversal[s[u]] = Some(then(l.transversal[u].as_ref().unwrap(), s));
+                    queue.push(s[u]);
+                }
+            }
+        }
+
+        // Schreier generators t_u s t_s(u)^-1 fix the base point
+        let l = self.levels[level].clone();
+        for (u, t) in l.transversal.iter().enumerate().filter_map(|(u, t)| t.as_ref().map(|t| (u, t))) {
+            for s in l.generators.iter() {
+                let schreier = then(&then(t, s), &inverse(l.transversal[s[u]].as_ref().unwrap()));
+                self.insert(level + 1, schreier);
+            }
+        }
+    }
+
+    /// Tells if `perm` belongs to the group.
+    pub fn contains(&self, perm : &[usize]) -> bool {
+        self.is_permutation(perm) && self.sift(0, perm.to_vec()).iter().enumerate().all(|(u, v)| u == *v)
+    }
+
+    /// Number of permutations in the group, the product of the orbit sizes of
+    /// the base points. Panics if it does not fit in a u128.
+    pub fn order(&self) -> u128 {
+        self.checked_order().expect("group order overflows u128")
+    }
+
+    /// Same as `order`, None if it does not fit in a u128.
+    pub fn checked_order(&self) -> Option<u128> {
+        self.levels.iter()
+            .map(|l| l.transversal.iter().filter(|t| t.is_some()).count() as u128)
+            .try_fold(1u128, |order, size| order.checked_mul(size))
+    }
+
+    /// Points the group maps v to, in increasing order.
+    pub fn orbit(&self, v : usize) -> Vec<usize> {
+        let mut in_orbit = vec![false; self.n];
+        in_orbit[v] = true;
+        let mut queue = vec![v];
+        while let Some(u) = queue.pop() {
+            for s in self.levels.iter().flat_map(|l| l.generators.iter()) {
+                if !in_orbit[s[u]] {
+                    in_orbit[s[u]] = true;
+                    queue.push(s[u]);
+                }
+            }
+        }
+        (0..self.n).filter(|u| in_orbit[*u]).collect()
+    }
+}
+
+impl GraphKey {
+
+    /// Compute the automorphism group of `g`, generated by the automorphisms
+    /// found by the search of `GraphKey::new_mckay`.
+    pub fn automorphism_group<G>(g : G) -> PermGroup
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = search_pruned(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MinDescriptor);
+        PermGroup::new(g.node_count(), &result.generators)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::PermGroup;
+    use crate::GraphKey;
+    use crate::common::{gen_test_graph, generate_random_graph, generate_permutated_graph};
+    use petgraph::graph::UnGraph;
+
+    #[test]
+    fn automorphism_groups() {
+
+        let k4 = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+        let group = GraphKey::automorphism_group(&k4);
+        assert_eq!(group.order(), 24);
+        assert!(group.contains(&[1, 0, 3, 2]));
+        assert!(group.contains(&[1, 2, 3, 0]));
+        assert!(!group.contains(&[1, 2, 3, 4]));
+        assert_eq!(group.orbit(2), vec![0, 1, 2, 3]);
+
+        // Rotations and reflections of the hexagon
+        let c6 = UnGraph::<usize, ()>::from_edges((0..6).map(|u| (u, (u + 1) % 6)));
+        let group = GraphKey::automorphism_group(&c6);
+        assert_eq!(group.order(), 12);
+        assert!(group.contains(&[1, 2, 3, 4, 5, 0]));
+        assert!(group.contains(&[0, 5, 4, 3, 2, 1]));
+        assert!(!group.contains(&[1, 0, 2, 3, 4, 5]));
+        assert_eq!(group.orbit(0), (0..6).collect::<Vec<usize>>());
+
+        // Two triangles joined by an edge 2 - 3 : 0 and 1 swap, as 4 and 5 do
+        let bowtie = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 5), (5, 3)]);
+        let group = GraphKey::automorphism_group(&bowtie);
+        assert_eq!(group.order(), 8);
+        assert_eq!((group.orbit(0), group.orbit(2)), (vec![0, 1, 4, 5], vec![2, 3]));
+
+        // Hypercube : the pruned search finds generators of all its 384 automorphisms
+        let q4 = UnGraph::<usize, ()>::from_edges((0..16u32).flat_map(|u| (0..4).map(move |b| (u, u ^ (1 << b)))).filter(|(u, v)| u < v));
+        for g in [gen_test_graph(), generate_random_graph(20, 0.2), generate_permutated_graph(&c6), q4] {
+            assert_eq!(Some(GraphKey::automorphism_group(&g).order()), GraphKey::automorphism_group_order(&g));
+        }
+    }
+
+    #[test]
+    fn groups_from_generators() {
+
+        assert_eq!(PermGroup::new(5, &[]).order(), 1);
+        assert_eq!(PermGroup::new(5, &[]).orbit(3), vec![3]);
+
+        // A transposition and a 5-cycle generate S5
+        let group = PermGroup::new(5, &[vec![1, 0, 2, 3, 4], vec![1, 2, 3, 4, 0]]);
+        assert_eq!(group.order(), 120);
+        assert!(group.contains(&[4, 3, 2, 1, 0]));
+
+        // Two 3-cycles generate A4, without the transpositions
+        let group = PermGroup::new(4, &[vec![1, 2, 0, 3], vec![0, 2, 3, 1]]);
+        assert_eq!(group.order(), 12);
+        assert!(!group.contains(&[1, 0, 2, 3]));
+        assert!(group.contains(&[1, 0, 3, 2]));
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt::Write;
+
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges};
+
+use crate::canonical_search;
+
+/// Write the canonical form of the undirected graph `g` as a dreadnaut `g`
+/// command, for instance `n=3 g\n0: 1 2;\n1: 2;\n2: .\n` for a triangle.
+///
+/// The nodes are numbered from 0 by their canonical position, so that
+/// isomorphic graphs give the same text. The list of a node only holds its
+/// neighbors from itself onwards, dreadnaut adding the reverse edges in
+/// undirected mode. Parallel edges are written once.
+pub fn to_dreadnaut<G>(g : G) -> String
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    let leaf = canonical_search(g).leaf;
+    let n = g.node_count();
+
+    // node at each canonical position
+    let mut node_at = vec![0; n];
+    for u in 0..n {
+        node_at[leaf.color_of(u)] = u;
+    }
+
+    let mut out = format!("n={} g\n", n);
+    for (i, u) in node_at.iter().enumerate() {
+        let mut row : Vec<usize> = g.neighbors(g.from_index(*u)).map(|v| leaf.color_of(g.to_index(v))).filter(|j| *j >= i).collect();
+        row.sort();
+        row.dedup();
+
+        write!(out, "{}:", i).unwrap();
+        row.iter().for_each(|j| write!(out, " {}", j).unwrap());
+        out.push_str(if i + 1 == n { " .\n" } else { ";\n" });
+    }
+    if n == 0 {
+        out.push_str(".\n");
+    }
+
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::to_dreadnaut;
+    use crate::common::{gen_test_graph, generate_permutated_graph};
+    use petgraph::graph::UnGraph;
+
+    #[test]
+    fn dreadnaut_format() {
+
+        let triangle = UnGraph::<usize, ()>::from_edges([(2, 0), (0, 1), (1, 2)]);
+        assert_eq!(to_dreadnaut(&triangle), "n=3 g\n0: 1 2;\n1: 2;\n2: .\n");
+
+        // Path with a doubled edge and a loop on an end
+        let path = UnGraph::<usize, ()>::from_edges([(1, 0), (1, 0), (1, 2), (2, 2)]);
+        assert_eq!(to_dreadnaut(&path), "n=3 g\n0: 0 2;\n1: 2;\n2: .\n");
+
+        let g = gen_test_graph();
+        assert_eq!(to_dreadnaut(&generate_permutated_graph(&g)), to_dreadnaut(&g));
+    }
+}
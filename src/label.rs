@@ -0,0 +1,172 @@
+//
+// Label trait
+//
+
+/// Node label whose value is written into the keys, see
+/// `GraphKey::new_with_labels`.
+///
+/// Labels are ordered with `Ord` to sort the initial cells, and the bytes of
+/// each distinct label are stored in the key, so that relabelling every node
+/// gives another key. Distinct labels must have distinct bytes, none of them a
+/// prefix of another, so that tuples can be encoded by concatenation. The
+/// bytes must not depend on the target.
+///
+/// ```
+/// use graphkey::{GraphKey, Label};
+/// use petgraph::graph::UnGraph;
+///
+/// #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// enum Atom { C, N, O }
+///
+/// impl Label for Atom {
+///     fn write_bytes(&self, bytes : &mut Vec<u8>) {
+///         bytes.push(*self as u8);
+///     }
+/// }
+///
+/// let chain = UnGraph::<(), ()>::from_edges([(0, 1), (1, 2)]);
+/// let ccn = GraphKey::new_with_labels(&chain, &[Atom::C, Atom::C, Atom::N]);
+/// assert_ne!(GraphKey::new_with_labels(&chain, &[Atom::C, Atom::C, Atom::O]), ccn);
+/// ```
+pub trait Label : Ord {
+    fn write_bytes(&self, bytes : &mut Vec<u8>);
+}
+
+macro_rules! impl_label_le_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl Label for $t {
+                fn write_bytes(&self, bytes : &mut Vec<u8>) {
+                    bytes.extend(self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_label_le_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// The width of usize and isize does not reach the keys
+
+impl Label for usize {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        (*self as u64).write_bytes(bytes);
+    }
+}
+
+impl Label for isize {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        (*self as i64).write_bytes(bytes);
+    }
+}
+
+impl Label for bool {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        bytes.push(u8::from(*self));
+    }
+}
+
+impl Label for char {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        u32::from(*self).write_bytes(bytes);
+    }
+}
+
+impl Label for () {
+    fn write_bytes(&self, _bytes : &mut Vec<u8>) {}
+}
+
+/// Strings are prefixed by their length in bytes.
+impl Label for str {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        self.len().write_bytes(bytes);
+        bytes.extend(self.as_bytes());
+    }
+}
+
+impl Label for String {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        self.as_str().write_bytes(bytes);
+    }
+}
+
+impl<T : Label + ?Sized> Label for &T {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        (**self).write_bytes(bytes);
+    }
+}
+
+impl<T : Label + ?Sized> Label for Box<T> {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        (**self).write_bytes(bytes);
+    }
+}
+
+impl<T : Label> Label for Option<T> {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        match self {
+            None => bytes.push(0),
+            Some(label) => {
+                bytes.push(1);
+                label.write_bytes(bytes);
+            }
+        }
+    }
+}
+
+/// Sequences are prefixed by their length.
+impl<T : Label> Label for [T] {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        self.len().write_bytes(bytes);
+        self.iter().for_each(|label| label.write_bytes(bytes));
+    }
+}
+
+impl<T : Label> Label for Vec<T> {
+    fn write_bytes(&self, bytes : &mut Vec<u8>) {
+        self.as_slice().write_bytes(bytes);
+    }
+}
+
+macro_rules! impl_label_tuple {
+    ($($name:ident),*) => {
+        impl<$($name : Label),*> Label for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn write_bytes(&self, bytes : &mut Vec<u8>) {
+                let ($($name,)*) = self;
+                $($name.write_bytes(bytes);)*
+            }
+        }
+    };
+}
+
+impl_label_tuple!(A, B);
+impl_label_tuple!(A, B, C);
+impl_label_tuple!(A, B, C, D);
+
+
+#[cfg(test)]
+mod tests {
+    use super::Label;
+
+    fn bytes<L : Label + ?Sized>(label : &L) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        label.write_bytes(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn label_bytes() {
+
+        assert_eq!(bytes(&258u16), vec![2, 1]);
+        assert_eq!(bytes(&3usize), bytes(&3u64));
+        assert_eq!(bytes(&-1isize), vec![0xff; 8]);
+        assert_eq!(bytes(&(true, 'a')), vec![1, 97, 0, 0, 0]);
+
+        // Prefixes keep the concatenations apart
+        assert_ne!(bytes(&("ab", "c")), bytes(&("a", "bc")));
+        assert_ne!(bytes(&(vec![1u8], vec![2u8, 3])), bytes(&(vec![1u8, 2], vec![3u8])));
+        assert_ne!(bytes(&Some(())), bytes(&None::<()>));
+        assert_eq!(bytes("ab"), bytes(&String::from("ab")));
+    }
+}
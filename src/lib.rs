@@ -1,350 +1,4054 @@
-use petgraph::visit::{NodeCompactIndexable, IntoNeighbors, IntoEdges};
-use crate::coloring::{Colouring, Kdim};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use petgraph::{EdgeType, Graph, Undirected};
+use petgraph::graph::IndexType;
+use petgraph::graphmap::{GraphMap, NodeTrait};
+use petgraph::algo::is_isomorphic;
+use petgraph::visit::{EdgeRef, NodeCompactIndexable, IntoNeighbors, IntoEdges};
+use petgraph::visit::{EdgeCount, GetAdjacencyMatrix, GraphProp, IntoNeighborsDirected, IntoNodeReferences, NodeRef};
+use rand::Rng;
+use crate::canonical::maps_edges;
+use crate::coloring::{color_add, color_mul, Colouring, Kdim, Neighbors, RefineGraph, RefineScratch, WorklistOrder, quantize};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 pub mod coloring;
+mod approx;
+mod builder;
+mod canonical;
+mod certificate;
+mod derived;
+mod directed;
+pub mod generators;
+mod group;
+mod label;
+pub mod io;
+mod provider;
+mod tree;
+mod wl;
+
+// The graph generators of the tests, shared with the integration tests, name
+// the crate as graphkey
+#[cfg(test)]
+extern crate self as graphkey;
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+pub use crate::approx::ApproxKey;
+pub use crate::builder::GraphKeyBuilder;
+pub use crate::canonical::{CompareResult, KeyDiff, U128_MAX_NODES, certified_compare, diff_keys};
+pub use crate::certificate::Certificate;
+pub use crate::directed::{HasCycle, NotATournament};
+pub use crate::group::PermGroup;
+pub use crate::label::Label;
+pub use crate::provider::NeighborProvider;
+pub use crate::tree::{TreeKey, NotATree};
+pub use crate::wl::WlColoring;
+
+
+//
+// GraphKey object
+//
+
+/// First value of the keys computed from a coloured graph. Unlabelled keys start
+/// with the node count, which cannot reach it.
+const LABELLED_MARKER : usize = usize::MAX;
+
+/// End of each row of a descriptor. Deltas between neighbors are smaller than
+/// the node count, the separator is above every value a descriptor can hold.
+pub(crate) const SEPARATOR : usize = usize::MAX;
+
+/// Canonical key of a graph.
+///
+/// The type parameter records the kind of graph the key was computed for
+/// (`Undirected` by default), so that keys of different kinds cannot be
+/// compared, see `tests/ui/mixed_kinds.rs`.
+///
+/// The descriptor of an undirected graph with n > 0 nodes and m edges holds
+/// n + m values: the node count, one value per edge and n - 1 separators.
+/// Labelled keys add 2 values, plus 2 per initial cell and one per byte of
+/// its label.
+#[derive(Debug)]
+pub struct GraphKey<Ty = Undirected>(Vec<usize>, PhantomData<Ty>);
+
+// The trait implementations are written by hand: derive would require them on
+// the marker type too, which petgraph does not provide.
+
+impl<Ty> Clone for GraphKey<Ty> {
+    fn clone(&self) -> GraphKey<Ty> {
+        GraphKey::from_descriptor(self.0.clone())
+    }
+}
+
+impl<Ty> PartialEq for GraphKey<Ty> {
+    fn eq(&self, other : &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<Ty> Eq for GraphKey<Ty> {}
+
+impl<Ty> Hash for GraphKey<Ty> {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.0.hash(state);
+    }
+}
+
+/// Keys are serialized as a sequence of `u64`, the same on every target, the
+/// labelled marker and the row separators becoming `u64::MAX`.
+#[cfg(feature = "serde")]
+impl<Ty> Serialize for GraphKey<Ty> {
+    fn serialize<S : serde::Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|v| to_portable(*v as u64, LABELLED_MARKER as u64)))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, Ty> Deserialize<'de> for GraphKey<Ty> {
+    fn deserialize<D : serde::Deserializer<'de>>(deserializer : D) -> Result<GraphKey<Ty>, D::Error> {
+        let values = Vec::<u64>::deserialize(deserializer)?;
+        let descriptor : Option<Vec<usize>> = values.into_iter().map(from_portable).collect();
+        descriptor.map(GraphKey::from_descriptor).ok_or_else(|| serde::de::Error::custom("descriptor value does not fit in usize"))
+    }
+}
+
+/// Value of a descriptor written in 64 bits, for a target whose labelled keys
+/// start with `marker`: the marker, which is also the row separator, becomes
+/// `u64::MAX` whatever its width.
+fn to_portable(value : u64, marker : u64) -> u64 {
+    if value == marker { u64::MAX } else { value }
+}
+
+/// Value of a descriptor read from its 64-bit form, None if it does not fit
+/// in a usize.
+fn from_portable(value : u64) -> Option<usize> {
+    if value == u64::MAX { Some(LABELLED_MARKER) } else { usize::try_from(value).ok().filter(|v| *v != LABELLED_MARKER) }
+}
+
+/// 64-bit FNV-1a hash of bytes, the same on every target and with every
+/// version of Rust.
+pub(crate) fn fnv1a<I>(bytes : I) -> u64
+where
+    I : IntoIterator<Item = u8>
+{
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Append the LEB128 encoding of value to bytes: 7 bits per byte, lowest
+/// first, the high bit set on every byte but the last.
+fn write_varint(bytes : &mut Vec<u8>, mut value : u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Read a value written by `write_varint` at the start of bytes. Returns the
+/// value and the number of bytes it takes.
+fn read_varint(bytes : &[u8]) -> Result<(u64, usize), ParseError> {
+    let mut value = 0;
+    for (i, b) in bytes.iter().enumerate() {
+        // The tenth byte only holds the highest bit of a u64
+        if i == 9 && *b > 1 {
+            return Err(ParseError::Malformed);
+        }
+        value |= ((b & 0x7f) as u64) << (7 * i);
+
+        if b & 0x80 == 0 {
+            // Each value has a single encoding, without trailing zero bytes
+            if i > 0 && *b == 0 {
+                return Err(ParseError::Malformed);
+            }
+            return Ok((value, i + 1));
+        }
+    }
+    Err(ParseError::Truncated)
+}
+
+/// Keys are written as the lowercase hexadecimal digits of `to_bytes`.
+impl<Ty> fmt::Display for GraphKey<Ty> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        self.to_bytes().iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+/// Keys are read from the hexadecimal digits of `to_bytes`, in either case.
+impl<Ty : EdgeType> FromStr for GraphKey<Ty> {
+    type Err = ParseError;
+
+    fn from_str(s : &str) -> Result<GraphKey<Ty>, ParseError> {
+        if !s.len().is_multiple_of(2) || !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidHex);
+        }
+
+        let digit = |c : u8| (c as char).to_digit(16).unwrap() as u8;
+        let bytes : Vec<u8> = s.as_bytes().chunks(2).map(|pair| digit(pair[0]) << 4 | digit(pair[1])).collect();
+        GraphKey::from_bytes(&bytes)
+    }
+}
+
+impl<Ty : EdgeType> GraphKey<Ty> {
+
+    /// Decode a key encoded by `to_bytes`.
+    ///
+    /// Fails on an empty or truncated input, on a value encoded with more bytes
+    /// than needed, and on a value that does not fit in a `usize`. The values
+    /// must have the layout of a key of `GraphKey::new`, possibly preceded by
+    /// the header of a labelled key: a row per node, or per node but the last
+    /// for undirected graphs, whose neighbors are nodes of the graph, and
+    /// labelled cells holding all the nodes. Keys with another layout, such as
+    /// the ones of `GraphKey::new_with_component_prefix`, are rejected.
+    pub fn from_bytes(bytes : &[u8]) -> Result<GraphKey<Ty>, ParseError> {
+        let mut descriptor = Vec::with_capacity(bytes.len());
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let (value, len) = read_varint(rest)?;
+            descriptor.push(from_portable(value.wrapping_sub(1)).ok_or(ParseError::Malformed)?);
+            rest = &rest[len..];
+        }
+
+        check_layout(&descriptor, Ty::is_directed())?;
+        Ok(GraphKey::from_descriptor(descriptor))
+    }
+}
+
+/// Check that `descriptor` has the layout of a key, see `GraphKey::from_bytes`.
+///
+/// Fails with `ParseError::Truncated` if values are missing, and with
+/// `ParseError::Malformed` if a value is out of range or follows the end.
+fn check_layout(descriptor : &[usize], directed : bool) -> Result<(), ParseError> {
+    let mut values = descriptor.iter().copied();
+    let mut next = || values.next().ok_or(ParseError::Truncated);
+
+    let mut n = next()?;
+    if n == LABELLED_MARKER {
+        // Cells, each with its size and the bytes of its label
+        let mut size = 0usize;
+        for _ in 0..next()? {
+            let cell_size = next()?;
+            if cell_size == 0 {
+                return Err(ParseError::Malformed);
+            }
+            size = size.checked_add(cell_size).ok_or(ParseError::Malformed)?;
+            for _ in 0..next()? {
+                if next()? > u8::MAX as usize {
+                    return Err(ParseError::Malformed);
+                }
+            }
+        }
+        n = next()?;
+        if n != size {
+            return Err(ParseError::Malformed);
+        }
+    }
+    if n == SEPARATOR {
+        return Err(ParseError::Malformed);
+    }
+
+    // Directed rows hold sorted successors, undirected ones the deltas between
+    // sorted neighbors above the node
+    let rows = if directed { n } else { n.saturating_sub(1) };
+    for i in 0..rows {
+        let mut prev_neigh = if directed { 0 } else { i };
+        loop {
+            let value = next()?;
+            if value == SEPARATOR {
+                break;
+            }
+            let neigh = if directed { Some(value).filter(|v| *v >= prev_neigh) } else { prev_neigh.checked_add(value).filter(|j| *j > i) };
+            prev_neigh = neigh.filter(|j| *j < n).ok_or(ParseError::Malformed)?;
+        }
+    }
+
+    match next() {
+        Ok(_) => Err(ParseError::Malformed),
+        Err(_) => Ok(()),
+    }
+}
+
+/// The default key is the key of the empty graph.
+impl<Ty> Default for GraphKey<Ty> {
+    fn default() -> GraphKey<Ty> {
+        GraphKey::from_descriptor(vec![0])
+    }
+}
+
+impl<Ty> GraphKey<Ty> {
+    pub fn get_descriptor(&self) -> &Vec<usize> {
+        &self.0
+    }
+
+    /// Number of bytes used by the key, including its heap allocation.
+    pub fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.0.capacity() * std::mem::size_of::<usize>()
+    }
+
+    /// Encode the key as a varint per value of its descriptor.
+    ///
+    /// Each value is written plus one in LEB128, the labelled marker and the
+    /// row separators as 0, so most values take a single byte. The encoding
+    /// does not depend on the width of `usize`: a key computed on a 32-bit
+    /// target has the same bytes as on a 64-bit one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.0.len());
+        for v in self.0.iter() {
+            write_varint(&mut bytes, to_portable(*v as u64, LABELLED_MARKER as u64).wrapping_add(1));
+        }
+        bytes
+    }
+
+    /// 64-bit FNV-1a hash of `to_bytes`, the same on every target and with
+    /// every version of Rust, unlike the `Hash` implementation.
+    pub fn fingerprint(&self) -> u64 {
+        fnv1a(self.to_bytes())
+    }
+
+    fn from_descriptor(descriptor : Vec<usize>) -> GraphKey<Ty> {
+        GraphKey(descriptor, PhantomData)
+    }
+
+    /// Compute the key of `g` starting the search from the cells of nodes
+    /// sharing a label.
+    ///
+    /// A header describing the initial cells is prepended to the descriptor:
+    /// their count, then for each cell in order of label its size, the number
+    /// of bytes of its label and these bytes. The canonical labelling respects
+    /// the order of the cells, so together with the canonical graph the header
+    /// describes the labelled graph up to isomorphism. It is preceded by
+    /// `LABELLED_MARKER`, which no unlabelled key starts with.
+    fn from_labels<S, L>(g : S, labels : &[L]) -> GraphKey<Ty>
+    where
+        S : SearchGraph,
+        L : Label
+    {
+        GraphKey::try_from_labels(g, labels, &CanonConfig::default(), LeafSelection::MaxDescriptor).expect("an unlimited search cannot fail")
+    }
+
+    /// Same as `from_labels`, with the search tuned by `cfg` and `selection`.
+    fn try_from_labels<S, L>(g : S, labels : &[L], cfg : &CanonConfig, selection : LeafSelection) -> Result<GraphKey<Ty>, CanonError>
+    where
+        S : SearchGraph,
+        L : Label
+    {
+        let gc = Colouring::from_labels(labels);
+
+        // The cells are ordered as their labels
+        let mut distinct : Vec<&L> = labels.iter().collect();
+        distinct.sort();
+        distinct.dedup();
+
+        let mut key = vec![LABELLED_MARKER, distinct.len()];
+        let mut bytes = Vec::new();
+        for (label, size) in distinct.into_iter().zip(gc.get_cell_sizes()) {
+            bytes.clear();
+            label.write_bytes(&mut bytes);
+            key.push(size);
+            key.push(bytes.len());
+            key.extend(bytes.iter().map(|b| *b as usize));
+        }
+
+        let result = search_with_limits(g, gc, cfg.selector.select(), selection, cfg.order, cfg.max_refine_rounds, cfg.search_node_budget)?;
+        key.extend(result.descriptor);
+
+        Ok(GraphKey::from_descriptor(key))
+    }
+}
+
+/// Graphs whose key can be computed by value or by reference: references to
+/// graphs implementing the petgraph visitor traits, and the owned petgraph
+/// graphs whose references do. `GraphKeySet` takes its graphs this way.
+///
+/// `GraphKey::new` takes any graph implementing the visitor traits instead,
+/// such as the views of petgraph, which are passed by value.
+///
+/// ```
+/// use petgraph::graphmap::UnGraphMap;
+/// use graphkey::{GraphKey, KeyInput};
+///
+/// let g = UnGraphMap::<u8, ()>::from_edges([(1, 2), (2, 3)]);
+/// assert_eq!(GraphKey::new(&g), g.into_key());
+/// ```
+pub trait KeyInput {
+    fn into_key(self) -> GraphKey;
+}
+
+impl<'a, G : ?Sized> KeyInput for &'a G
+where
+    &'a G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    fn into_key(self) -> GraphKey {
+        GraphKey::of_graph(self)
+    }
+}
+
+impl<N, E, Ty : EdgeType, Ix : IndexType> KeyInput for Graph<N, E, Ty, Ix> {
+    fn into_key(self) -> GraphKey {
+        GraphKey::of_graph(&self)
+    }
+}
+
+impl<N : NodeTrait, E, Ty : EdgeType> KeyInput for GraphMap<N, E, Ty> {
+    fn into_key(self) -> GraphKey {
+        GraphKey::of_graph(&self)
+    }
+}
+
+impl GraphKey {
+    /// Capacity to request for a map expected to hold n distinct keys, with
+    /// some headroom so that a few extra keys do not trigger a rehash of all
+    /// the descriptors.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::collections::hash_map::RandomState;
+    /// use graphkey::GraphKey;
+    ///
+    /// let capacity = GraphKey::heuristic_capacity(1000);
+    /// let map : HashMap<GraphKey, usize> = HashMap::with_capacity_and_hasher(capacity, RandomState::new());
+    /// assert!(map.capacity() >= 1000);
+    /// ```
+    pub fn heuristic_capacity(n : usize) -> usize {
+        n.saturating_add(n / 8)
+    }
+
+    /// Compute the key of `g`, a graph given by reference or a view of one,
+    /// such as `Reversed`, given by value. See `KeyInput` for owned graphs.
+    ///
+    /// Only the structure of g is used, its weights are ignored: see
+    /// `GraphKey::structural` and `GraphKey::weighted`.
+    ///
+    /// ```
+    /// use petgraph::graph::DiGraph;
+    /// use petgraph::visit::Reversed;
+    /// use graphkey::{GraphKey, KeyInput};
+    ///
+    /// let g = DiGraph::<(), ()>::from_edges([(0, 1), (0, 2)]);
+    /// let h = DiGraph::<(), ()>::from_edges([(1, 0), (2, 0)]);
+    /// assert_eq!(GraphKey::new(Reversed(&g)), GraphKey::new(&h));
+    /// assert_eq!(g.into_key(), GraphKey::new(Reversed(&h)));
+    /// ```
+    pub fn new<G>(g : G) -> GraphKey 
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        GraphKey::of_graph(g)
+    }
+
+    /// Compute the key of `g` from its structure alone, the same as `new`.
+    ///
+    /// Node and edge weights are ignored: graphs differing only by their
+    /// weights get the same key. See `GraphKey::weighted` to take the node
+    /// weights into account.
+    pub fn structural<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        GraphKey::new(g)
+    }
+
+    /// Compute the key of `g` where each node is labelled by its weight, see
+    /// `GraphKey::new_with_labels`.
+    ///
+    /// Two graphs get the same key iff they are isomorphic through a map
+    /// preserving the node weights, whose values are written into the key.
+    /// Edge weights are ignored.
+    pub fn weighted<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges + IntoNodeReferences,
+        G::NodeWeight : Label + Clone
+    {
+        let mut weights : Vec<Option<G::NodeWeight>> = vec![None; g.node_count()];
+        for node in g.node_references() {
+            weights[g.to_index(node.id())] = Some(node.weight().clone());
+        }
+        GraphKey::new_with_labels(g, &weights)
+    }
+
+    /// Compute the key of `g` like `new`, comparing the descriptors of the
+    /// final leaves in parallel on the rayon thread pool.
+    ///
+    /// The key, and the canonical leaf, are the ones of `new`.
+    #[cfg(feature = "rayon")]
+    pub fn new_parallel<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges + Sync
+    {
+        let result = search_parallel(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor);
+        GraphKey::from_descriptor(result.descriptor)
+    }
+
+    /// Compute the key of `g` like `new`, for any graph implementing the
+    /// visitor traits.
+    fn of_graph<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        let key = GraphKey::from_descriptor(result.descriptor);
+
+        let rate = f64::from_bits(VERIFICATION_SAMPLE_RATE.load(AtomicOrdering::Relaxed));
+        if rate > 0.0 && rand::thread_rng().gen_bool(rate) {
+            verify_key(g, &key);
+        }
+        key
+    }
+
+    /// Compute the key of `g` together with the depth of the canonical leaf in
+    /// the search tree, i.e. the number of individualizations leading to it.
+    ///
+    /// The depth is 0 when refining the uniform colouring is enough to make it
+    /// discrete.
+    pub fn new_with_depth<G>(g : G) -> (GraphKey, usize)
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        (GraphKey::from_descriptor(result.descriptor), result.depth)
+    }
+
+    /// Compute the key of `g`, prefixed by the sizes of its connected components.
+    ///
+    /// The descriptor starts with the number of components followed by their
+    /// sizes in decreasing order, so keys can be bucketed by component structure
+    /// without decoding the rest. These keys must not be compared with keys
+    /// computed without the prefix.
+    pub fn new_with_component_prefix<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let sizes = component_sizes(g);
+        let result = canonical_search(g);
+
+        let mut key = Vec::with_capacity(1 + sizes.len() + result.descriptor.len());
+        key.push(sizes.len());
+        key.extend(sizes);
+        key.extend(result.descriptor);
+
+        GraphKey::from_descriptor(key)
+    }
+
+    /// Compute the key of `g`, prefixed by the order of its automorphism group.
+    ///
+    /// The order is counted by the search computing the key, at no extra cost.
+    /// Isomorphic graphs always share the prefix, so keys can be bucketed by
+    /// group order without decoding the rest. These keys must not be compared
+    /// with keys computed without the prefix.
+    ///
+    /// The search enumerates a leaf per automorphism, which is too expensive on
+    /// very symmetric graphs: returns None if it creates more tree nodes than
+    /// `budget`.
+    pub fn new_with_group_order_prefix<G>(g : G, budget : usize) -> Option<GraphKey>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = search_with_limits(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor, SearchOrder::Bfs, None, Some(budget)).ok()?;
+
+        let mut key = Vec::with_capacity(1 + result.descriptor.len());
+        key.push(result.group_order);
+        key.extend(result.descriptor);
+
+        Some(GraphKey::from_descriptor(key))
+    }
+
+    /// Compute the key of `g`, choosing the canonical leaf with `selection`.
+    ///
+    /// `GraphKey::new` uses `LeafSelection::MaxDescriptor`.
+    pub fn new_with_selection<G>(g : G, selection : LeafSelection) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = search(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, selection);
+        GraphKey::from_descriptor(result.descriptor)
+    }
+
+    /// Compute the key of `g` like `GraphKey::new`, exploring the search tree
+    /// depth-first.
+    ///
+    /// The breadth-first search of `GraphKey::new` keeps a colouring per node of
+    /// the current level, which grows with the automorphism group: on a cycle
+    /// of 1000 nodes its peak heap usage is about 410 MB, against 0.6 MB here,
+    /// for the same running time.
+    /// The depth-first search keeps a single colouring, at the cost of
+    /// exploring subtrees that the breadth-first one would have pruned.
+    pub fn new_depth_first<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = search_depth_first(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor);
+        GraphKey::from_descriptor(result.descriptor)
+    }
+
+    /// Compute the key of `g` from the leaf whose descriptor is the greatest for
+    /// `cmp`, where `GraphKey::new` takes the lexicographic order.
+    ///
+    /// Any total order on the descriptors gives a canonical form, e.g. the
+    /// order of another tool, as long as all the keys compared are computed
+    /// with the same one. cmp must only return `Ordering::Equal` for equal
+    /// descriptors.
+    pub fn new_with_comparator<G, C>(g : G, cmp : C) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        C : Fn(&[usize], &[usize]) -> Ordering
+    {
+        let explored = explore(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, None, None).expect("an unlimited search cannot fail");
+        GraphKey::from_descriptor(select_leaf_by(Neighbors(g), explored, LeafSelection::MaxDescriptor, |a, b| cmp(&a.descriptor, &b.descriptor)).descriptor)
+    }
+
+    /// Compute the key of `g` from the minimal descriptor, the same as
+    /// `GraphKey::new_with_selection(g, LeafSelection::MinDescriptor)`.
+    pub fn new_min<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        GraphKey::new_with_selection(g, LeafSelection::MinDescriptor)
+    }
+
+    /// Compute the key of `g` like `GraphKey::new_min`, with a depth-first
+    /// search pruning the branches that a known automorphism maps onto an
+    /// explored one.
+    ///
+    /// Each pair of leaves reaching the best descriptor gives an automorphism of
+    /// g, as in McKay's method. On graphs with many automorphisms, most of the
+    /// tree is skipped once a few of them are known.
+    pub fn new_mckay<G>(g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        GraphKey::new_with_generator_count(g).0
+    }
+
+    /// Compute the key of `g` like `GraphKey::new_mckay`, along with the number
+    /// of automorphism generators the search collected.
+    ///
+    /// This gauges the symmetry of g without computing its group: 0 means that
+    /// no two leaves reached the best descriptor, so g is most likely
+    /// asymmetric. The generators may be redundant, and their number depends on
+    /// the order in which the tree is explored.
+    pub fn new_with_generator_count<G>(g : G) -> (GraphKey, usize)
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = search_pruned(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MinDescriptor);
+        (GraphKey::from_descriptor(result.descriptor), result.generators.len())
+    }
+
+    /// Compute the key of `g` with the search tuned by `cfg`.
+    ///
+    /// With `CanonConfig::default()` this is `GraphKey::new`. Capping the
+    /// refinement rounds or picking another selector still gives canonical keys,
+    /// but they must only be compared with keys computed with the same settings.
+    /// Returns `CanonError::BudgetExceeded` if the search creates more tree nodes
+    /// than `cfg.search_node_budget`.
+    pub fn new_with_config<G>(g : G, cfg : &CanonConfig) -> Result<GraphKey, CanonError>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = search_with_limits(Neighbors(g), Colouring::new(g), cfg.selector.select(), LeafSelection::MaxDescriptor, cfg.order, cfg.max_refine_rounds, cfg.search_node_budget)?;
+        Ok(GraphKey::from_descriptor(result.descriptor))
+    }
+
+    /// Compute the key of `g` where the node n carries the label labels[n].
+    ///
+    /// Two graphs get the same key iff they are isomorphic through a map
+    /// preserving the labels: the values of the labels are written into the
+    /// key, see `Label`. Labelled keys never equal unlabelled ones, even when
+    /// all the nodes carry the same label.
+    pub fn new_with_labels<G, L>(g : G, labels : &[L]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        L : Label
+    {
+        GraphKey::from_labels(Neighbors(g), labels)
+    }
+
+    /// Compute the key of `g` where the node of index u starts in the colour
+    /// class initial(u), e.g. the atom type of a molecule graph.
+    ///
+    /// Same as `GraphKey::new_with_labels`: the initial cells are the classes,
+    /// ordered by value, and the refinement and the search start from them.
+    /// The colour values are written into the key, not only their order.
+    pub fn new_with_colors<G, F>(g : G, initial : F) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        F : Fn(usize) -> u64
+    {
+        let colors : Vec<u64> = (0..g.node_count()).map(initial).collect();
+        GraphKey::new_with_labels(g, &colors)
+    }
+
+    /// Compute the key of `g` where the node n carries the bucket
+    /// `(f(n) / eps).round()` of its weight, see `Colouring::new_quantized`.
+    ///
+    /// The bucket values are written into the key: weights within noise of each
+    /// other share a key, while shifting every weight by eps or more gives
+    /// another one. Panics if eps is not positive.
+    pub fn new_quantized<G, F>(g : G, f : F, eps : f64) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        F : Fn(usize) -> f64
+    {
+        GraphKey::new_with_labels(g, &quantize(g.node_count(), f, eps))
+    }
+
+    /// Compute the key of `g` where each node carries the kind `kind(node)`.
+    ///
+    /// Same as `GraphKey::new_with_labels`, with the kinds given by node id.
+    /// Kinds need an order, which an enum can derive, and a `Label` encoding,
+    /// which writes them into the key:
+    ///
+    /// ```
+    /// use graphkey::{GraphKey, Label};
+    /// use petgraph::graph::UnGraph;
+    ///
+    /// #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    /// enum Kind { Call, Ident, Literal }
+    ///
+    /// impl Label for Kind {
+    ///     fn write_bytes(&self, bytes : &mut Vec<u8>) {
+    ///         bytes.push(*self as u8);
+    ///     }
+    /// }
+    ///
+    /// // f(x, 1) and its children listed in another order
+    /// let mut g = UnGraph::<Kind, ()>::new_undirected();
+    /// let (f, x, one) = (g.add_node(Kind::Call), g.add_node(Kind::Ident), g.add_node(Kind::Literal));
+    /// g.add_edge(f, x, ());
+    /// g.add_edge(f, one, ());
+    ///
+    /// let mut h = UnGraph::<Kind, ()>::new_undirected();
+    /// let (one, x, f) = (h.add_node(Kind::Literal), h.add_node(Kind::Ident), h.add_node(Kind::Call));
+    /// h.add_edge(f, one, ());
+    /// h.add_edge(f, x, ());
+    ///
+    /// assert_eq!(GraphKey::with_node_kind(&g, |u| g[u]), GraphKey::with_node_kind(&h, |u| h[u]));
+    /// ```
+    pub fn with_node_kind<G, F, K>(g : G, kind : F) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        F : Fn(G::NodeId) -> K,
+        K : Label
+    {
+        let kinds : Vec<K> = (0..g.node_count()).map(|u| kind(g.from_index(u))).collect();
+        GraphKey::new_with_labels(g, &kinds)
+    }
+
+    /// Compute the key of the compressed graph `g`, where the node n stands for
+    /// count(n) identical vertices.
+    ///
+    /// The counts are node labels, whose values are written into the key:
+    /// multiplying every count by 2 gives another key. Two compressed graphs
+    /// get the same key iff they are isomorphic through a map preserving the
+    /// counts, which for compressions merging the same kind of identical
+    /// vertices means that their expansions are isomorphic.
+    pub fn with_vertex_counts<G, F>(g : G, count : F) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+        F : Fn(G::NodeId) -> u64
+    {
+        let counts : Vec<u64> = (0..g.node_count()).map(|u| count(g.from_index(u))).collect();
+        GraphKey::new_with_labels(g, &counts)
+    }
+
+    /// Compute the key of `g` where the node n belongs to the class class_of[n].
+    ///
+    /// Classes are fixed: two graphs get the same key iff they are isomorphic
+    /// through a map sending each node to a node of the same class, with the
+    /// same class ids.
+    pub fn new_with_classes<G>(g : G, class_of : &[usize]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        assert_eq!(class_of.len(), g.node_count(), "class_of must have one entry per node");
+        GraphKey::new_with_labels(g, class_of)
+    }
+
+    /// Compute the key of `g` where the nodes listed in `wildcards` are
+    /// interchangeable with each other but never with the remaining nodes.
+    ///
+    /// Two graphs get the same key iff they are isomorphic through a map
+    /// sending wildcards to wildcards. The key tells which cell holds the
+    /// wildcards, so that making every node a wildcard differs from making none.
+    pub fn new_with_wildcards<G>(g : G, wildcards : &[usize]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let mut is_wildcard = vec![false; g.node_count()];
+        for u in wildcards {
+            is_wildcard[*u] = true;
+        }
+
+        GraphKey::from_labels(Neighbors(g), &is_wildcard)
+    }
+
+    /// Compute the key of `g` where the nodes of `marked`, e.g. a clique or an
+    /// independent set, are distinguished as a set.
+    ///
+    /// Same as `GraphKey::new_with_wildcards`: the marked nodes start in one
+    /// cell and the others in another, so two graphs get the same key iff they
+    /// are isomorphic through a map sending the marked set onto the marked set.
+    pub fn new_with_marked_set<G>(g : G, marked : &[usize]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        GraphKey::new_with_wildcards(g, marked)
+    }
+
+    /// Compute the key of `g` where the node `root` is distinguished.
+    ///
+    /// Two graphs get the same key iff they are isomorphic through a map
+    /// sending root to root.
+    pub fn new_rooted<G>(g : G, root : usize) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let is_other : Vec<bool> = (0..g.node_count()).map(|u| u != root).collect();
+        GraphKey::from_labels(Neighbors(g), &is_other)
+    }
+
+    /// Compute the key of `g` where the edge between u and v is distinguished,
+    /// with u distinguished from v if `ordered`.
+    ///
+    /// Two graphs get the same key iff they are isomorphic through a map
+    /// sending the marked edge to the marked edge, and u to u if `ordered`.
+    /// Fails if u or v is not a node of `g`, or if they are not adjacent.
+    pub fn new_edge_rooted<G>(g : G, u : usize, v : usize, ordered : bool) -> Result<GraphKey, EdgeError>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let node_count = g.node_count();
+        if let Some(node) = [u, v].into_iter().find(|w| *w >= node_count) {
+            return Err(EdgeError::NodeOutOfRange { node, node_count });
+        }
+        if !g.neighbors(g.from_index(u)).any(|w| g.to_index(w) == v) {
+            return Err(EdgeError::NotAnEdge { u, v });
+        }
+
+        let labels : Vec<usize> = (0..g.node_count()).map(|w| match w {
+            _ if w == u => 0,
+            _ if w == v => usize::from(ordered),
+            _ => 2,
+        }).collect();
+        Ok(GraphKey::from_labels(Neighbors(g), &labels))
+    }
+
+    /// Canonicalize `g` and feed its descriptor into `state`, without building
+    /// the intermediate `GraphKey`.
+    ///
+    /// The hasher receives exactly what `GraphKey::new(g).hash(state)` would write.
+    pub fn canonical_hash_into<H, G>(g : G, state : &mut H)
+    where
+        H : Hasher,
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        result.descriptor.hash(state);
+    }
+
+    /// Check whether refining the uniform colouring of `g` splits no cell, in
+    /// which case only the search can tell its nodes apart.
+    ///
+    /// This holds for regular graphs, and only for them: the first refinement
+    /// splits the nodes by degree.
+    pub fn is_refinement_trivial<G>(g : G) -> bool
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        Colouring::new(g).refine_report(g).splits == 0
+    }
+
+    /// Check whether refining the uniform colouring of `g` separates all its
+    /// nodes, in which case the key is computed without any backtracking.
+    pub fn refine_is_discrete<G>(g : G) -> bool
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let mut gc = Colouring::new(g);
+        gc.refine(g);
+        gc.is_discrete()
+    }
+
+    /// Compute the order of the automorphism group of `g`.
+    ///
+    /// The search of `GraphKey::automorphism_group` prunes the branches mapped
+    /// to each other by the automorphisms it finds, which generate Aut(g); the
+    /// order is the product of the orbit sizes of their stabilizer chain, so
+    /// the leaves are not enumerated. Returns `None` if the order does not fit
+    /// in a `u128`.
+    pub fn automorphism_group_order<G>(g : G) -> Option<u128>
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        GraphKey::automorphism_group(g).checked_order()
+    }
+
+    /// Compute the key of `g` together with the canonical graph it describes.
+    ///
+    /// The canonical graph is isomorphic to `g` and its nodes are labelled by
+    /// their canonical position, so `GraphKey::new(&record.graph) == record.key`.
+    pub fn to_record<G>(g : G) -> CanonicalRecord
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let result = canonical_search(g);
+        CanonicalRecord {
+            key : GraphKey::from_descriptor(result.descriptor),
+            graph : result.leaf.compute_graph_from_discrete(g),
+        }
+    }
+
+    /// Tells if `candidate` is isomorphic to the graph whose canonical graph is
+    /// `stored_canonical`, as given by `GraphKey::to_record`.
+    ///
+    /// Only the candidate is searched: its canonical graph is compared with the
+    /// stored one as a labelled graph, which is only meaningful if the stored
+    /// graph is canonical.
+    pub fn canonical_equals<G>(stored_canonical : &Graph<usize, (), Undirected>, candidate : G) -> bool
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        stored_canonical.node_count() == candidate.node_count()
+            && compute_descriptor(stored_canonical) == canonical_search(candidate).descriptor
+    }
+
+    /// Tells if `h` has this key, where the key was computed by `GraphKey::new`.
+    ///
+    /// The key is the descriptor of the graph relabelled by a leaf of the
+    /// search tree, which the tree of h holds iff h has the key. The tree of h
+    /// is explored depth first until a leaf has this descriptor, and a branch
+    /// is pruned as soon as its colouring cannot reach it: every leaf below it
+    /// puts the nodes of a cell at the positions of that cell, so their
+    /// neighbors in each cell must match the rows of the key at these
+    /// positions. Nothing is searched if the node counts differ.
+    ///
+    /// Self-loops and directed edges, which descriptors do not fully describe,
+    /// fall back to computing the key of h.
+    pub fn equals_graph_with_search<H>(&self, h : H) -> bool
+    where
+        H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        self.match_search(h).0
+    }
+
+    /// Outcome of `equals_graph_with_search`, with the number of tree nodes
+    /// explored.
+    fn match_search<H>(&self, h : H) -> (bool, usize)
+    where
+        H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let n = h.node_count();
+        if self.0.first() != Some(&n) {
+            return (false, 0);
+        }
+        let Some(rows) = descriptor_adjacency(&self.0) else {
+            return (false, 0);
+        };
+
+        // Adjacency of h as its descriptors see it : undirected, without loops
+        let mut adjacency = vec![Vec::new(); n];
+        for e in h.edge_references() {
+            let (u, v) = (h.to_index(e.source()), h.to_index(e.target()));
+            if u != v {
+                adjacency[u].push(v);
+                adjacency[v].push(u);
+            }
+        }
+        let faithful = (0..n).all(|u| {
+            let mut neigh : Vec<usize> = h.neighbors(h.from_index(u)).map(|v| h.to_index(v)).collect();
+            neigh.sort();
+            adjacency[u].sort();
+            neigh == adjacency[u]
+        });
+        if !faithful {
+            let result = canonical_search(h);
+            return (result.descriptor == self.0, result.explored);
+        }
+
+        let mut scratch = RefineScratch::default();
+        let mut gc = Colouring::new(h);
+        gc.refine_into(Neighbors(h), WorklistOrder::Heap, &mut scratch);
+
+        let mut explored = 0;
+        let mut stack = vec![gc];
+        while let Some(gc) = stack.pop() {
+            if !may_reach_rows(&gc, &adjacency, &rows) {
+                continue;
+            }
+            if gc.is_discrete() {
+                // Every row matches its node : the leaf has the descriptor
+                return (true, explored);
+            }
+
+            let target = Colouring::select_cell_smallest_first(&gc);
+            let mut members = gc.get_cell_members(target);
+            members.sort_by(|a, b| b.cmp(a));
+            for u in members {
+                let mut child = gc.clone();
+                child.individualize_and_refine_into(target, u, Neighbors(h), &mut scratch);
+                explored += 1;
+                stack.push(child);
+            }
+        }
+
+        (false, explored)
+    }
+}
+
+
+/// Partition `graphs` into isomorphism classes.
+///
+/// Returns a class id per input graph such that two graphs share an id iff
+/// they are isomorphic. Ids are assigned in order of first appearance.
+pub fn isomorphism_classes<G>(graphs : &[G]) -> Vec<usize>
+where
+    for<'a> &'a G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    let mut class_ids : HashMap<GraphKey, usize> = HashMap::new();
+
+    graphs
+        .iter()
+        .map(|g| {
+            let next_id = class_ids.len();
+            *class_ids.entry(GraphKey::new(g)).or_insert(next_id)
+        })
+        .collect()
+}
+
+
+/// Deduplicate `graphs` up to isomorphism, verifying every key match.
+///
+/// Returns the indices of the first graph of each isomorphism class, in order
+/// of appearance. Whenever two graphs share a key, their isomorphism is
+/// confirmed with `petgraph::algo::is_isomorphic`; a key match between
+/// non-isomorphic graphs is reported as a `CollisionError` instead of being
+/// trusted.
+pub fn dedup_verified<G>(graphs : &[G]) -> Result<Vec<usize>, CollisionError>
+where
+    for<'a> &'a G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+        + EdgeCount + GetAdjacencyMatrix + GraphProp + IntoNeighborsDirected
+{
+    let mut representatives : HashMap<GraphKey, usize> = HashMap::new();
+    let mut kept = Vec::new();
+
+    for (i, g) in graphs.iter().enumerate() {
+        match representatives.entry(GraphKey::new(g)) {
+            Entry::Occupied(e) => {
+                let first = *e.get();
+                if !is_isomorphic(&graphs[first], g) {
+                    return Err(CollisionError { first, second : i });
+                }
+            }
+            Entry::Vacant(e) => {
+                e.insert(i);
+                kept.push(i);
+            }
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Check the keys of `graphs` against `petgraph::algo::is_isomorphic`.
+///
+/// For every pair of graphs, key equality must agree with isomorphism. Returns
+/// the indices of the first pair where they disagree.
+pub fn conformance_check<G>(graphs : &[G]) -> Result<(), (usize, usize)>
+where
+    for<'a> &'a G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+        + EdgeCount + GetAdjacencyMatrix + GraphProp + IntoNeighborsDirected
+{
+    let keys : Vec<GraphKey> = graphs.iter().map(GraphKey::new).collect();
+
+    for i in 0..graphs.len() {
+        for j in (i + 1)..graphs.len() {
+            if (keys[i] == keys[j]) != is_isomorphic(&graphs[i], &graphs[j]) {
+                return Err((i, j));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Tells if `g` and `h` are isomorphic, computing their keys only if cheaper
+/// invariants do not tell them apart.
+///
+/// The `GraphPrefilter` of the graphs are compared first, then the colourings
+/// refined from the uniform colouring. Only graphs agreeing on all of them are
+/// searched: the key of g is computed, then the leaves of the search of h are
+/// described one value at a time and compared with it, each description
+/// stopping at the first value that differs. The key of h is never built, and
+/// the graphs are told apart as soon as a leaf of h beats the key of g.
+pub fn are_isomorphic_fast<G, H>(g : G, h : H) -> bool
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+    H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    compare_graphs(g, h).0
+}
+
+/// Check telling two graphs apart in `are_isomorphic_fast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Check {
+    Counts,
+    Degrees,
+    Refinement,
+    Search,
+}
+
+/// Outcome of `are_isomorphic_fast`, with the last check it ran.
+fn compare_graphs<G, H>(g : G, h : H) -> (bool, Check)
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges,
+    H : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    let (prefilter_g, prefilter_h) = (GraphPrefilter::new(g), GraphPrefilter::new(h));
+    if !prefilter_g.could_match(&prefilter_h) {
+        let same_counts = (prefilter_g.node_count, prefilter_g.edge_count) == (prefilter_h.node_count, prefilter_h.edge_count);
+        return (false, if same_counts { Check::Degrees } else { Check::Counts });
+    }
+
+    // The trace and the cell sizes of the refined colouring are invariants
+    let (mut gc, mut hc) = (Colouring::new(g), Colouring::new(h));
+    if gc.refine(g) != hc.refine(h) || gc.get_cell_sizes() != hc.get_cell_sizes() {
+        return (false, Check::Refinement);
+    }
+
+    // h is isomorphic to g iff its greatest leaf has the descriptor of g
+    let descriptor = search(Neighbors(g), gc, Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor).descriptor;
+    let explored = explore(Neighbors(h), hc, Colouring::select_cell_smallest_first, None, None).expect("an unlimited search cannot fail");
+    let mut same = false;
+    for leaf in explored.leaves.iter() {
+        match compare_description(Neighbors(h), leaf, &descriptor).0 {
+            Ordering::Greater => return (false, Check::Search),
+            Ordering::Equal => same = true,
+            Ordering::Less => {}
+        }
+    }
+    (same, Check::Search)
+}
+
+/// Compare the descriptor of the undirected graph `g` relabelled by the
+/// discrete colouring `leaf` with `descriptor`, producing it one row at a time
+/// and stopping at the first value that differs. Also returns the number of
+/// values produced.
+fn compare_description<N>(g : N, leaf : &Colouring, descriptor : &[usize]) -> (Ordering, usize)
+where
+    N : RefineGraph
+{
+    let n = leaf.size();
+    let mut node_at = vec![0; n];
+    (0..n).for_each(|u| node_at[leaf.color_of(u)] = u);
+
+    // Same values as descriptor_from_rows
+    let rows = (0..n.saturating_sub(1)).flat_map(|i| {
+        let mut row = Vec::new();
+        g.for_each_neighbor(node_at[i], |v, _| {
+            let j = leaf.color_of(v);
+            if j > i {
+                row.push(j);
+            }
+        });
+        row.sort();
+        let deltas : Vec<usize> = row.iter().scan(i, |prev_neigh, j| Some(j - std::mem::replace(prev_neigh, *j))).collect();
+        deltas.into_iter().chain([SEPARATOR])
+    });
+
+    let mut produced = 0;
+    let order = std::iter::once(n).chain(rows).inspect(|_| produced += 1).cmp(descriptor.iter().copied());
+    (order, produced)
+}
+
+/// Invariants of a graph that are cheap to compute, to reject most pairs of
+/// non-isomorphic graphs without computing their keys.
+///
+/// Prefilters can be computed once per graph and kept along with it. The
+/// degree sequence is only kept as a hash, with the same value on every target
+/// as `GraphKey::fingerprint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GraphPrefilter {
+    pub node_count : usize,
+    pub edge_count : usize,
+    /// 64-bit FNV-1a hash of the sorted degree sequence
+    pub degree_hash : u64,
+    pub max_degree : usize,
+}
+
+impl GraphPrefilter {
+    pub fn new<G>(g : G) -> GraphPrefilter
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let degrees = degree_sequence(g);
+        GraphPrefilter {
+            node_count : g.node_count(),
+            edge_count : g.edge_references().count(),
+            degree_hash : fnv1a(degrees.iter().flat_map(|d| (*d as u64).to_le_bytes())),
+            max_degree : degrees.last().copied().unwrap_or(0),
+        }
+    }
+
+    /// Tells if the graphs of `self` and `other` can be isomorphic: false
+    /// proves they are not, true is only a hint that they may be.
+    pub fn could_match(&self, other : &GraphPrefilter) -> bool {
+        self == other
+    }
+}
+
+fn degree_sequence<G>(g : G) -> Vec<usize>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    let mut degrees : Vec<usize> = (0..g.node_count()).map(|u| g.neighbors(g.from_index(u)).count()).collect();
+    degrees.sort();
+    degrees
+}
+
+/// Checks that `perm` is an automorphism of `g`, i.e. a permutation of the
+/// nodes such that (u, v) is an edge iff (perm[u], perm[v]) is an edge.
+///
+/// Returns false if `perm` is not a permutation of 0..n.
+pub fn is_automorphism<G>(g : G, perm : &[usize]) -> bool
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let n = g.node_count();
+    if perm.len() != n {
+        return false;
+    }
+
+    let mut seen = vec![false; n];
+    for p in perm {
+        if *p >= n || seen[*p] {
+            return false;
+        }
+        seen[*p] = true;
+    }
+
+    maps_edges(g, g, perm)
+}
+
+/// Compute a 64-bit fingerprint of the undirected graph `g`, equal to the hash
+/// of `GraphKey::new(g)` by a `DefaultHasher`.
+///
+/// The descriptor the search settled on is hashed as is, without being wrapped
+/// into a key.
+pub fn canonical_u64<G>(g : G) -> u64
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    let mut state = DefaultHasher::new();
+    canonical_search(g).descriptor.hash(&mut state);
+    state.finish()
+}
+
+/// Compute the keys of the graphs received on `graphs` on a worker thread.
+///
+/// Keys are sent in the order the graphs were received. The returned channel
+/// is closed once `graphs` is closed and every pending key has been sent.
+pub fn key_stream<N, E>(graphs : Receiver<Graph<N, E, Undirected>>) -> Receiver<GraphKey>
+where
+    N : Send + 'static,
+    E : Send + 'static
+{
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        for g in graphs {
+            if tx.send(GraphKey::new(&g)).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Two non-isomorphic graphs, given by their indices, were assigned the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionError {
+    pub first : usize,
+    pub second : usize,
+}
+
+impl fmt::Display for CollisionError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "graphs {} and {} share a key but are not isomorphic", self.first, self.second)
+    }
+}
+
+impl Error for CollisionError {}
+
+/// The search was stopped before reaching the canonical leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanonError {
+    /// More than `budget` tree nodes were needed.
+    BudgetExceeded { budget : usize },
+}
+
+impl fmt::Display for CanonError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanonError::BudgetExceeded { budget } => write!(f, "search exceeded its budget of {} tree nodes", budget),
+        }
+    }
+}
+
+impl Error for CanonError {}
+
+/// The matrices describing a coloured graph do not agree, see
+/// `GraphKey::from_colored_matrix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixError {
+    /// The row `row` of the edge matrix does not have one entry per row.
+    NotSquare { row : usize },
+    /// The entries (u, v) and (v, u) of the edge matrix differ.
+    NotSymmetric { u : usize, v : usize },
+    /// The vertex colors and the edge matrix do not have the same size.
+    LengthMismatch { vertices : usize, rows : usize },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatrixError::NotSquare { row } => write!(f, "row {} of the edge matrix does not have one entry per row", row),
+            MatrixError::NotSymmetric { u, v } => write!(f, "edge matrix entries ({}, {}) and ({}, {}) differ", u, v, v, u),
+            MatrixError::LengthMismatch { vertices, rows } => write!(f, "{} vertex colors for an edge matrix of {} rows", vertices, rows),
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+/// The edge given to `GraphKey::new_edge_rooted` is not an edge of the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeError {
+    /// The node `node` is not below the node count.
+    NodeOutOfRange { node : usize, node_count : usize },
+    /// The nodes `u` and `v` are not adjacent.
+    NotAnEdge { u : usize, v : usize },
+}
+
+impl fmt::Display for EdgeError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdgeError::NodeOutOfRange { node, node_count } => write!(f, "node {} is out of range for a graph of {} nodes", node, node_count),
+            EdgeError::NotAnEdge { u, v } => write!(f, "nodes {} and {} are not adjacent", u, v),
+        }
+    }
+}
+
+impl Error for EdgeError {}
+
+/// The input of `GraphKey::from_bytes` or `GraphKey::from_str` is not a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input is empty, ends in the middle of a value, or misses values of
+    /// the rows or of the labelled header.
+    Truncated,
+    /// A value is encoded with more bytes than needed, does not fit in a
+    /// `usize`, or does not fit the layout of a key.
+    Malformed,
+    /// The string is not an even number of hexadecimal digits.
+    InvalidHex,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Truncated => write!(f, "key is empty or truncated"),
+            ParseError::Malformed => write!(f, "key holds a malformed value"),
+            ParseError::InvalidHex => write!(f, "key is not an even number of hexadecimal digits"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+
+//
+// GraphTracker object
+//
+
+/// Tracks the isomorphism class of a graph evolving over time.
+#[derive(Debug, Clone, Default)]
+pub struct GraphTracker {
+    last : Option<GraphKey>,
+}
+
+impl GraphTracker {
+
+    pub fn new() -> GraphTracker {
+        GraphTracker::default()
+    }
+
+    /// Key of the graph given to the last update, if any
+    pub fn key(&self) -> Option<&GraphKey> {
+        self.last.as_ref()
+    }
+
+    /// Record the current state `g` of the graph.
+    ///
+    /// Returns whether its isomorphism class changed since the last update. The
+    /// first update always reports a change.
+    pub fn update<G>(&mut self, g : G) -> bool
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let key = GraphKey::of_graph(g);
+        let changed = self.last.as_ref() != Some(&key);
+        self.last = Some(key);
+        changed
+    }
+}
+
+
+//
+// GraphKeyCache object
+//
+
+/// Memoizes the keys of graphs that are seen again with exactly the same node
+/// count and edge list, in the same order.
+///
+/// Entries are looked up by hashing the raw edge list, which is much cheaper
+/// than the search. This only speeds up workloads with exact duplicates:
+/// isomorphic graphs with different labellings are separate entries, each
+/// canonicalized once.
+#[derive(Debug, Clone, Default)]
+pub struct GraphKeyCache {
+    entries : HashMap<(usize, Vec<(usize, usize)>), GraphKey>,
+    computed : usize,
+}
+
+impl GraphKeyCache {
+
+    pub fn new() -> GraphKeyCache {
+        GraphKeyCache::default()
+    }
+
+    /// Key of `g`, computed only if the same graph was not seen before.
+    pub fn get<G>(&mut self, g : G) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+    {
+        let raw : Vec<(usize, usize)> = g.edge_references().map(|e| (g.to_index(e.source()), g.to_index(e.target()))).collect();
+
+        match self.entries.entry((g.node_count(), raw)) {
+            Entry::Occupied(e) => e.get().clone(),
+            Entry::Vacant(e) => {
+                self.computed += 1;
+                e.insert(GraphKey::of_graph(g)).clone()
+            }
+        }
+    }
+
+    /// Number of keys actually computed by the cache
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+
+    /// Number of distinct graphs stored
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+
+//
+// GraphKeySet object
+//
+
+/// Set of isomorphism classes, holding the key of one graph of each class.
+#[derive(Debug, Clone, Default)]
+pub struct GraphKeySet {
+    keys : HashSet<GraphKey>,
+}
+
+impl GraphKeySet {
+
+    pub fn new() -> GraphKeySet {
+        GraphKeySet::default()
+    }
+
+    /// Add the class of `g`, returns true if it was not in the set.
+    pub fn insert_graph<G : KeyInput>(&mut self, g : G) -> bool {
+        self.keys.insert(g.into_key())
+    }
+
+    /// Tells if a graph isomorphic to `g` was inserted.
+    pub fn contains_graph<G : KeyInput>(&self, g : G) -> bool {
+        self.keys.contains(&g.into_key())
+    }
+
+    /// Replace the class `old_key` by the class of `new`, the tracked graph
+    /// once edited, and return the key of `new`.
+    ///
+    /// The new key is computed before the set changes, so the set either holds
+    /// the old class or the new one. The set does not count graphs: the old
+    /// class is removed even if another tracked graph belongs to it.
+    pub fn update_graph<G : KeyInput>(&mut self, old_key : &GraphKey, new : G) -> GraphKey {
+        let key = new.into_key();
+        self.keys.remove(old_key);
+        self.keys.insert(key.clone());
+        key
+    }
+
+    /// Number of classes in the set
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+
+//
+// CanonicalRecord object
+//
+
+/// A key stored alongside the canonical graph it was computed from, so that
+/// both can be persisted and reloaded without running the search again.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CanonicalRecord {
+    pub key : GraphKey,
+    pub graph : Graph<usize, (), Undirected>,
+}
+
+
+//
+// Sampled verification
+//
+
+/// Bits of the f64 probability that `GraphKey::new` checks its key, 0 when
+/// disabled.
+static VERIFICATION_SAMPLE_RATE : AtomicU64 = AtomicU64::new(0);
+
+/// Number of keys checked since the start of the process.
+static VERIFICATIONS : AtomicUsize = AtomicUsize::new(0);
+
+impl GraphKey {
+
+    /// Make `GraphKey::new` check a sample of the keys it computes: with
+    /// probability `rate`, the key of a random permutation of the graph is
+    /// computed too, and a mismatch panics.
+    ///
+    /// The setting is global to the process. Disabled by default, which only
+    /// costs an atomic load per key. Panics if rate is not within [0, 1].
+    pub fn set_verification_sample_rate(rate : f64) {
+        assert!((0.0..=1.0).contains(&rate), "the sample rate must be within [0, 1]");
+        VERIFICATION_SAMPLE_RATE.store(rate.to_bits(), AtomicOrdering::Relaxed);
+    }
+
+    pub fn verification_sample_rate() -> f64 {
+        f64::from_bits(VERIFICATION_SAMPLE_RATE.load(AtomicOrdering::Relaxed))
+    }
+
+    /// Number of keys checked by `GraphKey::new` since the start of the process.
+    pub fn verification_count() -> usize {
+        VERIFICATIONS.load(AtomicOrdering::Relaxed)
+    }
+}
+
+/// Check that a random permutation of `g` gets the key `key`.
+fn verify_key<G>(g : G, key : &GraphKey)
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let permuted = generators::permute(g, rand::thread_rng().gen());
+    VERIFICATIONS.fetch_add(1, AtomicOrdering::Relaxed);
+    assert!(canonical_search(&permuted).descriptor == key.0, "a permutation of the graph gets another key: {:?}", key);
+}
+
+
+//
+// Search
+//
+
+/// Outcome of the exploration of a search tree.
+struct SearchResult {
+    /// Maximal descriptor over the leaves
+    descriptor : Vec<usize>,
+    /// Discrete colouring of the leaf that produced the descriptor
+    leaf : Colouring,
+    /// Number of tree nodes created by individualization
+    #[allow(dead_code)]
+    explored : usize,
+    /// Number of colourings cloned to create them
+    #[allow(dead_code)]
+    clones : usize,
+    /// Order of the automorphism group of the coloured graph
+    group_order : usize,
+    /// Other leaves producing the descriptor, one per non-trivial automorphism
+    equivalent_leaves : Vec<Colouring>,
+    /// Number of individualizations from the root to the leaves
+    depth : usize,
+    /// Automorphisms collected by `search_pruned` to prune the tree, none for
+    /// the other searches
+    generators : Vec<Vec<usize>>,
+}
+
+/// Explore the search tree of `g` from the uniform colouring, with the default
+/// cell selector.
+fn canonical_search<G>(g : G) -> SearchResult
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    search(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor)
+}
+
+/// Explore the search tree of `g` rooted at the colouring `gc`, using `select`
+/// to pick the target cell of each tree node, and `selection` to pick the
+/// canonical leaf.
+fn search<S>(g : S, gc : Colouring, select : fn(&Colouring) -> usize, selection : LeafSelection) -> SearchResult
+where
+    S : SearchGraph
+{
+    search_with_limits(g, gc, select, selection, SearchOrder::Bfs, None, None).expect("an unlimited search cannot fail")
+}
+
+/// Same as `search`, exploring the tree in the given order, refining with at
+/// most `max_rounds` studied colors and failing once more than `budget` tree
+/// nodes have been created.
+fn search_with_limits<S>(
+    g : S,
+    gc : Colouring,
+    select : fn(&Colouring) -> usize,
+    selection : LeafSelection,
+    order : SearchOrder,
+    max_rounds : Option<usize>,
+    budget : Option<usize>
+) -> Result<SearchResult, CanonError>
+where
+    S : SearchGraph
+{
+    let explored = match order {
+        SearchOrder::Bfs => explore(g, gc, select, max_rounds, budget)?,
+        SearchOrder::BestFirst => explore_best_first(g, gc, select, max_rounds, budget)?,
+    };
+    Ok(select_leaf(g, explored, selection))
+}
+
+/// Same as `search`, choosing the canonical leaf with `select_leaf_parallel`.
+#[cfg(feature = "rayon")]
+fn search_parallel<S>(g : S, gc : Colouring, select : fn(&Colouring) -> usize, selection : LeafSelection) -> SearchResult
+where
+    S : SearchGraph + Sync
+{
+    let explored = explore(g, gc, select, None, None).expect("an unlimited search cannot fail");
+    select_leaf_parallel(g, explored, selection)
+}
+
+/// Choose the canonical leaf among the leaves left by the exploration.
+fn select_leaf<S>(g : S, explored : Explored, selection : LeafSelection) -> SearchResult
+where
+    S : SearchGraph
+{
+    select_leaf_by(g, explored, selection, |a, b| selection.compare(a.ranking(), b.ranking()))
+}
+
+/// Choose the canonical leaf as the greatest candidate for cmp, the traces of
+/// the leaves being computed for `selection`.
+fn select_leaf_by<S, C>(g : S, mut explored : Explored, selection : LeafSelection, cmp : C) -> SearchResult
+where
+    S : SearchGraph,
+    C : Fn(&Candidate, &Candidate) -> Ordering
+{
+    let best = std::mem::take(&mut explored.leaves).into_iter().enumerate()
+        .map(|(index, leaf)| Candidate::new(g, index, leaf, selection))
+        .reduce(|a, b| Candidate::best(a, b, &cmp))
+        .unwrap();
+
+    explored.finish(best)
+}
+
+/// Same as `select_leaf`, computing and comparing the descriptors on the
+/// rayon thread pool. The result does not depend on how the leaves are split
+/// between threads, see `Candidate::best`.
+#[cfg(feature = "rayon")]
+fn select_leaf_parallel<S>(g : S, mut explored : Explored, selection : LeafSelection) -> SearchResult
+where
+    S : SearchGraph + Sync
+{
+    use rayon::prelude::*;
+
+    let best = std::mem::take(&mut explored.leaves).into_par_iter().enumerate()
+        .map(|(index, leaf)| Candidate::new(g, index, leaf, selection))
+        .reduce_with(|a, b| Candidate::best(a, b, |a, b| selection.compare(a.ranking(), b.ranking())))
+        .unwrap();
+
+    explored.finish(best)
+}
+
+/// Leaves left by the exploration of a search tree, see `explore`.
+#[derive(Clone)]
+struct Explored {
+    /// Leaves sharing the best trace, in the order of the exploration, taken
+    /// by the final selection
+    leaves : Vec<Colouring>,
+    explored : usize,
+    clones : usize,
+    depth : usize,
+}
+
+impl Explored {
+
+    /// Build the result of the search won by `best`.
+    fn finish(self, best : Candidate) -> SearchResult {
+        let mut equivalent = best.equivalent;
+        equivalent.sort_by_key(|(index, _)| *index);
+
+        // Every leaf reaching the extremal descriptor is the image of the best leaf by
+        // exactly one automorphism, so counting them gives the order of the group.
+        SearchResult {
+            descriptor : best.descriptor,
+            leaf : best.leaf,
+            explored : self.explored,
+            clones : self.clones,
+            group_order : equivalent.len() + 1,
+            equivalent_leaves : equivalent.into_iter().map(|(_, leaf)| leaf).collect(),
+            depth : self.depth,
+            generators : vec![],
+        }
+    }
+}
+
+/// Leaf competing in the final selection, with the leaves found equal to it.
+struct Candidate {
+    descriptor : Vec<usize>,
+    /// See `LeafSelection::leaf_trace`
+    trace : Vec<usize>,
+    /// Position of the leaf in `Explored::leaves`
+    index : usize,
+    leaf : Colouring,
+    equivalent : Vec<(usize, Colouring)>,
+}
+
+impl Candidate {
+
+    fn new<S : SearchGraph>(g : S, index : usize, leaf : Colouring, selection : LeafSelection) -> Candidate {
+        Candidate { descriptor : g.describe(&leaf), trace : selection.leaf_trace(g, &leaf), index, leaf, equivalent : Vec::new() }
+    }
+
+    /// Trace and descriptor of the leaf, as compared by `LeafSelection`.
+    fn ranking(&self) -> (&[usize], &[usize]) {
+        (&self.trace, &self.descriptor)
+    }
+
+    /// Keep the better of a and b, the greater for cmp, merging them if they
+    /// are equal.
+    ///
+    /// A tie goes to the leaf of lowest index, which makes the reduction
+    /// associative and commutative: every grouping of the leaves picks the same
+    /// leaf, and the first one in the sequential order.
+    fn best<C>(a : Candidate, b : Candidate, cmp : C) -> Candidate
+    where
+        C : Fn(&Candidate, &Candidate) -> Ordering
+    {
+        match cmp(&a, &b) {
+            Ordering::Greater => a,
+            Ordering::Less => b,
+            Ordering::Equal => {
+                let (mut winner, loser) = if a.index < b.index { (a, b) } else { (b, a) };
+                winner.equivalent.push((loser.index, loser.leaf));
+                winner.equivalent.extend(loser.equivalent);
+                winner
+            }
+        }
+    }
+}
+
+/// Explore the search tree of `g` rooted at `gc` down to the leaves sharing
+/// the best trace, with the limits of `search_with_limits`.
+fn explore<S>(
+    g : S,
+    mut gc : Colouring,
+    select : fn(&Colouring) -> usize,
+    max_rounds : Option<usize>,
+    budget : Option<usize>
+) -> Result<Explored, CanonError>
+where
+    S : SearchGraph
+{
+    // Buffers shared by all the refinements of the search
+    let mut scratch = RefineScratch::default();
+    scratch.max_rounds = max_rounds;
+    let check_budget = |explored : usize| match budget {
+        Some(budget) if explored > budget => Err(CanonError::BudgetExceeded { budget }),
+        _ => Ok(()),
+    };
+
+    // First refine of the initial colouring.
+    gc.refine_into(g, WorklistOrder::Heap, &mut scratch);
+
+    let mut explored = 0;
+    let mut clones = 0;
+
+    // Set up the tree for exploration. If gc is already discrete, the root is
+    // the only leaf and goes straight to the final selection below.
+    let root = if gc.is_discrete() {
+        TreeNode{
+            c : gc,
+            target_cell: 0,
+            children : vec![],
+            son_in_exp_path: None,
+            k_dim : None,
+        }
+    } else {
+
+        let target = select(&gc);
+        let mut children = gc.get_cell_members(target);
+        children.sort_by(|a, b| b.cmp(a));
+
+        TreeNode{
+            c : gc,
+            target_cell: target,
+            children,
+            son_in_exp_path: None,
+            k_dim : None,
+        }
+    };
+
+    //
+    // 3. Main loop
+    //
+    //      * Follows the exploration path of Traces
+    //
+
+    let mut leaf_found = root.c.is_discrete();
+    let mut next_list = Vec::from([root]);      // list of colourings to study on next level
+
+    // let mut leaves_colouing : Vec<Graph<usize, ()>> = Vec::new();
+    // let mut leaves_descriptors : Vec<Vec<usize>> = Vec::new();
+
+    let mut depth = 0;
+
+    while !leaf_found { 
+
+        depth += 1;
+
+        let current_list = next_list;
+        next_list = Vec::new();
+
+        // The best trace is shared with the tree nodes reaching it, instead of
+        // being copied whenever a better one is found
+        let mut best_k_dim = Rc::new(Kdim::new(0, vec![]));
+
+        for node in current_list.into_iter() {
+
+            let mut node = node;
+
+            // Add son in exploration to next_list (losing ownership).
+            // It competes with its siblings through its own k_dim: comparing it with
+            // the k_dim of its parent would drop it depending on the labelling of g.
+            if let Some(b) = node.son_in_exp_path.take() {
+                let k_dim = b.k_dim.as_ref().unwrap();
+                if best_k_dim <= *k_dim { 
+                    if best_k_dim < *k_dim {
+                        next_list = Vec::new();
+                        best_k_dim = Rc::clone(k_dim);
+                    }
+                    next_list.push(*b);
+                }
+            }
+
+            while let Some(_v) = node.children.pop() {
+
+                // Create new TreeNode from the individualization of a (graph) node from the target cell
+                // The last child takes the colouring of its parent, which is not
+                // used afterwards
+                let mut _gc = if node.children.is_empty() {
+                    std::mem::replace(&mut node.c, Colouring::from_labels::<usize>(&[]))
+                } else {
+                    clones += 1;
+                    node.c.clone()
+                };
+                let trace = _gc.individualize_and_refine_into(node.target_cell, _v, g, &mut scratch);
+                explored += 1;
+                check_budget(explored)?;
+                let mut k_dim = Rc::new(Kdim::new(_gc.get_cell_count(), trace));
+
+                // at each iteration, the ownership of the current node is given to the parent
+                let mut ancestor_in_exp_path = &mut node;
+                
+                if best_k_dim > k_dim {
+                    continue;
+                }
+
+                if best_k_dim < k_dim {
+                    next_list = Vec::new();
+                    best_k_dim = Rc::clone(&k_dim);
+                }
+
+                // Compute experimental path
+                loop {
+                    
+                    if _gc.is_discrete() {
+                        
+                        // TODO : check automorphisms
+
+                        let leaf = TreeNode{ 
+                            c : _gc, 
+                            target_cell: 0,
+                            children : vec![],
+                            son_in_exp_path: None, 
+                            k_dim : Some(k_dim)
+                        };
+
+                        ancestor_in_exp_path.son_in_exp_path = Some(Box::new(leaf));
+
+                        break;
+                    }
+                    
+                    let target = select(&_gc);
+                    let children = _gc.get_cell_members(target);
+                    // children.sort_by(|a, b| b.cmp(a));             // TODO : delete
+                    let mut new_experimental_path_node = TreeNode{ 
+                        c : _gc, 
+                        target_cell: target, 
+                        children, 
+                        son_in_exp_path: None, 
+                        k_dim : Some(k_dim)
+                    };
+
+                    let _v = new_experimental_path_node.children.pop().unwrap();
+                    _gc = new_experimental_path_node.c.clone();
+                    clones += 1;
+                    let trace = _gc.individualize_and_refine_into(new_experimental_path_node.target_cell, _v, g, &mut scratch);
+                    explored += 1;
+                    check_budget(explored)?;
+                    k_dim = Rc::new(Kdim::new(_gc.get_cell_count(), trace));
+
+                    // Give ownership of the new node to its parent & create a new &mut
+                    ancestor_in_exp_path.son_in_exp_path = Some(Box::new(new_experimental_path_node));
+                    ancestor_in_exp_path = ancestor_in_exp_path.son_in_exp_path.as_deref_mut().unwrap();
+                }
+                
+                if let Some(_n) = node.son_in_exp_path.take() {
+                    next_list.push(*_n);
+                }
+            }
+        }
+
+        // All the nodes kept share the same k_dim, hence the same cell count:
+        // either they are all leaves or none of them is.
+        leaf_found = next_list[0].c.is_discrete();
+    }
+
+    Ok(Explored { leaves : next_list.into_iter().map(|leaf| leaf.c).collect(), explored, clones, depth })
+}
+
+
+
+/// Tree node waiting in the priority queue of `explore_best_first`.
+struct Frontier {
+    k_dim : Rc<Kdim>,
+    /// Traces of the ancestors of the node, and of the node itself
+    path : Vec<Rc<Kdim>>,
+    c : Colouring,
+}
+
+// Frontier nodes are ordered by their own trace only. Traces compare cell
+// counts first, so that the deepest nodes come out of the queue first.
+
+impl Ord for Frontier {
+    fn cmp(&self, other : &Self) -> Ordering {
+        self.k_dim.cmp(&other.k_dim)
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other : &Self) -> bool {
+        self.k_dim == other.k_dim
+    }
+}
+
+impl Eq for Frontier {}
+
+/// Explore the same search tree as `explore`, expanding the frontier node of
+/// best trace first.
+///
+/// The best trace seen so far is kept for each level, as in
+/// `search_depth_first`: a node below it is pruned, and a node above it
+/// replaces it and the levels under it. A node taken from the queue is only
+/// expanded if its path still follows the best traces. The leaves kept are the
+/// ones whose path is the final best path, which are the leaves kept by
+/// `explore`.
+fn explore_best_first<S>(
+    g : S,
+    mut gc : Colouring,
+    select : fn(&Colouring) -> usize,
+    max_rounds : Option<usize>,
+    budget : Option<usize>
+) -> Result<Explored, CanonError>
+where
+    S : SearchGraph
+{
+    let mut scratch = RefineScratch::default();
+    scratch.max_rounds = max_rounds;
+    gc.refine_into(g, WorklistOrder::Heap, &mut scratch);
+
+    if gc.is_discrete() {
+        return Ok(Explored { leaves : vec![gc], explored : 0, clones : 0, depth : 0 });
+    }
+
+    // best_path[d] : best trace among the nodes at depth d + 1
+    let mut best_path : Vec<Rc<Kdim>> = Vec::new();
+    let mut leaves : Vec<(Vec<Rc<Kdim>>, Colouring)> = Vec::new();
+    let mut heap = BinaryHeap::from([Frontier { k_dim : Rc::new(Kdim::new(0, vec![])), path : Vec::new(), c : gc }]);
+    let (mut explored, mut clones) = (0, 0);
+
+    while let Some(mut node) = heap.pop() {
+
+        let depth = node.path.len();
+        if best_path.get(..depth) != Some(&node.path[..]) {
+            continue;
+        }
+
+        let target = select(&node.c);
+        let mut children = node.c.get_cell_members(target);
+        children.sort_by(|a, b| b.cmp(a));
+
+        while let Some(v) = children.pop() {
+
+            // The last child takes the colouring of its parent
+            let mut c = if children.is_empty() {
+                std::mem::replace(&mut node.c, Colouring::from_labels::<usize>(&[]))
+            } else {
+                clones += 1;
+                node.c.clone()
+            };
+            let trace = c.individualize_and_refine_into(target, v, g, &mut scratch);
+            explored += 1;
+            if let Some(budget) = budget.filter(|budget| explored > *budget) {
+                return Err(CanonError::BudgetExceeded { budget });
+            }
+            let k_dim = Rc::new(Kdim::new(c.get_cell_count(), trace));
+
+            if depth == best_path.len() {
+                best_path.push(Rc::clone(&k_dim));
+            } else if best_path[depth] > k_dim {
+                continue;
+            } else if best_path[depth] < k_dim {
+                best_path.truncate(depth);
+                best_path.push(Rc::clone(&k_dim));
+            }
+
+            let mut path = node.path.clone();
+            path.push(Rc::clone(&k_dim));
+            if c.is_discrete() {
+                leaves.push((path, c));
+            } else {
+                heap.push(Frontier { k_dim, path, c });
+            }
+        }
+    }
+
+    let depth = best_path.len();
+    let leaves = leaves.into_iter().filter(|(path, _)| *path == best_path).map(|(_, leaf)| leaf).collect();
+    Ok(Explored { leaves, explored, clones, depth })
+}
+
+/// Explore the same search tree as `search`, depth-first.
+///
+/// A single colouring is individualized and refined down each branch, then
+/// restored with `Colouring::undo_splits` on the way back, so memory does not
+/// grow with the width of the tree. The best trace seen so far is kept for
+/// each level: a node below it is pruned, and a node above it discards every
+/// candidate found under the previous best. The surviving leaves are the ones
+/// kept by the breadth-first search, but subtrees are explored before their
+/// siblings could prune them. Only the best leaf is kept, so
+/// `equivalent_leaves` is always empty.
+fn search_depth_first<S>(g : S, mut gc : Colouring, select : fn(&Colouring) -> usize, selection : LeafSelection) -> SearchResult
+where
+    S : SearchGraph
+{
+    struct Frame {
+        cell_count : usize,
+        target_cell : usize,
+        children : Vec<usize>,
+    }
+
+    let mut scratch = RefineScratch::default();
+    gc.refine_into(g, WorklistOrder::Heap, &mut scratch);
+
+    if gc.is_discrete() {
+        let descriptor = g.describe(&gc);
+        return SearchResult { descriptor, leaf : gc, explored : 0, clones : 0, group_order : 1, equivalent_leaves : vec![], depth : 0, generators : vec![] };
+    }
+
+    let target = select(&gc);
+    let mut stack = vec![Frame { cell_count : gc.get_cell_count(), target_cell : target, children : gc.get_cell_members(target) }];
+
+    // best_k_dims[d] : best trace among the nodes at depth d + 1
+    let mut best_k_dims : Vec<Kdim> = Vec::new();
+    let mut best : Option<(Vec<usize>, Vec<usize>, Colouring)> = None;
+    let mut group_order = 0;
+    let (mut explored, mut clones, mut depth) = (0, 0, 0);
+
+    while !stack.is_empty() {
+
+        let level = stack.len() - 1;
+        let frame = &mut stack[level];
+        let Some(v) = frame.children.pop() else {
+            stack.pop();
+            if let Some(parent) = stack.last() {
+                gc.undo_splits(parent.cell_count);
+            }
+            continue;
+        };
+
+        let cell_count = frame.cell_count;
+        let trace = gc.individualize_and_refine_into(frame.target_cell, v, g, &mut scratch);
+        explored += 1;
+        let k_dim = Kdim::new(gc.get_cell_count(), trace);
+
+        if level == best_k_dims.len() {
+            best_k_dims.push(k_dim);
+        } else if best_k_dims[level] > k_dim {
+            gc.undo_splits(cell_count);
+            continue;
+        } else if best_k_dims[level] < k_dim {
+            // Every candidate so far lies below a worse node of this level
+            best_k_dims.truncate(level);
+            best_k_dims.push(k_dim);
+            best = None;
+            group_order = 0;
+        }
+
+        if !gc.is_discrete() {
+            let target = select(&gc);
+            let children = gc.get_cell_members(target);
+            stack.push(Frame { cell_count : gc.get_cell_count(), target_cell : target, children });
+            continue;
+        }
+
+        let descriptor = g.describe(&gc);
+        let trace = selection.leaf_trace(g, &gc);
+        let order = match &best {
+            None => Ordering::Greater,
+            Some((best_descriptor, best_trace, _)) => selection.compare((&trace, &descriptor), (best_trace, best_descriptor)),
+        };
+        match order {
+            Ordering::Greater => {
+                best = Some((descriptor, trace, gc.clone()));
+                clones += 1;
+                group_order = 1;
+                depth = level + 1;
+            }
+            Ordering::Equal => { group_order += 1; }
+            Ordering::Less => {}
+        }
+
+        gc.undo_splits(cell_count);
+    }
+
+    let (descriptor, _, leaf) = best.unwrap();
+    SearchResult { descriptor, leaf, explored, clones, group_order, equivalent_leaves : vec![], depth, generators : vec![] }
+}
+
+/// Explore the same search tree as `search_depth_first`, skipping the
+/// children that an automorphism fixing their ancestors maps onto an explored
+/// sibling.
+///
+/// Such a child roots the image of the subtree of its sibling, with the same
+/// traces and descriptors, so it cannot change the result. Each leaf reaching
+/// the best descriptor gives an automorphism, mapping the best leaf onto it;
+/// a child is skipped if it shares an orbit with an explored sibling under
+/// the automorphisms found so far that fix its ancestors. Skipped leaves are
+/// not counted, so `group_order` is only the number of leaves reached with the
+/// best descriptor.
+fn search_pruned<S>(g : S, mut gc : Colouring, select : fn(&Colouring) -> usize, selection : LeafSelection) -> SearchResult
+where
+    S : SearchGraph
+{
+    struct Frame {
+        cell_count : usize,
+        target_cell : usize,
+        children : Vec<usize>,
+        visited : Vec<usize>,
+    }
+
+    let mut scratch = RefineScratch::default();
+    gc.refine_into(g, WorklistOrder::Heap, &mut scratch);
+
+    if gc.is_discrete() {
+        let descriptor = g.describe(&gc);
+        return SearchResult { descriptor, leaf : gc, explored : 0, clones : 0, group_order : 1, equivalent_leaves : vec![], depth : 0, generators : vec![] };
+    }
+
+    let n = gc.size();
+    let target = select(&gc);
+    let mut stack = vec![Frame { cell_count : gc.get_cell_count(), target_cell : target, children : gc.get_cell_members(target), visited : vec![] }];
+
+    // Nodes individualized from the root down to the current frame
+    let mut path : Vec<usize> = Vec::new();
+    let mut automorphisms : Vec<Vec<usize>> = Vec::new();
+
+    // best_k_dims[d] : best trace among the nodes at depth d + 1
+    let mut best_k_dims : Vec<Kdim> = Vec::new();
+    let mut best : Option<(Vec<usize>, Vec<usize>, Colouring)> = None;
+    let mut best_path : Vec<usize> = Vec::new();
+    let mut group_order = 0;
+    let (mut explored, mut clones, mut depth) = (0, 0, 0);
+
+    while !stack.is_empty() {
+
+        let level = stack.len() - 1;
+        let frame = &mut stack[level];
+        let Some(v) = frame.children.pop() else {
+            stack.pop();
+            path.pop();
+            if let Some(parent) = stack.last() {
+                gc.undo_splits(parent.cell_count);
+            }
+            continue;
+        };
+
+        if !frame.visited.is_empty() {
+            let orbit_of = stabilizer_orbits(&automorphisms, &path, n);
+            if frame.visited.iter().any(|w| orbit_of[*w] == orbit_of[v]) {
+                continue;
+            }
+        }
+        frame.visited.push(v);
+
+        let cell_count = frame.cell_count;
+        let trace = gc.individualize_and_refine_into(frame.target_cell, v, g, &mut scratch);
+        explored += 1;
+        let k_dim = Kdim::new(gc.get_cell_count(), trace);
+
+        if level == best_k_dims.len() {
+            best_k_dims.push(k_dim);
+        } else if best_k_dims[level] > k_dim {
+            gc.undo_splits(cell_count);
+            continue;
+        } else if best_k_dims[level] < k_dim {
+            // Every candidate so far lies below a worse node of this level
+            best_k_dims.truncate(level);
+            best_k_dims.push(k_dim);
+            best = None;
+            group_order = 0;
+        }
+
+        if !gc.is_discrete() {
+            let target = select(&gc);
+            let children = gc.get_cell_members(target);
+            stack.push(Frame { cell_count : gc.get_cell_count(), target_cell : target, children, visited : vec![] });
+            path.push(v);
+            continue;
+        }
+
+        let descriptor = g.describe(&gc);
+        let trace = selection.leaf_trace(g, &gc);
+        let order = match &best {
+            None => Ordering::Greater,
+            Some((best_descriptor, best_trace, _)) => selection.compare((&trace, &descriptor), (best_trace, best_descriptor)),
+        };
+        match order {
+            Ordering::Greater => {
+                best = Some((descriptor, trace, gc.clone()));
+                best_path.clone_from(&path);
+                best_path.push(v);
+                clones += 1;
+                group_order = 1;
+                depth = level + 1;
+            }
+            Ordering::Equal => {
+                // The node at each position of the best leaf is mapped to the
+                // node at the same position of this one
+                let mut node_at = vec![0; n];
+                (0..n).for_each(|u| node_at[gc.color_of(u)] = u);
+                let best_leaf = &best.as_ref().unwrap().2;
+                automorphisms.push((0..n).map(|u| node_at[best_leaf.color_of(u)]).collect());
+                group_order += 1;
+
+                // The automorphism fixes the nodes both paths share and maps the
+                // subtree of the best path below them onto the current one, which
+                // holds no other candidate: go back to where the paths split
+                let split = path.iter().chain([&v]).zip(&best_path).take_while(|(u, w)| u == w).count();
+                if split < level {
+                    stack.truncate(split + 1);
+                    path.truncate(split);
+                    gc.undo_splits(stack[split].cell_count);
+                    continue;
+                }
+            }
+            Ordering::Less => {}
+        }
+
+        gc.undo_splits(cell_count);
+    }
+
+    let (descriptor, _, leaf) = best.unwrap();
+    SearchResult { descriptor, leaf, explored, clones, group_order, equivalent_leaves : vec![], depth, generators : automorphisms }
+}
+
+/// Orbits of the group generated by the permutations of `automorphisms` that
+/// fix every node of `fixed`: entry u is the representative of the orbit of u.
+fn stabilizer_orbits(automorphisms : &[Vec<usize>], fixed : &[usize], n : usize) -> Vec<usize> {
+    fn find(parent : &mut [usize], mut u : usize) -> usize {
+        while parent[u] != u {
+            parent[u] = parent[parent[u]];
+            u = parent[u];
+        }
+        u
+    }
+
+    let mut parent : Vec<usize> = (0..n).collect();
+    for p in automorphisms.iter().filter(|p| fixed.iter().all(|u| p[*u] == *u)) {
+        for (u, v) in p.iter().enumerate() {
+            let (a, b) = (find(&mut parent, u), find(&mut parent, *v));
+            parent[a] = b;
+        }
+    }
+
+    (0..n).map(|u| find(&mut parent, u)).collect()
+}
+
+/// Graph explored by the search: it refines the colourings of the tree nodes,
+/// and describes the graph relabelled by a leaf.
+pub(crate) trait SearchGraph : RefineGraph {
+    fn describe(&self, leaf : &Colouring) -> Vec<usize>;
+}
+
+impl<G> SearchGraph for Neighbors<G>
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    fn describe(&self, leaf : &Colouring) -> Vec<usize> {
+        compute_descriptor(&leaf.compute_graph_from_discrete(self.0))
+    }
+}
+
+/// Criterion choosing the canonical leaf among the leaves left by the search.
+///
+/// The search prunes the tree by comparing the traces of the nodes at each
+/// level, so all the remaining leaves share the same path. They are told apart
+/// by their descriptors, or first by the trace of the leaf itself: the number
+/// of connections of the node at each position to the nodes before it. Every
+/// criterion is canonical, but keys computed with different criteria must not
+/// be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeafSelection {
+    #[default]
+    MaxDescriptor,
+    MinDescriptor,
+    /// The greatest leaf trace, ties broken by the greatest descriptor
+    MaxTrace,
+}
+
+impl LeafSelection {
+
+    /// Compare the leaves a and b, given by their traces and descriptors, the
+    /// better one being the greater.
+    fn compare(self, (a_trace, a) : (&[usize], &[usize]), (b_trace, b) : (&[usize], &[usize])) -> Ordering {
+        match self {
+            LeafSelection::MaxDescriptor => a.cmp(b),
+            LeafSelection::MinDescriptor => b.cmp(a),
+            LeafSelection::MaxTrace => a_trace.cmp(b_trace).then_with(|| a.cmp(b)),
+        }
+    }
+
+    /// Trace of the discrete colouring `leaf`: for the node at each position,
+    /// its number of connections of kind 0, then of kind 1, to the nodes at
+    /// lower positions. Only `MaxTrace` compares it, the other criteria get an
+    /// empty one.
+    fn leaf_trace<S : SearchGraph>(self, g : S, leaf : &Colouring) -> Vec<usize> {
+        if self != LeafSelection::MaxTrace {
+            return vec![];
+        }
+
+        let n = leaf.size();
+        let mut trace = vec![0; color_mul(2, n)];
+        for u in 0..n {
+            let i = leaf.color_of(u);
+            g.for_each_neighbor(u, |v, kind| if leaf.color_of(v) < i {
+                let count = &mut trace[color_add(color_mul(2, i), kind)];
+                *count = color_add(*count, 1);
+            });
+        }
+        trace
+    }
+}
+
+/// Rule choosing the target cell of each node of the search tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectorKind {
+    /// `Colouring::select_cell_smallest_first`
+    #[default]
+    SmallestFirst,
+    /// `Colouring::select_cell_v1`
+    FirstNonSingleton,
+}
+
+impl SelectorKind {
+    pub(crate) fn select(self) -> fn(&Colouring) -> usize {
+        match self {
+            SelectorKind::SmallestFirst => Colouring::select_cell_smallest_first,
+            SelectorKind::FirstNonSingleton => Colouring::select_cell_v1,
+        }
+    }
+}
+
+/// Settings of the search run by `GraphKey::new_with_config`.
+///
+/// The default settings are those of `GraphKey::new`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CanonConfig {
+    /// Maximal number of colors studied by each refinement, unlimited if None.
+    pub max_refine_rounds : Option<usize>,
+    /// Rule choosing the target cells.
+    pub selector : SelectorKind,
+    /// Maximal number of tree nodes created by individualization, unlimited if None.
+    pub search_node_budget : Option<usize>,
+    /// Order in which the tree nodes are explored.
+    pub order : SearchOrder,
+}
+
+/// Order in which the search explores its tree. Both give the same keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchOrder {
+    /// Level by level, keeping the nodes of best trace at each level, and
+    /// following an experimental path down to a leaf from each of them.
+    #[default]
+    Bfs,
+    /// Node of best trace first, from a priority queue of the frontier nodes.
+    BestFirst,
+}
+
+struct TreeNode {
+    c : Colouring,
+    target_cell : usize, 
+    children : Vec<usize>,
+    son_in_exp_path : Option<Box<TreeNode>>,
+    k_dim : Option<Rc<Kdim>>,
+}
+
+fn compute_descriptor<G>(g : G) -> Vec<usize>
+where
+    G : NodeCompactIndexable + IntoNeighbors + IntoEdges
+{
+    let n = g.node_count();
+    let rows = (0..n.saturating_sub(1)).map(|i| {
+        g.neighbors(g.from_index(i)).map(|j| g.to_index(j)).filter(|j| *j > i).collect()
+    });
+    descriptor_from_rows(n, rows)
+}
+
+/// Encode the descriptor of a graph on n nodes from the rows of its
+/// adjacency: the i-th row holds the neighbors j > i of the node i, and the
+/// last node has no row.
+pub(crate) fn descriptor_from_rows<I>(n : usize, rows : I) -> Vec<usize>
+where
+    I : IntoIterator<Item = Vec<usize>>
+{
+    let mut canonical = vec![n];
+
+    for (i, mut ordered_neighbors) in rows.into_iter().enumerate() {
+        let mut prev_neigh = i;
+        ordered_neighbors.sort(); 
+        for j in ordered_neighbors {
+            canonical.push(j - prev_neigh);
+            prev_neigh = j;
+        }
+        canonical.push(SEPARATOR);
+    }
+    
+    canonical
+}
+
+/// Adjacency of the graph described by `descriptor`, as given by
+/// `descriptor_from_rows`: the i-th list holds the neighbors of the node i, in
+/// increasing order. Returns None if it does not describe a graph.
+pub(crate) fn descriptor_adjacency(descriptor : &[usize]) -> Option<Vec<Vec<usize>>> {
+    let (n, values) = descriptor.split_first()?;
+    let mut adjacency = vec![Vec::new(); *n];
+    let mut values = values.iter();
+
+    for i in 0..n.saturating_sub(1) {
+        let mut prev_neigh = i;
+        loop {
+            match values.next()? {
+                &SEPARATOR => break,
+                delta => {
+                    prev_neigh = prev_neigh.checked_add(*delta).filter(|j| j < n && (*j > i))?;
+                    adjacency[i].push(prev_neigh);
+                    adjacency[prev_neigh].push(i);
+                }
+            }
+        }
+    }
+    if values.next().is_some() {
+        return None;
+    }
+
+    adjacency.iter_mut().for_each(|neigh| neigh.sort());
+    Some(adjacency)
+}
+
+/// Tells if a leaf below the colouring `gc` of a graph given by its adjacency
+/// may relabel it into the graph whose rows are `rows`.
+///
+/// A leaf puts the nodes of each cell at the positions of the cell, so every
+/// node and every row at these positions must have the same number of
+/// neighbors in each cell.
+fn may_reach_rows(gc : &Colouring, adjacency : &[Vec<usize>], rows : &[Vec<usize>]) -> bool {
+    let n = adjacency.len();
+
+    // Color of the cell holding each position, and members of each cell
+    let mut members = vec![Vec::new(); n];
+    (0..n).for_each(|u| members[gc.color_of(u)].push(u));
+    let mut cell_at = vec![0; n];
+    for p in 1..n {
+        cell_at[p] = if members[p].is_empty() { cell_at[p - 1] } else { p };
+    }
+
+    let profile = |neigh : &mut dyn Iterator<Item = usize>| {
+        let mut colors : Vec<usize> = neigh.collect();
+        colors.sort();
+        colors
+    };
+    (0..n).filter(|c| !members[*c].is_empty()).all(|c| {
+        let expected = profile(&mut rows[c].iter().map(|q| cell_at[*q]));
+        (c..c + members[c].len()).all(|p| profile(&mut rows[p].iter().map(|q| cell_at[*q])) == expected)
+            && members[c].iter().all(|u| profile(&mut adjacency[*u].iter().map(|v| gc.color_of(*v))) == expected)
+    })
+}
+
+/// Sizes of the connected components of `g`, in decreasing order.
+fn component_sizes<G>(g : G) -> Vec<usize>
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    let mut sizes : Vec<usize> = components(g).iter().map(Vec::len).collect();
+    sizes.sort_by(|a, b| b.cmp(a));
+    sizes
+}
+
+/// Connected components of `g`, each sorted, in increasing order of their
+/// smallest node.
+pub(crate) fn components<G>(g : G) -> Vec<Vec<usize>>
+where
+    G : NodeCompactIndexable + IntoEdges
+{
+    // Union-find over the edges, with path halving
+    let mut parent : Vec<usize> = (0..g.node_count()).collect();
+    fn find(parent : &mut [usize], mut u : usize) -> usize {
+        while parent[u] != u {
+            parent[u] = parent[parent[u]];
+            u = parent[u];
+        }
+        u
+    }
+
+    for e in g.edge_references() {
+        let (u, v) = (find(&mut parent, g.to_index(e.source())), find(&mut parent, g.to_index(e.target())));
+        parent[u] = v;
+    }
+
+    let mut index = HashMap::new();
+    let mut components : Vec<Vec<usize>> = Vec::new();
+    for u in 0..g.node_count() {
+        let i = *index.entry(find(&mut parent, u)).or_insert(components.len());
+        if i == components.len() {
+            components.push(Vec::new());
+        }
+        components[i].push(u);
+    }
+
+    components
+}
+
+//
+//
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::graph::{NodeIndex, UnGraph};
+    use petgraph::{Graph, Undirected};
+    use rand::{Rng, thread_rng};
+    use std::collections::HashSet;
+    use petgraph::algo::is_isomorphic;
+    use crate::common::{gen_test_graph, generate_random_graph, generate_permutated_graph};
+
+    #[test]
+    fn key_generation() {
+        
+        let g1 = gen_test_graph();
+        let g2 = generate_permutated_graph(&g1);
+        
+        let key1 = GraphKey::new(&g1);
+        let key2 = GraphKey::new(&g2);
+        
+        assert_eq!(key1, key2);
+    }
+
+    #[test]
+    fn key_generation_large() {
+        
+        let g1 = generate_random_graph(2000, 0.05);
+        let g2 = generate_permutated_graph(&g1);
+        
+        let key1 = GraphKey::new(&g1);
+        let key2 = GraphKey::new(&g2);
+        
+        assert_eq!(key1, key2);
+    }
+
+    #[test]
+    fn key_generation_reference() {
+
+        // Key of the test graph, as computed before any optimization of the
+        // colouring, where the rows then ended with the node count
+        const S : usize = SEPARATOR;
+        let expected = vec![10, 4, 1, S, 1, 1, S, 6, 1, S, 3, 1, S, 3, 2, S, 1, 2, S, 2, S, 2, S, S];
+        assert_eq!(*GraphKey::new(&gen_test_graph()).get_descriptor(), expected);
+    }
+
+    #[test]
+    fn separator_is_out_of_band() {
+
+        // A single cell of 3 nodes : the size of the cell is the node count,
+        // which used to end the rows
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        let key = GraphKey::new_with_labels(&path, &[3, 3, 3]);
+        assert_eq!(*key.get_descriptor(), vec![LABELLED_MARKER, 1, 3, 4, 3, 0, 0, 0, 3, 2, SEPARATOR, 1, SEPARATOR]);
+        assert_eq!(key.get_descriptor()[1..].iter().filter(|v| **v == SEPARATOR).count(), 2);
+
+        // Every value but the node count and the separators is a delta below n
+        for g in [gen_test_graph(), generate_random_graph(30, 0.3)] {
+            let n = g.node_count();
+            let descriptor = GraphKey::new(&g).get_descriptor().clone();
+            assert_eq!(descriptor.iter().filter(|v| **v == SEPARATOR).count(), n - 1);
+            assert!(descriptor[1..].iter().all(|v| *v == SEPARATOR || *v < n));
+        }
+    }
+
+    #[test]
+    fn hashset_graphkeys() {
+        
+        let mut g = generate_random_graph(1000, 0.1);
+        
+        let g1 = generate_permutated_graph(&g);
+        let g2 = generate_permutated_graph(&g);
+        
+        match g.find_edge(0.into(), 1.into()) {
+            Some(_ix) => { g.remove_edge(_ix); }
+            None => { g.add_edge(0.into(), 1.into(), ()); }
+        }
+        
+        let g3 = generate_permutated_graph(&g);
+        let g4 = generate_permutated_graph(&g);
+
+        // generate Hashset
+        let mut s = HashSet::new();
+
+        s.insert(GraphKey::new(&g1));
+        s.insert(GraphKey::new(&g2));
+        s.insert(GraphKey::new(&g3));
+        s.insert(GraphKey::new(&g4));
+
+        assert_eq!(s.len(), 2);
+    }
+
+
+    #[test]
+    fn graph_key_set() {
+
+        let mut g = generate_random_graph(60, 0.2);
+        let g1 = generate_permutated_graph(&g);
+        let g2 = generate_permutated_graph(&g);
+
+        match g.find_edge(0.into(), 1.into()) {
+            Some(_ix) => { g.remove_edge(_ix); }
+            None => { g.add_edge(0.into(), 1.into(), ()); }
+        }
+        let g3 = generate_permutated_graph(&g);
+        let g4 = generate_permutated_graph(&g);
+
+        let mut s = GraphKeySet::new();
+        assert!(s.is_empty());
+        assert!(s.insert_graph(&g1));
+        assert!(!s.insert_graph(&g2));
+        assert!(!s.contains_graph(&g3));
+        assert!(s.insert_graph(&g3));
+        assert!(!s.insert_graph(&g4));
+
+        assert_eq!(s.len(), 2);
+        assert!(s.contains_graph(&g) && s.contains_graph(&g2));
+    }
+
+    #[test]
+    fn graph_key_set_updates() {
+
+        let mut g = gen_test_graph();
+        let other = generate_random_graph(12, 0.3);
+        let mut s = GraphKeySet::new();
+        s.insert_graph(&g);
+        s.insert_graph(&other);
+
+        // Add the edge 0 - 1 if missing, remove it otherwise
+        let old_key = GraphKey::new(&g);
+        match g.find_edge(0.into(), 1.into()) {
+            Some(e) => { g.remove_edge(e); }
+            None => { g.add_edge(0.into(), 1.into(), ()); }
+        }
+        let key = s.update_graph(&old_key, &g);
+
+        assert_eq!(key, GraphKey::new(&g));
+        assert!(s.contains_graph(generate_permutated_graph(&g)));
+        assert!(!s.contains_graph(gen_test_graph()));
+        assert!(s.contains_graph(&other));
+        assert_eq!(s.len(), 2);
+
+        // An edit keeping the class leaves the set as is
+        assert_eq!(s.update_graph(&key, generate_permutated_graph(&g)), key);
+        assert_eq!(s.len(), 2);
+    }
+
+
+    #[test]
+    fn is_isomorphic_test() {
+
+        for _ in 0..100 {
+            let g1 = generate_random_graph(500, 0.05);
+            let g2 = generate_random_graph(500, 0.05);
+            let g3 = generate_permutated_graph(&g1);
+
+            let key1 = GraphKey::new(&g1);
+            let key2 = GraphKey::new(&g2);
+            let key3 = GraphKey::new(&g3);
+
+            assert_eq!(is_isomorphic(&g1, &g2), key1 == key2);
+            assert_eq!(key1, key3);
+        }
+    }
+
+    #[test]
+    fn fast_isomorphism_checks() {
+
+        for _ in 0..50 {
+            let g1 = generate_random_graph(20, 0.3);
+            let g2 = generate_random_graph(20, 0.3);
+            let g3 = generate_permutated_graph(&g1);
+
+            assert_eq!(are_isomorphic_fast(&g1, &g2), is_isomorphic(&g1, &g2));
+            assert_eq!(compare_graphs(&g1, &g3), (true, Check::Search));
+        }
+
+        // Each check stops at the first invariant telling the graphs apart
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let triangles = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+        let p6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5)]);
+        let star = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (0, 4), (0, 5)]);
+        let p5 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        let triangle_and_edge = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4)]);
+
+        assert_eq!(compare_graphs(&c6, &p6), (false, Check::Counts));
+        assert_eq!(compare_graphs(&p6, &star), (false, Check::Degrees));
+        assert_eq!(compare_graphs(&p5, &triangle_and_edge), (false, Check::Refinement));
+        assert_eq!(compare_graphs(&c6, &triangles), (false, Check::Search));
+
+        // The leaves of h are compared with the key of g value by value: the
+        // first row of a leaf of the triangles already differs from C6
+        let key = GraphKey::new(&c6);
+        let explored = explore(Neighbors(&triangles), Colouring::new(&triangles), Colouring::select_cell_smallest_first, None, None).unwrap();
+        for leaf in explored.leaves.iter() {
+            let (order, produced) = compare_description(Neighbors(&triangles), leaf, &key.0);
+            assert_ne!(order, Ordering::Equal);
+            assert!(produced < key.0.len(), "{} values produced out of {}", produced, key.0.len());
+        }
+        let (order, produced) = compare_description(Neighbors(&c6), &canonical_search(&c6).leaf, &key.0);
+        assert_eq!((order, produced), (Ordering::Equal, key.0.len()));
+    }
+
+    #[test]
+    fn prefilters() {
+
+        let g = generate_random_graph(30, 0.2);
+        let prefilter = GraphPrefilter::new(&g);
+        assert!(prefilter.could_match(&GraphPrefilter::new(&generate_permutated_graph(&g))));
+        assert_eq!(compare_graphs(&g, &generate_permutated_graph(&g)), (true, Check::Search));
+
+        // Moving an edge end from a node of degree 2 to another: same counts,
+        // another maximal degree
+        let p4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        let star = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (1, 3)]);
+        let (prefilter_p4, prefilter_star) = (GraphPrefilter::new(&p4), GraphPrefilter::new(&star));
+        assert_eq!((prefilter_p4.max_degree, prefilter_star.max_degree), (2, 3));
+        assert!(!prefilter_p4.could_match(&prefilter_star));
+        assert_eq!(compare_graphs(&p4, &star), (false, Check::Degrees));
+
+        // Same degrees: the prefilters match, the search tells the graphs apart
+        let c6 = UnGraph::<usize, ()>::from_edges((0..6).map(|u| (u, (u + 1) % 6)));
+        let triangles = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+        assert!(GraphPrefilter::new(&c6).could_match(&GraphPrefilter::new(&triangles)));
+        assert_eq!(compare_graphs(&c6, &triangles), (false, Check::Search));
+    }
+
+    #[test]
+    fn comparison_with_stored_canonical_graphs() {
+
+        let g = generate_random_graph(20, 0.3);
+        let stored = GraphKey::to_record(&g).graph;
+
+        for _ in 0..10 {
+            let candidate = if thread_rng().gen_bool(0.5) { generate_permutated_graph(&g) } else { generate_random_graph(20, 0.3) };
+            assert_eq!(GraphKey::canonical_equals(&stored, &candidate), GraphKey::new(&candidate) == GraphKey::new(&g));
+        }
+
+        // The stored graph is taken as is : of the three labellings of the path
+        // on 3 nodes, only the canonical one matches
+        let paths = [[(1, 0), (0, 2)], [(0, 1), (1, 2)], [(0, 2), (2, 1)]].map(UnGraph::<usize, ()>::from_edges);
+        let canonical = GraphKey::to_record(&paths[0]).graph;
+        assert!(paths.iter().all(|p| GraphKey::canonical_equals(&canonical, p)));
+        assert_eq!(paths.iter().filter(|p| GraphKey::canonical_equals(p, &canonical)).count(), 1);
+    }
+
+    #[test]
+    fn equality_with_search() {
+
+        for _ in 0..20 {
+            let g1 = generate_random_graph(20, 0.3);
+            let g2 = generate_random_graph(20, 0.3);
+            let key = GraphKey::new(&g1);
+            assert!(key.equals_graph_with_search(&generate_permutated_graph(&g1)));
+            assert_eq!(key.equals_graph_with_search(&g2), key == GraphKey::new(&g2));
+        }
+
+        // The 6-cycle and two triangles are 2-regular, so the root is kept, but
+        // the children of the root cannot reach the rows of the other key
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let triangles = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+        for (g, h) in [(&c6, &triangles), (&triangles, &c6)] {
+            let (equal, explored) = GraphKey::new(g).match_search(h);
+            assert!(!equal);
+            assert_eq!(explored, 6);
+            assert!(explored < canonical_search(h).explored);
+        }
+        assert!(GraphKey::new(&c6).match_search(&generate_permutated_graph(&c6)).0);
+
+        // Regular graphs keep more of the tree
+        for seed in 0..10 {
+            let g = generators::random_regular(14, 3, seed);
+            let key = GraphKey::new(&g);
+            assert!(key.equals_graph_with_search(&generators::permute(&g, seed)));
+            let h = generators::random_regular(14, 3, seed + 10);
+            assert_eq!(key.equals_graph_with_search(&h), key == GraphKey::new(&h));
+        }
+
+        // Moving an edge of a random graph
+        let g = generate_random_graph(30, 0.2);
+        let mut h = generate_permutated_graph(&g);
+        let e = h.edge_indices().next().unwrap();
+        let (u, _) = h.edge_endpoints(e).unwrap();
+        h.remove_edge(e);
+        if let Some(v) = h.node_indices().find(|v| *v != u && h.find_edge(u, *v).is_none()) {
+            h.add_edge(u, v, ());
+            let (equal, explored) = GraphKey::new(&g).match_search(&h);
+            assert_eq!(equal, GraphKey::new(&g) == GraphKey::new(&h));
+            assert!(explored <= canonical_search(&h).explored);
+        }
+
+        // Different node counts are told apart without searching, and self-loops
+        // fall back to the key
+        assert_eq!(GraphKey::new(&g).match_search(&gen_test_graph()), (false, 0));
+        let looped = UnGraph::<usize, ()>::from_edges([(0, 0), (0, 1)]);
+        assert!(GraphKey::new(&looped).equals_graph_with_search(&looped));
+        assert!(GraphKey::new(&looped).equals_graph_with_search(&UnGraph::<usize, ()>::from_edges([(0, 1), (1, 1)])));
+    }
+
+    #[test]
+    fn structural_and_weighted_keys() {
+
+        // Path 0 - 1 - 2 weighted by index, and the same path with the weights
+        // of the ends swapped, or moved to the middle
+        let mut g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        let mut ends_swapped = g.clone();
+        let mut middle = g.clone();
+        for i in 0..3 {
+            g[NodeIndex::new(i)] = i;
+            ends_swapped[NodeIndex::new(i)] = 2 - i;
+            middle[NodeIndex::new(i)] = [0, 2, 1][i];
+        }
+
+        assert_eq!(GraphKey::structural(&g), GraphKey::new(&g));
+        assert_eq!(GraphKey::structural(&middle), GraphKey::structural(&g));
+        assert_eq!(GraphKey::weighted(&ends_swapped), GraphKey::weighted(&g));
+        assert_ne!(GraphKey::weighted(&middle), GraphKey::weighted(&g));
+        assert_eq!(GraphKey::weighted(&g), GraphKey::new_with_labels(&g, &[Some(0usize), Some(1), Some(2)]));
+
+        // Uniform weights
+        let mut h = generate_permutated_graph(&gen_test_graph());
+        h.node_weights_mut().for_each(|w| *w = 1);
+        assert_eq!(GraphKey::structural(&h), GraphKey::new(&gen_test_graph()));
+        assert_eq!(GraphKey::weighted(&h), GraphKey::new_with_labels(&h, &[Some(1usize); 10]));
+
+        // Adding the same amount to every weight
+        let mut shifted = g.clone();
+        shifted.node_weights_mut().for_each(|w| *w += 1);
+        assert_ne!(GraphKey::weighted(&shifted), GraphKey::weighted(&g));
+        let mut doubled = h.clone();
+        doubled.node_weights_mut().for_each(|w| *w = 2);
+        assert_ne!(GraphKey::weighted(&doubled), GraphKey::weighted(&h));
+    }
+
+    #[test]
+    fn conformance_on_mixed_graphs() {
+
+        let g = generate_random_graph(30, 0.2);
+        let graphs = vec![
+            g.clone(),
+            generate_random_graph(30, 0.2),
+            generate_permutated_graph(&g),
+            gen_test_graph(),
+            generate_random_graph(30, 0.1),
+            generate_permutated_graph(&gen_test_graph()),
+            generate_permutated_graph(&g),
+        ];
+        assert_eq!(conformance_check(&graphs), Ok(()));
+        assert_eq!(conformance_check::<Graph<usize, (), Undirected>>(&[]), Ok(()));
+    }
+
+    #[test]
+    fn cell_selectors() {
+
+        // Triangle + K6 : refinement splits the two components, leaving a
+        // cell of 3 nodes and a cell of 6 nodes.
+        let mut edges = vec![(0, 1), (1, 2), (2, 0)];
+        for u in 3..9 {
+            for v in (u+1)..9 {
+                edges.push((u, v));
+            }
+        }
+        let g1 = UnGraph::<usize, ()>::from_edges(edges);
+        let g2 = generate_permutated_graph(&g1);
+
+        let first = |g| search(Neighbors(g), Colouring::new(g), Colouring::select_cell_v1, LeafSelection::MaxDescriptor);
+        let smallest = |g| search(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor);
+
+        assert_eq!(first(&g1).descriptor, first(&g2).descriptor);
+        assert_eq!(smallest(&g1).descriptor, smallest(&g2).descriptor);
+        assert_eq!(smallest(&g1).descriptor, GraphKey::new(&g2).0);
+        assert_eq!(first(&g1).descriptor, smallest(&g1).descriptor);
+        assert!(smallest(&g1).explored < first(&g1).explored);
+    }
+
+    #[test]
+    fn last_children_take_the_parent_colouring() {
+
+        // Random graphs are often discrete after the first refinement, with
+        // nothing to clone
+        for g in [gen_test_graph(), generate_random_graph(20, 0.2)] {
+            let result = canonical_search(&g);
+            assert!(result.clones <= result.explored);
+            assert_eq!(result.descriptor, GraphKey::new(&generate_permutated_graph(&g)).0);
+        }
+        let result = canonical_search(&gen_test_graph());
+        assert!(result.clones < result.explored);
+
+        // A 6-cycle is vertex-transitive : the root has 6 children, the last
+        // of which reuses its colouring
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let result = canonical_search(&c6);
+        assert_eq!(result.group_order, 12);
+        assert!(result.clones < result.explored);
+    }
+
+    #[test]
+    fn depth_first_keys() {
+
+        for g in [gen_test_graph(), generate_random_graph(20, 0.2)] {
+            assert_eq!(GraphKey::new_depth_first(&g), GraphKey::new(&g));
+            assert_eq!(GraphKey::new_depth_first(&generate_permutated_graph(&g)), GraphKey::new(&g));
+        }
+
+        // Vertex-transitive graphs, where every branch reaches a leaf
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let result = search_depth_first(Neighbors(&c6), Colouring::new(&c6), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor);
+        assert_eq!(result.descriptor, GraphKey::new(&c6).0);
+        assert_eq!(result.group_order, 12);
+
+        let mut edges = vec![(0, 1), (1, 2), (2, 0)];
+        for u in 3..9 {
+            for v in (u+1)..9 {
+                edges.push((u, v));
+            }
+        }
+        let g = UnGraph::<usize, ()>::from_edges(edges);
+        assert_eq!(GraphKey::new_depth_first(&g), GraphKey::new(&generate_permutated_graph(&g)));
+    }
+
+    #[test]
+    fn mckay_keys() {
+
+        for g in [gen_test_graph(), generate_random_graph(20, 0.2), generate_random_graph(30, 0.1)] {
+            assert_eq!(GraphKey::new_mckay(&g), GraphKey::new_min(&g));
+            assert_eq!(GraphKey::new_mckay(&generate_permutated_graph(&g)), GraphKey::new_min(&g));
+        }
+
+        // Cycle and hypercube : automorphisms prune most of the tree
+        let c12 = UnGraph::<usize, ()>::from_edges((0..12).map(|u| (u, (u + 1) % 12)));
+        let q4 = UnGraph::<usize, ()>::from_edges((0..16u32).flat_map(|u| (0..4).map(move |b| (u, u ^ (1 << b)))).filter(|(u, v)| u < v));
+        for g in [c12, q4] {
+            let pruned = search_pruned(Neighbors(&g), Colouring::new(&g), Colouring::select_cell_smallest_first, LeafSelection::MinDescriptor);
+            let full = search(Neighbors(&g), Colouring::new(&g), Colouring::select_cell_smallest_first, LeafSelection::MinDescriptor);
+            assert_eq!(GraphKey::from_descriptor(pruned.descriptor), GraphKey::new_min(&g));
+            assert!(pruned.explored < full.explored, "{} nodes explored, against {}", pruned.explored, full.explored);
+            assert_eq!(GraphKey::new_mckay(&generate_permutated_graph(&g)), GraphKey::new_min(&g));
+        }
+    }
+
+    #[test]
+    fn generator_counts() {
+
+        let c6 = UnGraph::<usize, ()>::from_edges((0..6).map(|u| (u, (u + 1) % 6)));
+        let (key, generators) = GraphKey::new_with_generator_count(&c6);
+        assert_eq!(key, GraphKey::new_mckay(&c6));
+        assert!(generators >= 2, "{} generators", generators);
+
+        // Asymmetric: every leaf gives another descriptor
+        let g = crate::generators::random_gnp(20, 0.3, 4);
+        assert_eq!(GraphKey::automorphism_group_order(&g), Some(1));
+        assert_eq!(GraphKey::new_with_generator_count(&g), (GraphKey::new_min(&g), 0));
+    }
+
+    #[test]
+    fn undo_splits_restores_the_colouring() {
+
+        let g = gen_test_graph();
+        let mut gc = Colouring::new(&g);
+        gc.refine(&g);
+        let (cell_count, colors) = (gc.get_cell_count(), (0..10).map(|u| gc.color_of(u)).collect::<Vec<usize>>());
+
+        let target = gc.select_cell_smallest_first();
+        gc.individualize_and_refine(target, gc.get_cell_members(target)[0], &g);
+        assert!(gc.get_cell_count() > cell_count);
+
+        gc.undo_splits(cell_count);
+        assert_eq!(gc.get_cell_count(), cell_count);
+        assert_eq!((0..10).map(|u| gc.color_of(u)).collect::<Vec<usize>>(), colors);
+        assert!(gc.colors_are_contiguous());
+    }
+
+    #[test]
+    fn comparator_keys() {
+
+        for g in [gen_test_graph(), generate_random_graph(20, 0.2)] {
+            let h = generate_permutated_graph(&g);
+            assert_eq!(GraphKey::new_with_comparator(&g, |a, b| b.cmp(a)), GraphKey::new_min(&g));
+            assert_eq!(GraphKey::new_with_comparator(&h, |a, b| b.cmp(a)), GraphKey::new_min(&g));
+            assert_eq!(GraphKey::new_with_comparator(&g, |a, b| a.cmp(b)), GraphKey::new(&g));
+
+            // Another total order : sums of the values first, then lexicographic
+            let by_sum = |a : &[usize], b : &[usize]| a.iter().fold(0usize, |s, v| s.wrapping_add(*v)).cmp(&b.iter().fold(0usize, |s, v| s.wrapping_add(*v))).then(a.cmp(b));
+            assert_eq!(GraphKey::new_with_comparator(&g, by_sum), GraphKey::new_with_comparator(&h, by_sum));
+        }
+    }
+
+    #[test]
+    fn leaf_selections() {
+
+        for _ in 0..10 {
+            let g = generate_random_graph(20, 0.2);
+            let h = generate_permutated_graph(&g);
+
+            for selection in [LeafSelection::MaxDescriptor, LeafSelection::MinDescriptor, LeafSelection::MaxTrace] {
+                assert_eq!(GraphKey::new_with_selection(&g, selection), GraphKey::new_with_selection(&h, selection));
+            }
+            assert_eq!(GraphKey::new_with_selection(&g, LeafSelection::MaxDescriptor), GraphKey::new(&g));
+        }
+
+        // Regular graphs leave many leaves to the final selection
+        for seed in 0..20 {
+            let g = crate::generators::random_regular(16, 3, seed);
+            let h = crate::generators::permute(&g, seed);
+            for selection in [LeafSelection::MaxDescriptor, LeafSelection::MinDescriptor, LeafSelection::MaxTrace] {
+                assert_eq!(GraphKey::new_with_selection(&g, selection), GraphKey::new_with_selection(&h, selection));
+            }
+        }
+
+        // Both criteria count the same automorphisms
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let min = search(Neighbors(&c6), Colouring::new(&c6), Colouring::select_cell_smallest_first, LeafSelection::MinDescriptor);
+        assert_eq!(min.group_order, 12);
+        assert!(min.descriptor <= GraphKey::new(&c6).0);
+    }
+
+    #[test]
+    fn canon_configs() {
+
+        let unlimited = CanonConfig::default();
+        let first = CanonConfig { selector : SelectorKind::FirstNonSingleton, ..CanonConfig::default() };
+        let capped = CanonConfig { max_refine_rounds : Some(1), ..CanonConfig::default() };
+
+        for g in [gen_test_graph(), generate_random_graph(20, 0.2)] {
+            let h = generate_permutated_graph(&g);
+            assert_eq!(GraphKey::new_with_config(&g, &unlimited), Ok(GraphKey::new(&g)));
+            assert_eq!(GraphKey::new_with_config(&g, &first), GraphKey::new_with_config(&h, &first));
+        }
+
+        // Capping the rounds weakens the pruning, so keep the graph small
+        let g = gen_test_graph();
+        assert_eq!(GraphKey::new_with_config(&g, &capped), GraphKey::new_with_config(&generate_permutated_graph(&g), &capped));
+
+        // Without refinement, every node of a triangle plus a pendant node is
+        // individualized in turn
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3)]);
+        let no_refine = CanonConfig { max_refine_rounds : Some(0), ..CanonConfig::default() };
+        let key = GraphKey::new_with_config(&g, &no_refine).unwrap();
+        assert_eq!(GraphKey::new_with_config(&generate_permutated_graph(&g), &no_refine), Ok(key));
+
+        // A tiny budget stops the search, unless refinement alone is enough
+        let tiny = CanonConfig { search_node_budget : Some(0), ..CanonConfig::default() };
+        assert_eq!(GraphKey::new_with_config(&gen_test_graph(), &tiny), Err(CanonError::BudgetExceeded { budget : 0 }));
+        let spider = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)]);
+        assert_eq!(GraphKey::new_with_config(&spider, &tiny), Ok(GraphKey::new(&spider)));
+    }
+
+    #[test]
+    fn group_order_prefixes() {
+
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        for g in [gen_test_graph(), c6.clone(), generate_random_graph(30, 0.3)] {
+            let key = GraphKey::new_with_group_order_prefix(&g, 10_000).unwrap();
+            assert_eq!(Some(key.get_descriptor()[0] as u128), GraphKey::automorphism_group_order(&g));
+            assert_eq!(key.get_descriptor()[1..], GraphKey::new(&g).get_descriptor()[..]);
+            assert_eq!(GraphKey::new_with_group_order_prefix(&generate_permutated_graph(&g), 10_000), Some(key));
+        }
+
+        // The 12 automorphisms of C6 need more than 12 tree nodes, the spider
+        // none at all
+        assert_eq!(GraphKey::new_with_group_order_prefix(&c6, 12), None);
+        let spider = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)]);
+        assert_eq!(GraphKey::new_with_group_order_prefix(&spider, 0).map(|key| key.get_descriptor()[0]), Some(1));
+
+        // 8! leaves on 8 isolated nodes
+        let mut isolated = UnGraph::<usize, ()>::default();
+        (0..8).for_each(|u| { isolated.add_node(u); });
+        assert_eq!(GraphKey::new_with_group_order_prefix(&isolated, 10_000), None);
+    }
+
+    #[test]
+    fn component_prefixes() {
+
+        // Triangle, path on 4 nodes and an isolated node
+        let mut g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 6)]);
+        g.add_node(7);
+        assert_eq!(component_sizes(&g), vec![4, 3, 1]);
+
+        let key = GraphKey::new_with_component_prefix(&g);
+        assert_eq!(key.get_descriptor()[..4], [3, 4, 3, 1]);
+        assert_eq!(key.get_descriptor()[4..], GraphKey::new(&g).get_descriptor()[..]);
+        assert_eq!(GraphKey::new_with_component_prefix(&generate_permutated_graph(&g)), key);
+
+        // Test graph plus a copy of the triangle and the path
+        let mut h = gen_test_graph();
+        let offset = h.node_count();
+        (0..8).for_each(|i| { h.add_node(offset + i); });
+        for e in g.edge_indices() {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            h.add_edge(NodeIndex::new(offset + u.index()), NodeIndex::new(offset + v.index()), ());
+        }
+        let key = GraphKey::new_with_component_prefix(&h);
+        assert_eq!(key.get_descriptor()[..5], [4, 10, 4, 3, 1]);
+        assert_eq!(GraphKey::new_with_component_prefix(&generate_permutated_graph(&h)), key);
+    }
+
+    #[test]
+    fn search_depths() {
+
+        // Refinement alone makes an asymmetric tree discrete
+        let tree = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)]);
+        assert_eq!(GraphKey::new_with_depth(&tree), (GraphKey::new(&tree), 0));
+
+        // A cycle needs a node and one of its neighbors to be individualized
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        assert_eq!(GraphKey::new_with_depth(&c6), (GraphKey::new(&c6), 2));
+
+        // The test graph needs three
+        let (_, depth) = GraphKey::new_with_depth(&gen_test_graph());
+        assert_eq!(depth, 3);
+    }
+
+    #[test]
+    fn automorphism_checks() {
+
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+
+        // Rotation and reflection
+        assert!(is_automorphism(&c6, &[1, 2, 3, 4, 5, 0]));
+        assert!(is_automorphism(&c6, &[0, 5, 4, 3, 2, 1]));
+        assert!(is_automorphism(&c6, &[0, 1, 2, 3, 4, 5]));
+
+        // Swapping two adjacent nodes breaks the cycle
+        assert!(!is_automorphism(&c6, &[1, 0, 2, 3, 4, 5]));
+
+        // Not permutations of 0..6
+        assert!(!is_automorphism(&c6, &[1, 2, 3, 4, 5]));
+        assert!(!is_automorphism(&c6, &[1, 2, 3, 4, 5, 1]));
+        assert!(!is_automorphism(&c6, &[1, 2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn isomorphism_classes_ids() {
+
+        let mut g = generate_random_graph(100, 0.1);
+
+        let g1 = generate_permutated_graph(&g);
+        let g2 = generate_permutated_graph(&g);
+
+        match g.find_edge(0.into(), 1.into()) {
+            Some(_ix) => { g.remove_edge(_ix); }
+            None => { g.add_edge(0.into(), 1.into(), ()); }
+        }
+
+        let g3 = generate_permutated_graph(&g);
+        let g4 = generate_permutated_graph(&g);
+
+        assert_eq!(isomorphism_classes(&[g3, g1, g4, g2]), vec![0, 1, 0, 1]);
+        assert!(isomorphism_classes::<Graph<usize, (), Undirected>>(&[]).is_empty());
+    }
+
+    #[test]
+    fn dedup_verified_keeps_representatives() {
+
+        let g = generate_random_graph(60, 0.1);
+        let h = generate_random_graph(60, 0.1);
+
+        let graphs = vec![
+            generate_permutated_graph(&g), generate_permutated_graph(&h),
+            generate_permutated_graph(&g), generate_permutated_graph(&g),
+            generate_permutated_graph(&h),
+        ];
+
+        assert_eq!(dedup_verified(&graphs), Ok(vec![0, 1]));
+    }
+
+    #[test]
+    fn clone_and_default() {
+
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |k : &GraphKey| {
+            let mut h = DefaultHasher::new();
+            k.hash(&mut h);
+            h.finish()
+        };
+
+        let key = GraphKey::new(&gen_test_graph());
+        let copy = key.clone();
+
+        assert_eq!(key, copy);
+        assert_eq!(hash(&key), hash(&copy));
+
+        let empty = UnGraph::<usize, ()>::new_undirected();
+        assert_eq!(GraphKey::default(), GraphKey::new(&empty));
+        assert_ne!(GraphKey::default(), key);
+    }
+
+    #[test]
+    fn rooted_keys() {
+
+        // Path 0 - 1 - 2 - 3 - 4, and the same path relabelled as 4 - 2 - 0 - 1 - 3
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        let h = UnGraph::<usize, ()>::from_edges([(4, 2), (2, 0), (0, 1), (1, 3)]);
+
+        assert_eq!(GraphKey::new_rooted(&g, 0), GraphKey::new_rooted(&h, 4));
+        assert_eq!(GraphKey::new_rooted(&g, 0), GraphKey::new_rooted(&g, 4));
+        assert_eq!(GraphKey::new_rooted(&g, 2), GraphKey::new_rooted(&h, 0));
+
+        // Rooted at an end vs rooted at the middle
+        assert_ne!(GraphKey::new_rooted(&g, 0), GraphKey::new_rooted(&g, 2));
+        assert_ne!(GraphKey::new_rooted(&g, 0), GraphKey::new_rooted(&h, 0));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_final_selection() {
+
+        let mut graphs = vec![gen_test_graph()];
+        graphs.extend((0..30).map(|i| generate_random_graph(10 + i % 15, 0.3)));
+
+        for g in graphs.iter() {
+            let colors = |c : &Colouring| (0..g.node_count()).map(|u| c.color_of(u)).collect::<Vec<usize>>();
+            assert_eq!(GraphKey::new_parallel(g), GraphKey::new(g));
+
+            // The children of a tree node are visited in the order of a HashSet :
+            // compare both selections on the same leaves
+            for selection in [LeafSelection::MaxDescriptor, LeafSelection::MinDescriptor, LeafSelection::MaxTrace] {
+                let explored = explore(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, None, None).unwrap();
+                let sequential = select_leaf(Neighbors(g), explored.clone(), selection);
+                let parallel = select_leaf_parallel(Neighbors(g), explored, selection);
+                assert_eq!(parallel.descriptor, sequential.descriptor);
+                assert_eq!(colors(&parallel.leaf), colors(&sequential.leaf));
+                assert_eq!(parallel.equivalent_leaves.iter().map(colors).collect::<Vec<_>>(), sequential.equivalent_leaves.iter().map(colors).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn search_orders() {
+
+        let best_first = CanonConfig { order : SearchOrder::BestFirst, ..CanonConfig::default() };
+        let petersen = UnGraph::<usize, ()>::from_edges([
+            (0, 1), (1, 2), (2, 3), (3, 4), (4, 0),
+            (5, 7), (7, 9), (9, 6), (6, 8), (8, 5),
+            (0, 5), (1, 6), (2, 7), (3, 8), (4, 9),
+        ]);
+
+        let mut graphs = vec![gen_test_graph(), petersen];
+        graphs.extend((0..20).map(|i| generate_random_graph(10 + i, 0.3)));
+
+        for g in graphs.iter() {
+            assert_eq!(GraphKey::new_with_config(g, &best_first), Ok(GraphKey::new(g)));
+
+            // Same leaves, reached through different nodes
+            let explore_with = |order| search_with_limits(Neighbors(g), Colouring::new(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor, order, None, None).unwrap();
+            let (bfs, best) = (explore_with(SearchOrder::Bfs), explore_with(SearchOrder::BestFirst));
+            assert_eq!(best.group_order, bfs.group_order);
+            assert_eq!(best.depth, bfs.depth);
+            assert_eq!(best.explored == 0, bfs.explored == 0);
+        }
+
+        // A triangle beside a square, and a 4-regular graph: the levels are
+        // not pruned in the same order, best-first creates fewer nodes
+        let c3_c4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 6), (6, 3)]);
+        for g in [c3_c4, crate::generators::random_regular(8, 4, 0)] {
+            let explore_with = |order| search_with_limits(Neighbors(&g), Colouring::new(&g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor, order, None, None).unwrap();
+            let (bfs, best) = (explore_with(SearchOrder::Bfs), explore_with(SearchOrder::BestFirst));
+            assert_eq!(best.descriptor, bfs.descriptor);
+            assert!(best.explored < bfs.explored, "{} nodes explored best-first, against {}", best.explored, bfs.explored);
+            assert!(best.clones < bfs.clones, "{} clones best-first, against {}", best.clones, bfs.clones);
+        }
+
+        // The budget applies to both orders
+        let budget = CanonConfig { search_node_budget : Some(3), ..best_first };
+        assert_eq!(GraphKey::new_with_config(&graphs[1], &budget), Err(CanonError::BudgetExceeded { budget : 3 }));
+    }
+
+    #[test]
+    fn edge_rooted_keys() {
+
+        // All the edges of a cycle are alike, in both directions
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+        let key = GraphKey::new_edge_rooted(&c6, 0, 1, false).unwrap();
+        assert_ne!(key, GraphKey::new(&c6));
+        let h = UnGraph::<usize, ()>::from_edges([(2, 5), (5, 1), (1, 3), (3, 0), (0, 4), (4, 2)]);
+        assert_eq!(GraphKey::new_edge_rooted(&h, 1, 3, false).unwrap(), GraphKey::new_edge_rooted(&c6, 3, 4, false).unwrap());
+        assert_eq!(GraphKey::new_edge_rooted(&c6, 4, 3, true).unwrap(), GraphKey::new_edge_rooted(&c6, 0, 1, true).unwrap());
+        assert_ne!(GraphKey::new_edge_rooted(&c6, 0, 1, true).unwrap(), key);
+
+        // Path 0 - 1 - 2 - 3 : end edges and middle edge
+        let p4 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(GraphKey::new_edge_rooted(&p4, 0, 1, false).unwrap(), GraphKey::new_edge_rooted(&p4, 3, 2, false).unwrap());
+        assert_ne!(GraphKey::new_edge_rooted(&p4, 0, 1, false).unwrap(), GraphKey::new_edge_rooted(&p4, 1, 2, false).unwrap());
+        assert_eq!(GraphKey::new_edge_rooted(&p4, 0, 1, false).unwrap(), GraphKey::new_edge_rooted(&p4, 1, 0, false).unwrap());
+        assert_ne!(GraphKey::new_edge_rooted(&p4, 0, 1, true).unwrap(), GraphKey::new_edge_rooted(&p4, 1, 0, true).unwrap());
+        assert_eq!(GraphKey::new_edge_rooted(&p4, 1, 2, true).unwrap(), GraphKey::new_edge_rooted(&p4, 2, 1, true).unwrap());
+
+        // The marked pair must be an edge of the graph
+        assert_eq!(GraphKey::new_edge_rooted(&p4, 0, 2, false), Err(EdgeError::NotAnEdge { u : 0, v : 2 }));
+        assert_eq!(GraphKey::new_edge_rooted(&p4, 3, 4, true), Err(EdgeError::NodeOutOfRange { node : 4, node_count : 4 }));
+    }
+
+    #[test]
+    fn key_generation_regular_union() {
+
+        // Frucht graph (asymmetric) and cube : both 3-regular, so refinement
+        // alone cannot split anything and the search goes several levels deep.
+        let mut edges = Vec::new();
+        for (i, d) in [-5, -2, -4, 2, 5, -2, 2, 5, -2, -5, 4, 2].into_iter().enumerate() {
+            let (i, j) = (i as u32, (i as i32 + d).rem_euclid(12) as u32);
+            edges.push((i, (i + 1) % 12));
+            if i < j { edges.push((i, j)); }
+        }
+        for u in 0..8 {
+            for k in [1, 2, 4] {
+                if u < u ^ k { edges.push((12 + u, 12 + (u ^ k))); }
+            }
+        }
+
+        let g = UnGraph::<usize, ()>::from_edges(edges);
+        let key = GraphKey::new(&g);
+
+        assert_eq!(GraphKey::automorphism_group_order(&g), Some(48));
+        for _ in 0..20 {
+            assert_eq!(GraphKey::new(&generate_permutated_graph(&g)), key);
+        }
+    }
+
+    #[test]
+    fn discrete_early_key() {
+
+        // Smallest asymmetric tree : refinement alone makes it discrete
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)]);
+        let result = canonical_search(&g);
+        assert_eq!(result.explored, 0);
+        assert_eq!(result.group_order, 1);
+        assert_eq!(result.descriptor, compute_descriptor(&result.leaf.compute_graph_from_discrete(&g)));
+
+        // Refinement is invariant, so every relabelling is discrete early too and
+        // must select the same canonical leaf.
+        let key = GraphKey::new(&g);
+        for _ in 0..10 {
+            assert_eq!(GraphKey::new(&generate_permutated_graph(&g)), key);
+        }
+    }
+
+    #[test]
+    fn trivial_refinements() {
+
+        let k5 = UnGraph::<usize, ()>::from_edges((0..5).flat_map(|u| ((u + 1)..5).map(move |v| (u, v))));
+        assert!(GraphKey::is_refinement_trivial(&k5));
+        assert!(GraphKey::is_refinement_trivial(&UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)])));
 
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
+        assert!(!GraphKey::is_refinement_trivial(&path));
 
-//
-// GraphKey object
-//
+        // Triangle and a disjoint edge : regular components of different degrees
+        assert!(!GraphKey::is_refinement_trivial(&UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (3, 4)])));
+    }
 
-#[derive(Debug, Hash, PartialEq, Eq)]
-pub struct GraphKey(Vec<usize>);
+    #[test]
+    fn discrete_refinements() {
 
-impl GraphKey {
-    pub fn get_descriptor(&self) -> &Vec<usize> {
-        &self.0
+        // Spider with legs of length 1, 2 and 3
+        assert!(GraphKey::refine_is_discrete(&UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)])));
+        assert!(!GraphKey::refine_is_discrete(&generators::random_regular(16, 3, 1)));
+
+        // Refinement finds the orbits of a tree, so it is discrete iff the tree
+        // is asymmetric
+        let mut rng = thread_rng();
+        for _ in 0..20 {
+            let tree = UnGraph::<usize, ()>::from_edges((1..12u32).map(|u| (rng.gen_range(0..u), u)));
+            assert_eq!(GraphKey::refine_is_discrete(&tree), GraphKey::automorphism_group_order(&tree) == Some(1));
+        }
     }
-}
 
-impl GraphKey {
-    pub fn new<G>(g : G) -> GraphKey 
-    where
-        G : NodeCompactIndexable + IntoNeighbors + IntoEdges
-    {
+    #[test]
+    fn automorphism_group_orders() {
 
-        // Generate first colouring & first refine.
-        let mut gc = Colouring::new(g);
-        gc.refine(g);
+        let k4 = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+        let c6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+
+        // Path 0 - 1 - 2 - 3 - 4 - 5 with a chord 2 - 4
+        let asymmetric = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (2, 4)]);
+
+        assert_eq!(GraphKey::automorphism_group_order(&k4), Some(24));
+        assert_eq!(GraphKey::automorphism_group_order(&c6), Some(12));
+        assert_eq!(GraphKey::automorphism_group_order(&generate_permutated_graph(&c6)), Some(12));
+        assert_eq!(GraphKey::automorphism_group_order(&asymmetric), Some(1));
+
+        // Far too many leaves to enumerate : 20 isolated nodes, and K35 whose
+        // 35! automorphisms do not fit in a u128
+        let mut isolated = UnGraph::<usize, ()>::default();
+        (0..20).for_each(|u| { isolated.add_node(u); });
+        assert_eq!(GraphKey::automorphism_group_order(&isolated), Some((1..=20).product()));
+        let k35 = UnGraph::<usize, ()>::from_edges((0..35).flat_map(|u| (u + 1..35).map(move |v| (u, v))));
+        assert_eq!(GraphKey::automorphism_group_order(&k35), None);
+    }
 
-        // If gc is discrete, compute the associated key.
-        if gc.is_discrete() {
-            let descr = gc.compute_graph_from_discrete(g);
-            return GraphKey(compute_descriptor(&descr));
+    #[test]
+    fn canonical_hash_into_matches_key_hash() {
+
+        use std::collections::hash_map::DefaultHasher;
+
+        for g in [gen_test_graph(), generate_random_graph(50, 0.1)] {
+            let mut from_key = DefaultHasher::new();
+            GraphKey::new(&g).hash(&mut from_key);
+
+            let mut direct = DefaultHasher::new();
+            GraphKey::canonical_hash_into(&generate_permutated_graph(&g), &mut direct);
+
+            assert_eq!(from_key.finish(), direct.finish());
         }
+    }
 
-        // Otherwise, set up the tree for exploration.
-        let root = {
+    #[test]
+    fn key_stream_in_order() {
 
-            let target = gc.select_cell_v1();
-            let mut children = gc.get_cell_members(target);
-            children.sort_by(|a, b| b.cmp(a));
+        let graphs : Vec<_> = (0..6).map(|i| generate_random_graph(20 + i, 0.2)).collect();
+        let expected : Vec<GraphKey> = graphs.iter().map(GraphKey::new).collect();
 
-            TreeNode{
-                c : gc,
-                target_cell: target,
-                children,
-                son_in_exp_path: None,
-                son_k_dim : None,
-            }
-        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        let keys = key_stream(rx);
 
-        //
-        // 3. Main loop
-        //
-        //      * Follows the exploration path of Traces
-        //
+        for g in graphs.iter() {
+            tx.send(generate_permutated_graph(g)).unwrap();
+        }
+        drop(tx);
 
-        let mut next_list = Vec::from([root]);      // list of colourings to study on next level
-        let mut leaf_found = false;
+        assert_eq!(keys.iter().collect::<Vec<_>>(), expected);
+    }
 
-        // let mut leaves_colouing : Vec<Graph<usize, ()>> = Vec::new();
-        // let mut leaves_descriptors : Vec<Vec<usize>> = Vec::new();
+    #[test]
+    fn graph_tracker_updates() {
 
-        while !leaf_found { 
+        let mut g = gen_test_graph();
+        let mut tracker = GraphTracker::new();
 
-            let current_list = next_list;
-            next_list = Vec::new();
+        assert!(tracker.update(&g));
+        assert!(!tracker.update(&g));
+        assert!(!tracker.update(&generate_permutated_graph(&g)));
 
-            let mut best_k_dim = Kdim::new(0, vec![]);
+        let e = g.add_edge(0.into(), 1.into(), ());
+        assert!(tracker.update(&g));
+        assert!(!tracker.update(&generate_permutated_graph(&g)));
 
-            for node in current_list.into_iter() {
+        g.remove_edge(e);
+        assert!(tracker.update(&g));
+        assert_eq!(tracker.key(), Some(&GraphKey::new(&gen_test_graph())));
+    }
 
-                let mut node = node;
+    #[test]
+    fn labelled_keys() {
 
-                // Add son in exploration to next_list (losing ownership)
-                if let Some(b) = node.son_in_exp_path {
-                    let k_dim = node.son_k_dim.as_ref().unwrap();
-                    if b.c.is_discrete() { leaf_found = true; }
-                    if best_k_dim <= *k_dim { 
-                        if best_k_dim < *k_dim {
-                            next_list = Vec::new();
-                            best_k_dim = k_dim.clone();
-                        }
-                        next_list.push(*b);
-                    }
-                    node.son_in_exp_path = None;             
-                }
+        let g = gen_test_graph();
+        let h = generate_permutated_graph(&g);
 
-                while !node.children.is_empty() {
+        // Uniform labels : same structure, but the key is marked as labelled and
+        // holds the value of the label
+        assert_ne!(GraphKey::new_with_labels(&g, &[0; 10]), GraphKey::new(&g));
+        assert_eq!(GraphKey::new_with_labels(&g, &[0; 10]), GraphKey::new_with_labels(&h, &[0; 10]));
+        assert_ne!(GraphKey::new_with_labels(&g, &[0; 10]), GraphKey::new_with_labels(&h, &[1; 10]));
+        assert_ne!(GraphKey::new_with_labels(&g, &["b"; 10]), GraphKey::new_with_labels(&h, &["a"; 10]));
+        assert_eq!(GraphKey::new_with_labels(&g, &[0; 10]).get_descriptor()[0], LABELLED_MARKER);
 
-                    // Create new TreeNode from the individualization of a (graph) node from the target cell
-                    let _v = node.children.pop().unwrap();
-                    let mut _gc = node.c.clone();
-                    let new_color = _gc.individualize(node.target_cell, _v);
-                    let mut trace = _gc.refine(g);
-                    trace.insert(0, new_color);
-                    let mut k_dim = Kdim::new(_gc.get_cell_count(), trace);
+        let empty = UnGraph::<usize, ()>::new_undirected();
+        assert_ne!(GraphKey::new_with_labels::<_, usize>(&empty, &[]), GraphKey::new(&empty));
 
-                    // at each iteration, the ownership of the current node is given to the parent
-                    let mut ancestor_in_exp_path = &mut node;
-                    
-                    if best_k_dim > k_dim {
-                        continue;
-                    }
+        // C - C - O and C - C - N have labels in the same order
+        let chain = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        assert_ne!(GraphKey::new_with_labels(&chain, &['C', 'C', 'O']), GraphKey::new_with_labels(&chain, &['C', 'C', 'N']));
+    }
 
-                    if best_k_dim < k_dim {
-                        next_list = Vec::new();
-                        best_k_dim = k_dim.clone();
-                    }
+    #[test]
+    fn initial_color_keys() {
 
-                    // Compute experimental path
-                    loop {
-                        
-                        if _gc.is_discrete() {
-                            
-                            // TODO : check automorphisms
-
-                            let leaf = TreeNode{ 
-                                c : _gc, 
-                                target_cell: 0,
-                                children : vec![],
-                                son_in_exp_path: None, 
-                                son_k_dim : Some(k_dim)
-                            };
-
-                            ancestor_in_exp_path.son_in_exp_path = Some(Box::new(leaf));
-
-                            break;
-                        }
-                        
-                        let target = _gc.select_cell_v1();
-                        let children = _gc.get_cell_members(target);
-                        // children.sort_by(|a, b| b.cmp(a));             // TODO : delete
-                        let mut new_experimental_path_node = TreeNode{ 
-                            c : _gc, 
-                            target_cell: target, 
-                            children, 
-                            son_in_exp_path: None, 
-                            son_k_dim : Some(k_dim)
-                        };
+        // Ethanol C - C - O and dimethyl ether C - O - C, hydrogens omitted
+        const C : u64 = 6;
+        const O : u64 = 8;
+        let chain = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        let ethanol = GraphKey::new_with_colors(&chain, |u| [C, C, O][u]);
+        assert_ne!(GraphKey::new_with_colors(&chain, |u| [C, O, C][u]), ethanol);
+        assert_eq!(GraphKey::new_with_colors(&chain, |u| [O, C, C][u]), ethanol);
 
-                        let _v = new_experimental_path_node.children.pop().unwrap();
-                        _gc = new_experimental_path_node.c.clone();
-                        let new_color = _gc.individualize(new_experimental_path_node.target_cell, _v);
-                        let mut trace = _gc.refine(g);
-                        trace.insert(0, new_color);
-                        k_dim = Kdim::new(_gc.get_cell_count(), trace);
+        // Same order of the colors, but C - C - N is not ethanol
+        assert_ne!(GraphKey::new_with_colors(&chain, |u| [C, C, 7][u]), ethanol);
 
-                        // Give ownership of the new node to its parent & create a new &mut
-                        ancestor_in_exp_path.son_in_exp_path = Some(Box::new(new_experimental_path_node));
-                        ancestor_in_exp_path = ancestor_in_exp_path.son_in_exp_path.as_deref_mut().unwrap();
-                    }
-                    
-                    if let Some(_n) = node.son_in_exp_path {
-                        if _n.c.is_discrete() { leaf_found = true; }
-                        next_list.push(*_n);
-                        node.son_in_exp_path = None;
-                    }
-                }
-            }
+        let g = gen_test_graph();
+        let atoms = [C, O, C, C, 7, C, O, C, C, 1];
+        let key = GraphKey::new_with_colors(&g, |u| atoms[u]);
+        assert_ne!(key, GraphKey::new(&g));
+
+        // Colors follow the nodes through the permutation u -> 9 - u
+        let mut h = UnGraph::<usize, ()>::default();
+        (0..10).for_each(|u| { h.add_node(u); });
+        for e in g.edge_indices() {
+            let (u, v) = g.edge_endpoints(e).unwrap();
+            h.add_edge(NodeIndex::new(9 - u.index()), NodeIndex::new(9 - v.index()), ());
         }
+        assert_eq!(GraphKey::new_with_colors(&h, |u| atoms[9 - u]), key);
+    }
+
+    #[test]
+    fn node_kind_keys() {
 
-        let canonical = next_list[0].c.compute_graph_from_discrete(g);
-        let mut best_descriptor = compute_descriptor(&canonical);
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+        enum Kind { Add, Mul, Var, Const }
 
-        for leaf in next_list.into_iter().skip(1) {
-            let _canonical = leaf.c.compute_graph_from_discrete(g);
-            let _descriptor = compute_descriptor(&_canonical);
-            if _descriptor > best_descriptor {
-                best_descriptor = _descriptor;
+        impl Label for Kind {
+            fn write_bytes(&self, bytes : &mut Vec<u8>) {
+                bytes.push(*self as u8);
             }
         }
 
-        GraphKey(best_descriptor)
-    }
-}
+        // (a + b) * 2, with the nodes listed in two different orders
+        let g = UnGraph::<(), ()>::from_edges([(0, 1), (1, 2), (1, 3), (0, 4)]);
+        let g_kinds = [Kind::Mul, Kind::Add, Kind::Var, Kind::Var, Kind::Const];
+        let h = UnGraph::<(), ()>::from_edges([(4, 2), (2, 0), (2, 1), (4, 3)]);
+        let h_kinds = [Kind::Var, Kind::Var, Kind::Add, Kind::Const, Kind::Mul];
 
+        let key = GraphKey::with_node_kind(&g, |u| g_kinds[u.index()]);
+        assert_eq!(GraphKey::with_node_kind(&h, |u| h_kinds[u.index()]), key);
+        assert_eq!(GraphKey::new_with_labels(&g, &g_kinds), key);
 
+        // (a * b) + 2 has the same shape
+        let swapped = [Kind::Add, Kind::Mul, Kind::Var, Kind::Var, Kind::Const];
+        assert_ne!(GraphKey::with_node_kind(&g, |u| swapped[u.index()]), key);
 
-struct TreeNode {
-    c : Colouring,
-    target_cell : usize, 
-    children : Vec<usize>,
-    son_in_exp_path : Option<Box<TreeNode>>,
-    son_k_dim : Option<Kdim>,
-}
+        // Changing the kind of every node the same way
+        assert_ne!(GraphKey::with_node_kind(&g, |_| Kind::Var), GraphKey::with_node_kind(&g, |_| Kind::Const));
+    }
 
-fn compute_descriptor<G>(g : G) -> Vec<usize>
-where
-    G : NodeCompactIndexable + IntoNeighbors + IntoEdges
-{
-    let n = g.node_count();
-    let mut canonical = vec![n];
-    let mut prev_neigh;
+    #[test]
+    fn canonical_u64_matches_key_hash() {
 
-    for i in 0..(n-1)  {
-        prev_neigh = i;
-        let mut ordered_neighbors : Vec<usize>  = g.neighbors(g.from_index(i)).filter(|j| { g.to_index(*j) > i }).map(|j| { g.to_index(j) } ).collect();
-        ordered_neighbors.sort(); 
-        for j in ordered_neighbors {
-            canonical.push(j - prev_neigh);
-            prev_neigh = j;
+        let hash = |key : &GraphKey| {
+            let mut state = std::collections::hash_map::DefaultHasher::new();
+            key.hash(&mut state);
+            state.finish()
+        };
+
+        let mut graphs = vec![gen_test_graph(), UnGraph::<usize, ()>::new_undirected(), UnGraph::<usize, ()>::from_edges([(0, 0), (0, 1), (0, 1)])];
+        graphs.extend((0..20).map(|i| generate_random_graph(5 + i, 0.3)));
+
+        for g in graphs.iter() {
+            assert_eq!(canonical_u64(g), hash(&GraphKey::new(g)));
+            assert_eq!(canonical_u64(&generate_permutated_graph(g)), canonical_u64(g));
         }
-        canonical.push(n);
     }
-    
-    canonical
-}
 
-//
-//
-//
+    #[test]
+    fn class_keys() {
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use petgraph::graph::{NodeIndex, UnGraph};
-    use petgraph::{Graph, Undirected};
-    use rand::{Rng, thread_rng};
-    use rand::seq::SliceRandom;
-    use std::collections::HashSet;
-    use petgraph::algo::is_isomorphic;
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
 
-    fn gen_test_graph() -> Graph::<usize, (), Undirected> {
-    
-        let edges : Vec<(u32, u32)> = vec![
-            (0, 3), (0, 5), (0, 8), (1, 4), (1, 6), (1, 8),
-            (2, 5), (2, 7), (3, 6), (3, 9), (4, 7), (4, 9),
-            (5, 8), (7, 9)
-        ];
-    
-        UnGraph::from_edges(edges)
+        // Relabelled as 3 - 1 - 0 - 2, with the classes following the nodes
+        let h = UnGraph::<usize, ()>::from_edges([(3, 1), (1, 0), (0, 2)]);
+        assert_eq!(GraphKey::new_with_classes(&g, &[0, 1, 2, 2]), GraphKey::new_with_classes(&h, &[2, 1, 2, 0]));
+
+        // Same path and same class sizes, but the inner nodes swap classes : the
+        // graphs are only isomorphic through a map mixing the classes
+        assert_ne!(GraphKey::new_with_classes(&g, &[0, 1, 2, 2]), GraphKey::new_with_classes(&g, &[0, 2, 1, 2]));
+        assert_eq!(GraphKey::new(&g), GraphKey::new(&h));
+
+        // Class ids are part of the key, not only their order
+        let triangle = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        assert_ne!(GraphKey::new_with_classes(&triangle, &[0, 0, 1]), GraphKey::new_with_classes(&triangle, &[0, 0, 5]));
     }
 
-    
-    fn generate_random_graph(n : usize, p : f64) -> Graph::<usize, (), Undirected> {
-        
-        let mut rng = rand::thread_rng();
-        let mut g = UnGraph::<usize, ()>::new_undirected();
-        g.reserve_nodes(n);
-        (0..n).for_each(|i| { g.add_node(i); });
-        
-        for i in 0..n {
-            for j in (i+1)..n {
-                if rng.gen_range((0.)..(1.)) < p {
-                    g.add_edge(NodeIndex::new(i), NodeIndex::new(j), ());
-                }
-            }
+    #[test]
+    fn memory_footprints() {
+
+        let small = GraphKey::new(&UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]));
+        let large = GraphKey::new(&gen_test_graph());
+
+        // 10 nodes and 14 edges
+        assert_eq!(large.get_descriptor().len(), 24);
+
+        for key in [&small, &large] {
+            assert!(key.memory_footprint() >= std::mem::size_of::<GraphKey>() + key.get_descriptor().len() * std::mem::size_of::<usize>());
         }
+        assert!(large.memory_footprint() > small.memory_footprint());
+        assert!(GraphKey::heuristic_capacity(800) >= 800);
+    }
 
-        g
+    #[test]
+    fn key_cache_computes_once() {
+
+        let g = gen_test_graph();
+        let h = generate_permutated_graph(&g);
+        let mut cache = GraphKeyCache::new();
+
+        let key = cache.get(&g);
+        assert_eq!(cache.get(&g), key);
+        assert_eq!(cache.get(&g.clone()), key);
+        assert_eq!(cache.computed(), 1);
+
+        // An isomorphic copy is a different graph for the cache
+        assert_eq!(cache.get(&h), key);
+        assert_eq!(cache.computed(), 2);
+        assert_eq!(cache.len(), 2);
     }
 
-    
-    fn generate_permutated_graph(g : &Graph::<usize, (), Undirected>) -> Graph::<usize, (), Undirected> {
+    #[test]
+    fn wildcard_keys() {
 
-        let n = g.node_count();
-        let mut perm : Vec<usize> = (0..n).collect();
-        let mut rng = thread_rng();
-        perm.shuffle(&mut rng);
+        // Path 0 - 1 - 2 - 3
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3)]);
 
-        
-        let edges : Vec<(usize, usize)> = g.edge_indices()
-        .map(|e| { 
+        // Same path, relabelled as 2 - 0 - 3 - 1
+        let h = UnGraph::<usize, ()>::from_edges([(2, 0), (0, 3), (3, 1)]);
+
+        // An end node is a wildcard in both graphs
+        assert_eq!(GraphKey::new_with_wildcards(&g, &[0]), GraphKey::new_with_wildcards(&h, &[1]));
+        assert_eq!(GraphKey::new_with_wildcards(&g, &[0, 3]), GraphKey::new_with_wildcards(&h, &[1, 2]));
+
+        // An end node cannot be mapped to an inner node
+        assert_ne!(GraphKey::new_with_wildcards(&g, &[0]), GraphKey::new_with_wildcards(&h, &[0]));
+        assert_ne!(GraphKey::new_with_wildcards(&g, &[0]), GraphKey::new_with_wildcards(&g, &[0, 3]));
+
+        // All the nodes as wildcards, or none
+        let k3 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        assert_ne!(GraphKey::new_with_wildcards(&k3, &[0, 1, 2]), GraphKey::new_with_wildcards(&k3, &[]));
+    }
+
+    #[test]
+    fn vertex_count_keys() {
+
+        // Path 0 - 1 - 2 where the middle node stands for 2 or 3 vertices
+        let path = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2)]);
+        let key = GraphKey::with_vertex_counts(&path, |u| [1, 2, 1][u.index()]);
+        assert_ne!(GraphKey::with_vertex_counts(&path, |u| [1, 3, 1][u.index()]), key);
+        assert_ne!(GraphKey::with_vertex_counts(&path, |u| [2, 4, 2][u.index()]), key);
+        assert_ne!(GraphKey::with_vertex_counts(&path, |u| [2, 1, 1][u.index()]), key);
+        assert_ne!(GraphKey::with_vertex_counts(&path, |_| 1 << 40), GraphKey::with_vertex_counts(&path, |_| 1 << 41));
+
+        // Counts follow the nodes through a permutation
+        let g = generate_random_graph(20, 0.2);
+        let mut h = UnGraph::<usize, ()>::default();
+        (0..20).for_each(|u| { h.add_node(u); });
+        for e in g.edge_indices() {
             let (u, v) = g.edge_endpoints(e).unwrap();
-            (perm[u.index()] , perm[v.index()])
-        })
-        .collect();
+            h.add_edge(NodeIndex::new(19 - u.index()), NodeIndex::new(19 - v.index()), ());
+        }
+        let count = |u : usize| (u % 3) as u64 + 1;
+        assert_eq!(GraphKey::with_vertex_counts(&h, |u| count(19 - u.index())), GraphKey::with_vertex_counts(&g, |u| count(u.index())));
+    }
 
-        let mut g = UnGraph::<usize, ()>::new_undirected();
+    #[test]
+    fn marked_set_keys() {
 
-        g.reserve_nodes(n);
-        (0..n).for_each(|_| { g.add_node(1); });
+        // Triangle 0 1 2 with a pendant node 3 on 2, and the same graph relabelled
+        let paw = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0), (2, 3)]);
+        let relabelled = UnGraph::<usize, ()>::from_edges([(3, 0), (0, 1), (1, 3), (1, 2)]);
+        let key = GraphKey::new_with_marked_set(&paw, &[0, 1]);
+        assert_eq!(GraphKey::new_with_marked_set(&relabelled, &[3, 0]), key);
 
-        g.reserve_edges(edges.len());
-        edges.into_iter().for_each(|(u, v)| { g.add_edge(NodeIndex::new(u), NodeIndex::new(v), ()); });
+        // The cliques {0, 1} and {1, 2} only match if the marking is ignored
+        assert_eq!(GraphKey::new(&paw), GraphKey::new(&relabelled));
+        assert_ne!(GraphKey::new_with_marked_set(&paw, &[1, 2]), key);
+        assert_ne!(GraphKey::new_with_marked_set(&relabelled, &[0, 1]), key);
 
-        g
+        // Marking every node of K3 is not the same as marking none
+        let k3 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        assert_ne!(GraphKey::new_with_marked_set(&k3, &[0, 1, 2]), GraphKey::new_with_marked_set(&k3, &[]));
     }
 
+    #[test]
+    fn bytes_do_not_depend_on_usize_width() {
+
+        let labels = [0, 1, 0, 1, 2, 0, 1, 0, 1, 2];
+        for key in [GraphKey::new(&gen_test_graph()), GraphKey::new_with_labels(&gen_test_graph(), &labels), GraphKey::default()] {
+            let bytes = key.to_bytes();
+
+            // Same descriptor held in 32-bit values, where the marker is u32::MAX
+            let mut bytes_32 = Vec::new();
+            for v in key.get_descriptor().iter().map(|v| *v as u32) {
+                write_varint(&mut bytes_32, to_portable(v as u64, u32::MAX as u64).wrapping_add(1));
+            }
+            assert_eq!(bytes_32, bytes);
+        }
+
+        assert_eq!(GraphKey::new_with_labels(&gen_test_graph(), &labels).to_bytes()[0], 0);
+        assert_eq!(GraphKey::<Undirected>::default().fingerprint(), GraphKey::<Undirected>::default().fingerprint());
+        assert_ne!(GraphKey::new(&gen_test_graph()).fingerprint(), GraphKey::<Undirected>::default().fingerprint());
+    }
 
     #[test]
-    fn key_generation() {
-        
-        let g1 = gen_test_graph();
-        let g2 = generate_permutated_graph(&g1);
-        
-        let key1 = GraphKey::new(&g1);
-        let key2 = GraphKey::new(&g2);
-        
-        assert_eq!(key1, key2);
+    fn fnv1a_reference_values() {
+
+        assert_eq!(fnv1a([]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(*b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(*b"foobar"), 0x8594_4171_f739_67e8);
+        assert_eq!(GraphKey::new(&gen_test_graph()).fingerprint(), fnv1a(GraphKey::new(&gen_test_graph()).to_bytes()));
     }
 
     #[test]
-    fn key_generation_large() {
-        
-        let g1 = generate_random_graph(2000, 0.05);
-        let g2 = generate_permutated_graph(&g1);
-        
-        let key1 = GraphKey::new(&g1);
-        let key2 = GraphKey::new(&g2);
-        
-        assert_eq!(key1, key2);
+    fn bytes_round_trip() {
+
+        let mut single = UnGraph::<usize, ()>::default();
+        single.add_node(0);
+        let keys = [
+            GraphKey::new(&gen_test_graph()),
+            GraphKey::new(&generate_random_graph(200, 0.1)),
+            GraphKey::new_with_labels(&gen_test_graph(), &[0, 1, 0, 1, 2, 0, 1, 0, 1, 2]),
+            GraphKey::new(&UnGraph::<usize, ()>::default()),
+            GraphKey::new(&single),
+            GraphKey::default(),
+            // Parallel edges give deltas of 0
+            GraphKey::new(&UnGraph::<usize, ()>::from_edges([(0, 1), (0, 1), (1, 2)])),
+        ];
+
+        for key in keys {
+            let bytes = key.to_bytes();
+            assert_eq!(GraphKey::from_bytes(&bytes), Ok(key.clone()));
+            assert_eq!(key.to_string().parse(), Ok(key));
+        }
+
+        // Directed keys have a row per node
+        let cycle = petgraph::graph::DiGraph::<(), ()>::from_edges([(0, 1), (1, 2), (2, 0), (0, 0)]);
+        for key in [GraphKey::new_directed(&cycle), GraphKey::for_automaton(&cycle, |u| u == 0)] {
+            assert_eq!(GraphKey::from_bytes(&key.to_bytes()), Ok(key.clone()));
+        }
+
+        // Values around the varint boundaries, and the largest ones that are not a separator
+        for value in [0, 127, 128, 16383, 16384, u32::MAX as u64, u64::MAX - 1] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            assert_eq!(read_varint(&bytes), Ok((value, bytes.len())));
+        }
+
+        // Separators and small values take a byte each
+        assert_eq!(GraphKey::<Undirected>::from_descriptor(vec![3, 1, SEPARATOR, 0, SEPARATOR]).to_bytes(), vec![4, 2, 0, 1, 0]);
+        let empty = GraphKey::<Undirected>::from_descriptor([vec![10], vec![SEPARATOR; 9]].concat());
+        assert_eq!(empty.to_string(), "0b000000000000000000");
+        assert_eq!("0B000000000000000000".parse(), Ok(empty));
     }
 
     #[test]
-    fn hashset_graphkeys() {
-        
-        let mut g = generate_random_graph(1000, 0.1);
-        
-        let g1 = generate_permutated_graph(&g);
-        let g2 = generate_permutated_graph(&g);
-        
-        match g.find_edge(0.into(), 1.into()) {
-            Some(_ix) => { g.remove_edge(_ix); }
-            None => { g.add_edge(0.into(), 1.into(), ()); }
+    fn malformed_bytes_are_rejected() {
+
+        assert_eq!(GraphKey::<Undirected>::from_bytes(&[]), Err(ParseError::Truncated));
+        assert_eq!(GraphKey::<Undirected>::from_bytes(&[4, 2, 0x81]), Err(ParseError::Truncated));
+
+        // Trailing zero byte, more than 64 bits, more than 10 bytes
+        assert_eq!(GraphKey::<Undirected>::from_bytes(&[0x81, 0x00]), Err(ParseError::Malformed));
+        assert_eq!(GraphKey::<Undirected>::from_bytes(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]), Err(ParseError::Malformed));
+        assert_eq!(GraphKey::<Undirected>::from_bytes(&[0x80; 11]), Err(ParseError::Malformed));
+
+        // A node count that fits, but whose rows are missing
+        assert_eq!(GraphKey::<Undirected>::from_bytes(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]), Err(ParseError::Truncated));
+
+        // Rows that do not match the node count : too few, too many, a neighbor
+        // out of range or below the node of the row
+        let parse = |descriptor : Vec<usize>| GraphKey::<Undirected>::from_bytes(&GraphKey::<Undirected>::from_descriptor(descriptor).to_bytes());
+        assert_eq!(parse(vec![3, 1, SEPARATOR]), Err(ParseError::Truncated));
+        assert_eq!(parse(vec![2, 1, SEPARATOR, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![2, 1, SEPARATOR, 1]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![3, 2, 1, SEPARATOR, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![3, 1, SEPARATOR, 0, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![1, 0]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![SEPARATOR]), Err(ParseError::Truncated));
+
+        // Labelled headers whose cells do not hold the nodes, or whose label
+        // bytes are not bytes
+        assert_eq!(parse(vec![LABELLED_MARKER, 1, 2, 0, 3, 1, SEPARATOR, 1, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![LABELLED_MARKER, 1, 2, 1, 256, 2, 1, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse(vec![LABELLED_MARKER, 1, 2, 1, 255, 2, 1, SEPARATOR]).map(|key| key.0.len()), Ok(8));
+
+        // Directed rows hold sorted successors, one row per node
+        let parse_directed = |descriptor : Vec<usize>| GraphKey::<petgraph::Directed>::from_bytes(&GraphKey::<petgraph::Directed>::from_descriptor(descriptor).to_bytes());
+        assert!(parse_directed(vec![2, 0, 1, SEPARATOR, SEPARATOR]).is_ok());
+        assert_eq!(parse_directed(vec![2, 1, 0, SEPARATOR, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse_directed(vec![2, 2, SEPARATOR, SEPARATOR]), Err(ParseError::Malformed));
+        assert_eq!(parse_directed(vec![2, 1, SEPARATOR]), Err(ParseError::Truncated));
+
+        // Every proper prefix of a key fails, without panicking
+        let labelled = GraphKey::new_with_labels(&gen_test_graph(), &["a", "b", "a", "b", "c", "a", "b", "a", "b", "c"]);
+        for bytes in [GraphKey::new(&generate_random_graph(50, 0.2)).to_bytes(), labelled.to_bytes(), GraphKey::new_directed(&gen_test_graph()).to_bytes()] {
+            for len in 0..bytes.len() {
+                assert!(GraphKey::<Undirected>::from_bytes(&bytes[..len]).is_err());
+                assert!(GraphKey::<petgraph::Directed>::from_bytes(&bytes[..len]).is_err());
+            }
         }
-        
-        let g3 = generate_permutated_graph(&g);
-        let g4 = generate_permutated_graph(&g);
 
-        // generate Hashset
-        let mut s = HashSet::new();
+        for s in ["", "0", "0g", "+1", "01 ", "é1"] {
+            assert!(s.parse::<GraphKey>().is_err());
+        }
+        assert_eq!("".parse::<GraphKey>(), Err(ParseError::Truncated));
+        assert_eq!("012".parse::<GraphKey>(), Err(ParseError::InvalidHex));
+    }
 
-        s.insert(GraphKey::new(&g1));
-        s.insert(GraphKey::new(&g2));
-        s.insert(GraphKey::new(&g3));
-        s.insert(GraphKey::new(&g4));
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_keys_are_u64() {
 
-        assert_eq!(s.len(), 2);
+        let key = GraphKey::new_with_labels(&gen_test_graph(), &[0, 1, 0, 1, 2, 0, 1, 0, 1, 2]);
+        let bytes = bincode::serialize(&key).unwrap();
+
+        // bincode : length prefix, then the values as 8 little-endian bytes
+        let values : Vec<u8> = key.get_descriptor().iter().flat_map(|v| to_portable(*v as u64, LABELLED_MARKER as u64).to_le_bytes()).collect();
+        assert_eq!(bytes[8..], values);
+        assert_eq!(bincode::deserialize::<GraphKey>(&bytes).unwrap(), key);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn canonical_record_round_trip() {
+
+        let g = gen_test_graph();
+        let record = GraphKey::to_record(&generate_permutated_graph(&g));
+
+        assert_eq!(record.key, GraphKey::new(&g));
+
+        let bytes = bincode::serialize(&record).unwrap();
+        let loaded : CanonicalRecord = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(loaded.key, record.key);
+        assert_eq!(loaded.key, GraphKey::new(&loaded.graph));
+    }
+
+    // Every public type can be shared between threads
+    const _ : fn() = || {
+        fn assert_send_sync<T : Send + Sync>() {}
+        assert_send_sync::<GraphKey>();
+        assert_send_sync::<GraphKey<petgraph::Directed>>();
+        assert_send_sync::<ApproxKey>();
+        assert_send_sync::<Certificate>();
+        assert_send_sync::<KeyDiff>();
+        assert_send_sync::<CompareResult>();
+        assert_send_sync::<TreeKey>();
+        assert_send_sync::<HasCycle>();
+        assert_send_sync::<NotATournament>();
+        assert_send_sync::<GraphPrefilter>();
+        assert_send_sync::<PermGroup>();
+        assert_send_sync::<NotATree>();
+        assert_send_sync::<WlColoring>();
+        assert_send_sync::<GraphKeyBuilder>();
+        assert_send_sync::<Colouring>();
+        assert_send_sync::<coloring::RefineReport>();
+        assert_send_sync::<coloring::StepInfo>();
+        assert_send_sync::<coloring::RefinementStepper<&Graph<usize, (), Undirected>>>();
+        assert_send_sync::<WorklistOrder>();
+        assert_send_sync::<Kdim>();
+        assert_send_sync::<CollisionError>();
+        assert_send_sync::<CanonError>();
+        assert_send_sync::<MatrixError>();
+        assert_send_sync::<GraphTracker>();
+        assert_send_sync::<GraphKeyCache>();
+        assert_send_sync::<GraphKeySet>();
+        assert_send_sync::<CanonicalRecord>();
+        assert_send_sync::<LeafSelection>();
+        assert_send_sync::<SelectorKind>();
+        assert_send_sync::<CanonConfig>();
+        assert_send_sync::<SearchOrder>();
+        assert_send_sync::<ParseError>();
+    };
+
+    //
+    // Property tests
+    //
+
+    use proptest::prelude::*;
+
+    /// Simple graph on n nodes : self-loops and repeated edges are dropped.
+    fn simple_graph(n : usize, edges : &[(usize, usize)]) -> Graph::<usize, (), Undirected> {
+        let mut g = UnGraph::<usize, ()>::new_undirected();
+        (0..n).for_each(|i| { g.add_node(i); });
+        for (u, v) in edges {
+            if u != v && g.find_edge(NodeIndex::new(*u), NodeIndex::new(*v)).is_none() {
+                g.add_edge(NodeIndex::new(*u), NodeIndex::new(*v), ());
+            }
+        }
+        g
     }
 
+    /// Random simple graph with at most max_n nodes.
+    ///
+    /// The search keeps all the leaves of the tree, e.g. n! for the empty graph
+    /// on n nodes, so max_n must stay small.
+    fn arb_graph(max_n : usize) -> impl Strategy<Value = Graph::<usize, (), Undirected>> {
+        (1..=max_n).prop_flat_map(|n| {
+            prop::collection::vec((0..n, 0..n), 0..=(n * (n - 1) / 2))
+                .prop_map(move |edges| simple_graph(n, &edges))
+        })
+    }
+
+    /// Random graph together with a random permutation of its nodes
+    fn arb_graph_and_permutation(max_n : usize) -> impl Strategy<Value = (Graph::<usize, (), Undirected>, Vec<usize>)> {
+        arb_graph(max_n).prop_flat_map(|g| {
+            let perm = Just((0..g.node_count()).collect::<Vec<usize>>()).prop_shuffle();
+            (Just(g), perm)
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_keys_are_invariant((g, perm) in arb_graph_and_permutation(8)) {
+            let edges : Vec<(usize, usize)> = g.edge_indices().map(|e| {
+                let (u, v) = g.edge_endpoints(e).unwrap();
+                (perm[u.index()], perm[v.index()])
+            }).collect();
+            let h = simple_graph(g.node_count(), &edges);
+
+            prop_assert_eq!(GraphKey::new(&g), GraphKey::new(&h));
+        }
+
+        #[test]
+        fn prop_refine_is_invariant((g, perm) in arb_graph_and_permutation(16), labels in prop::collection::vec(0..3usize, 16)) {
+            let n = g.node_count();
+            let edges : Vec<(usize, usize)> = g.edge_indices().map(|e| {
+                let (u, v) = g.edge_endpoints(e).unwrap();
+                (perm[u.index()], perm[v.index()])
+            }).collect();
+            let h = simple_graph(n, &edges);
+            let mut permuted_labels = vec![0; n];
+            (0..n).for_each(|u| permuted_labels[perm[u]] = labels[u]);
+
+            // P(C).refine(P(G)) == P(C.refine(G)) : each node keeps its color
+            // through the permutation, so the partitions and traces match too
+            let mut gc = Colouring::from_labels(&labels[..n]);
+            let mut hc = Colouring::from_labels(&permuted_labels);
+            prop_assert_eq!(gc.refine(&g), hc.refine(&h));
+            prop_assert!((0..n).all(|u| gc.color_of(u) == hc.color_of(perm[u])));
+        }
+
+        #[test]
+        fn prop_keys_match_isomorphism(g in arb_graph(6), h in arb_graph(6)) {
+            prop_assert_eq!(GraphKey::new(&g) == GraphKey::new(&h), is_isomorphic(&g, &h));
+        }
+    }
+
+    /// Disjoint union of g and h, the nodes of h coming after those of g.
+    fn disjoint_union(g : &Graph::<usize, (), Undirected>, h : &Graph::<usize, (), Undirected>) -> Graph::<usize, (), Undirected> {
+        let n = g.node_count();
+        let edges : Vec<(usize, usize)> = g.edge_indices().map(|e| g.edge_endpoints(e).unwrap())
+            .map(|(u, v)| (u.index(), v.index()))
+            .chain(h.edge_indices().map(|e| h.edge_endpoints(e).unwrap()).map(|(u, v)| (n + u.index(), n + v.index())))
+            .collect();
+        simple_graph(n + h.node_count(), &edges)
+    }
+
+    /// Key of g computed from its refined colouring by degree instead of the
+    /// uniform colouring.
+    fn key_from_degrees(g : &Graph::<usize, (), Undirected>) -> GraphKey {
+        let result = search(Neighbors(g), Colouring::new_refined_by_degree(g), Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor);
+        GraphKey::from_descriptor(result.descriptor)
+    }
 
     #[test]
-    fn is_isomorphic_test() {
+    fn degree_start_of_the_empty_graph() {
+        let empty = UnGraph::<usize, ()>::default();
+        assert_eq!(key_from_degrees(&empty), GraphKey::new(&empty));
+    }
 
-        for _ in 0..100 {
-            let g1 = generate_random_graph(500, 0.05);
-            let g2 = generate_random_graph(500, 0.05);
-            let g3 = generate_permutated_graph(&g1);
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
 
-            let key1 = GraphKey::new(&g1);
-            let key2 = GraphKey::new(&g2);
-            let key3 = GraphKey::new(&g3);
+        // Both starts refine to the same colouring, so the choice only affects
+        // performance
+        #[test]
+        fn prop_degree_start_gives_the_same_key(g in arb_graph(6)) {
+            prop_assert_eq!(key_from_degrees(&g), GraphKey::new(&g));
+        }
 
-            assert_eq!(is_isomorphic(&g1, &g2), key1 == key2);
-            assert_eq!(key1, key3);
+        #[test]
+        fn prop_degree_start_of_disconnected_graphs(g in arb_graph(4), h in arb_graph(4)) {
+            let union = disjoint_union(&g, &h);
+            prop_assert_eq!(key_from_degrees(&union), GraphKey::new(&union));
         }
     }
 }
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use petgraph::visit::{NodeCompactIndexable, IntoNeighbors};
+
+use crate::{GraphKey, LeafSelection, SearchGraph, descriptor_from_rows, search};
+use crate::coloring::{Colouring, RefineGraph};
+
+//
+// NeighborProvider trait
+//
+
+/// Undirected graph on the nodes 0..node_count(), read one adjacency list at a
+/// time, e.g. from a memory-mapped file, see `GraphKey::from_provider`.
+///
+/// An edge u - v is listed by both u and v, and a self-loop once by u, as
+/// petgraph does for undirected graphs.
+pub trait NeighborProvider {
+    fn node_count(&self) -> usize;
+
+    /// Call f on each neighbor of u, once per edge.
+    fn for_each_neighbor<F>(&self, u : usize, f : F)
+    where
+        F : FnMut(usize);
+}
+
+/// Provider as seen by the search.
+struct Provided<'a, P : ?Sized>(&'a P);
+
+impl<P : ?Sized> Clone for Provided<'_, P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P : ?Sized> Copy for Provided<'_, P> {}
+
+impl<P> RefineGraph for Provided<'_, P>
+where
+    P : NeighborProvider + ?Sized
+{
+    fn for_each_neighbor<F>(&self, u : usize, mut f : F)
+    where
+        F : FnMut(usize, usize)
+    {
+        self.0.for_each_neighbor(u, |v| f(v, 0));
+    }
+}
+
+impl<P> SearchGraph for Provided<'_, P>
+where
+    P : NeighborProvider + ?Sized
+{
+    // Rows are read straight from the provider in canonical order, one at a
+    // time, without building the relabelled graph
+    fn describe(&self, leaf : &Colouring) -> Vec<usize> {
+        let n = self.0.node_count();
+        let mut node_at = vec![0; n];
+        (0..n).for_each(|u| node_at[leaf.color_of(u)] = u);
+
+        let rows = (0..n.saturating_sub(1)).map(|i| {
+            let mut row = Vec::new();
+            self.0.for_each_neighbor(node_at[i], |v| {
+                let j = leaf.color_of(v);
+                if j > i {
+                    row.push(j);
+                }
+            });
+            row
+        });
+        descriptor_from_rows(n, rows)
+    }
+}
+
+impl GraphKey {
+
+    /// Compute the key of the graph listed by `p`, the same as `GraphKey::new`
+    /// on the equivalent petgraph graph.
+    ///
+    /// The graph is never loaded as a whole: refinement reads the adjacency
+    /// lists on demand, and only the candidate leaves are described, one row
+    /// per node.
+    pub fn from_provider<P>(p : &P) -> GraphKey
+    where
+        P : NeighborProvider + ?Sized
+    {
+        let gc = Colouring::from_labels(&vec![0u8; p.node_count()]);
+        GraphKey::from_descriptor(search(Provided(p), gc, Colouring::select_cell_smallest_first, LeafSelection::MaxDescriptor).descriptor)
+    }
+
+    /// Compute the key of the subgraph of `g` induced by `vertices`, the same as
+    /// `GraphKey::new` on the induced subgraph, without building it.
+    ///
+    /// The search reads the neighbors of the nodes of g, keeping those in
+    /// vertices, renumbered on the fly by their position in it. The vertices
+    /// must be distinct.
+    pub fn of_induced_view<G>(g : G, vertices : &[usize]) -> GraphKey
+    where
+        G : NodeCompactIndexable + IntoNeighbors
+    {
+        let position = vertices.iter().enumerate().map(|(i, u)| (*u, i)).collect();
+        GraphKey::from_provider(&InducedView { g, vertices, position })
+    }
+}
+
+/// Subgraph of g induced by vertices, the node i being vertices[i].
+struct InducedView<'a, G> {
+    g : G,
+    vertices : &'a [usize],
+    position : HashMap<usize, usize>,
+}
+
+impl<G> NeighborProvider for InducedView<'_, G>
+where
+    G : NodeCompactIndexable + IntoNeighbors
+{
+    fn node_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    fn for_each_neighbor<F>(&self, u : usize, mut f : F)
+    where
+        F : FnMut(usize)
+    {
+        let g = self.g;
+        for v in g.neighbors(g.from_index(self.vertices[u])) {
+            if let Some(i) = self.position.get(&g.to_index(v)) {
+                f(*i);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::NeighborProvider;
+    use crate::GraphKey;
+    use crate::derived::induced_subgraph;
+    use crate::common::{gen_test_graph, generate_random_graph, generate_permutated_graph};
+    use petgraph::graph::{NodeIndex, UnGraph};
+
+    /// Adjacency lists kept in memory, counting the lists read.
+    struct Lists {
+        adjacency : Vec<Vec<usize>>,
+        reads : Cell<usize>,
+    }
+
+    impl Lists {
+        fn new(g : &UnGraph<usize, ()>) -> Lists {
+            let adjacency = g.node_indices().map(|u| g.neighbors(u).map(NodeIndex::index).collect()).collect();
+            Lists { adjacency, reads : Cell::new(0) }
+        }
+    }
+
+    impl NeighborProvider for Lists {
+        fn node_count(&self) -> usize {
+            self.adjacency.len()
+        }
+
+        fn for_each_neighbor<F>(&self, u : usize, f : F)
+        where
+            F : FnMut(usize)
+        {
+            self.reads.set(self.reads.get() + 1);
+            self.adjacency[u].iter().copied().for_each(f);
+        }
+    }
+
+    #[test]
+    fn provider_keys_match_new() {
+
+        let g = gen_test_graph();
+        for g in [g.clone(), generate_permutated_graph(&g), generate_random_graph(40, 0.15)] {
+            let lists = Lists::new(&g);
+            assert_eq!(GraphKey::from_provider(&lists), GraphKey::new(&g));
+            assert!(lists.reads.get() > 0);
+        }
+
+        // Self-loop and parallel edges
+        let multi = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 1), (1, 2), (2, 2), (2, 3)]);
+        assert_eq!(GraphKey::from_provider(&Lists::new(&multi)), GraphKey::new(&multi));
+
+        // Discrete after the first refinement: a spider with legs of length 1, 2 and 3
+        let spider = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 2), (2, 3), (0, 4), (4, 5), (5, 6)]);
+        assert_eq!(GraphKey::from_provider(&Lists::new(&spider)), GraphKey::new(&spider));
+
+        assert_eq!(GraphKey::from_provider(&Lists::new(&UnGraph::default())), GraphKey::new(&UnGraph::<usize, ()>::default()));
+    }
+
+    #[test]
+    fn induced_view_keys() {
+
+        let g = generate_random_graph(40, 0.15);
+        let subsets : [Vec<usize>; 4] = [(0..40).collect(), (0..40).step_by(3).collect(), vec![39, 2, 17, 5, 23, 11, 30], vec![]];
+        for vertices in subsets.iter() {
+            assert_eq!(GraphKey::of_induced_view(&g, vertices), GraphKey::new(&induced_subgraph(&g, vertices)));
+        }
+
+        // Self-loops and parallel edges are kept
+        let multi = UnGraph::<usize, ()>::from_edges([(0, 1), (0, 1), (1, 2), (2, 2), (2, 3), (3, 4)]);
+        assert_eq!(GraphKey::of_induced_view(&multi, &[2, 1, 0]), GraphKey::new(&induced_subgraph(&multi, &[2, 1, 0])));
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+use petgraph::visit::{NodeCompactIndexable, IntoEdges, EdgeRef};
+
+use crate::GraphKey;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+//
+// Tree keys
+//
+
+/// Canonical key of a tree, see `GraphKey::of_tree`.
+///
+/// Tree keys are computed without the search and are not comparable with
+/// `GraphKey`s: two trees get the same `TreeKey` iff they are isomorphic.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TreeKey(Vec<usize>);
+
+impl TreeKey {
+    pub fn get_descriptor(&self) -> &Vec<usize> {
+        &self.0
+    }
+}
+
+/// The graph given to `GraphKey::of_tree` is not a tree: it is empty, not
+/// connected, or has a cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotATree;
+
+impl fmt::Display for NotATree {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "graph is not a tree")
+    }
+}
+
+impl Error for NotATree {}
+
+impl GraphKey {
+
+    /// Compute the key of the tree `g` with the AHU algorithm, in linear time
+    /// up to sorting, instead of running the search.
+    ///
+    /// The tree is rooted at its center; a tree with two centers is encoded
+    /// from both, keeping the smallest encoding. Fails if `g` is not a tree,
+    /// i.e. not a connected graph with n - 1 edges.
+    pub fn of_tree<G>(g : G) -> Result<TreeKey, NotATree>
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let n = g.node_count();
+        let mut adjacency = vec![Vec::new(); n];
+        let mut edge_count = 0;
+        for e in g.edge_references() {
+            let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+            adjacency[u].push(v);
+            adjacency[v].push(u);
+            edge_count += 1;
+        }
+
+        // With n - 1 edges, a connected graph has no cycle
+        if n == 0 || edge_count != n - 1 || bfs(&adjacency, 0).0.len() != n {
+            return Err(NotATree);
+        }
+
+        centers(&adjacency).into_iter().map(|root| encode(&adjacency, root)).min().map(TreeKey).ok_or(NotATree)
+    }
+}
+
+/// Nodes of the tree in BFS order from root, with the parent of each node.
+fn bfs(adjacency : &[Vec<usize>], root : usize) -> (Vec<usize>, Vec<usize>) {
+    let mut parent = vec![usize::MAX; adjacency.len()];
+    let mut order = vec![root];
+    let mut queue = VecDeque::from([root]);
+    parent[root] = root;
+
+    while let Some(u) = queue.pop_front() {
+        for v in adjacency[u].iter() {
+            if parent[*v] == usize::MAX {
+                parent[*v] = u;
+                order.push(*v);
+                queue.push_back(*v);
+            }
+        }
+    }
+
+    (order, parent)
+}
+
+/// The one or two centers of the tree, left by removing the leaves until at
+/// most two nodes remain.
+fn centers(adjacency : &[Vec<usize>]) -> Vec<usize> {
+    let n = adjacency.len();
+    let mut degree : Vec<usize> = adjacency.iter().map(|a| a.len()).collect();
+    let mut leaves : Vec<usize> = (0..n).filter(|u| degree[*u] <= 1).collect();
+    let mut remaining = n;
+
+    while remaining > 2 {
+        remaining -= leaves.len();
+        let mut next = Vec::new();
+        for u in leaves {
+            for v in adjacency[u].iter() {
+                degree[*v] -= 1;
+                if degree[*v] == 1 {
+                    next.push(*v);
+                }
+            }
+        }
+        leaves = next;
+    }
+
+    leaves
+}
+
+/// AHU encoding of the tree rooted at root.
+///
+/// Levels are labelled from the deepest one up: the label of a node is the
+/// rank of the sorted labels of its children among those of its level. The
+/// encoding lists, for each level, its distinct sorted child labels in
+/// increasing order, each one preceded by its length. The last level only
+/// holds the root, which makes the encoding describe the whole tree.
+fn encode(adjacency : &[Vec<usize>], root : usize) -> Vec<usize> {
+    let n = adjacency.len();
+    let (order, parent) = bfs(adjacency, root);
+    let mut depth = vec![0; n];
+    for u in order.iter().skip(1) {
+        depth[*u] = depth[parent[*u]] + 1;
+    }
+
+    let mut children : Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut encoding = vec![n];
+
+    // Nodes of each level are contiguous in the BFS order
+    let mut end = n;
+    while end > 0 {
+        let start = order[..end].iter().rposition(|u| depth[*u] != depth[order[end - 1]]).map_or(0, |i| i + 1);
+        let level = &order[start..end];
+
+        for u in level {
+            children[*u].sort();
+        }
+        let mut distinct : Vec<&Vec<usize>> = level.iter().map(|u| &children[*u]).collect();
+        distinct.sort();
+        distinct.dedup();
+
+        encoding.push(distinct.len());
+        for c in distinct.iter() {
+            encoding.push(c.len());
+            encoding.extend(c.iter());
+        }
+
+        let ranks : Vec<usize> = level.iter().map(|u| distinct.binary_search(&&children[*u]).unwrap()).collect();
+        for (u, rank) in level.iter().zip(ranks) {
+            if *u != root {
+                children[parent[*u]].push(rank);
+            }
+        }
+
+        end = start;
+    }
+
+    encoding
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::NotATree;
+    use crate::GraphKey;
+    use crate::common::generate_permutated_graph;
+    use petgraph::graph::{NodeIndex, UnGraph};
+    use rand::{Rng, thread_rng};
+
+    #[test]
+    fn caterpillar_and_star() {
+
+        // Caterpillar : spine 0 - 1 - 2 with two leaves on each spine node
+        let caterpillar = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (0, 3), (0, 4), (1, 5), (1, 6), (2, 7), (2, 8)]);
+        let star = UnGraph::<usize, ()>::from_edges((1..9).map(|v| (0, v)));
+
+        let key = GraphKey::of_tree(&caterpillar).unwrap();
+        assert_eq!(GraphKey::of_tree(&generate_permutated_graph(&caterpillar)), Ok(key.clone()));
+        assert_ne!(GraphKey::of_tree(&star), Ok(key));
+        assert_eq!(GraphKey::of_tree(&generate_permutated_graph(&star)), GraphKey::of_tree(&star));
+
+        // Paths have two centers when their node count is even
+        let p6 = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5)]);
+        let q6 = UnGraph::<usize, ()>::from_edges([(3, 1), (1, 5), (5, 0), (0, 4), (4, 2)]);
+        assert_eq!(GraphKey::of_tree(&p6), GraphKey::of_tree(&q6));
+    }
+
+    #[test]
+    fn tree_keys_match_graph_keys() {
+
+        // Random trees : each node is attached to an earlier one
+        let mut rng = thread_rng();
+        let random_tree = |rng : &mut rand::rngs::ThreadRng| {
+            let mut t = UnGraph::<usize, ()>::from_edges([(0, 1)]);
+            for u in 2..8 {
+                let v = rng.gen_range(0..u);
+                t.add_node(u);
+                t.add_edge(NodeIndex::new(u), NodeIndex::new(v), ());
+            }
+            t
+        };
+
+        for _ in 0..200 {
+            let (t1, t2) = (random_tree(&mut rng), random_tree(&mut rng));
+            assert_eq!(GraphKey::of_tree(&t1) == GraphKey::of_tree(&t2), GraphKey::new(&t1) == GraphKey::new(&t2));
+        }
+    }
+
+    #[test]
+    fn graphs_that_are_not_trees() {
+
+        assert_eq!(GraphKey::of_tree(&UnGraph::<usize, ()>::new_undirected()), Err(NotATree));
+        assert_eq!(GraphKey::of_tree(&UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)])), Err(NotATree));
+
+        // 3 edges on 4 nodes, but a triangle and an isolated node
+        let mut g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (2, 0)]);
+        g.add_node(3);
+        assert_eq!(GraphKey::of_tree(&g), Err(NotATree));
+
+        let mut single = UnGraph::<usize, ()>::new_undirected();
+        single.add_node(0);
+        assert!(GraphKey::of_tree(&single).is_ok());
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use petgraph::visit::{NodeCompactIndexable, IntoEdges, EdgeRef};
+
+//
+// WlColoring object
+//
+
+/// Weisfeiler-Leman colours of a graph edited one edge at a time.
+///
+/// Rounds of colour refinement are run from the uniform colouring: the colour
+/// of a node in a round hashes its colour and the multiset of the colours of
+/// its neighbors in the previous round, until a round does not split any
+/// class. Two nodes end with the same colour iff `Colouring::wl_refine` puts
+/// them in the same cell.
+///
+/// Every round is kept, so that an edit only recomputes the nodes it can
+/// affect: the endpoints of the edge, and the neighbors of the nodes whose
+/// colour changed in the previous round.
+///
+/// A self-loop counts once among the neighbors of its node, as in the
+/// neighbors of an undirected petgraph graph.
+///
+/// Colours are 64-bit hashes, not cell indices: two distinct pairs of a
+/// colour and a multiset of neighbor colours may hash to the same value, and
+/// their classes are then merged. This is unlikely, but unlike the cells of
+/// `Colouring::wl_refine`, equal colours do not prove that two nodes are
+/// alike.
+#[derive(Debug, Clone)]
+pub struct WlColoring {
+    adjacency : Vec<Vec<usize>>,
+    /// rounds[t][u] : colour of the node u after t rounds
+    rounds : Vec<Vec<usize>>,
+    /// counts[t][c] : number of nodes of colour c after t rounds
+    counts : Vec<HashMap<usize, usize>>,
+}
+
+impl WlColoring {
+
+    /// Create the colouring of the graph with n nodes and no edge.
+    pub fn new(n : usize) -> WlColoring {
+        WlColoring::from_adjacency(vec![Vec::new(); n])
+    }
+
+    /// Create the colouring of `g`.
+    pub fn from_graph<G>(g : G) -> WlColoring
+    where
+        G : NodeCompactIndexable + IntoEdges
+    {
+        let mut adjacency = vec![Vec::new(); g.node_count()];
+        for e in g.edge_references() {
+            let (u, v) = (g.to_index(e.source()), g.to_index(e.target()));
+            adjacency[u].push(v);
+            if u != v {
+                adjacency[v].push(u);
+            }
+        }
+
+        WlColoring::from_adjacency(adjacency)
+    }
+
+    fn from_adjacency(adjacency : Vec<Vec<usize>>) -> WlColoring {
+        let n = adjacency.len();
+        let mut wl = WlColoring {
+            adjacency,
+            rounds : vec![vec![0; n]],
+            counts : vec![if n == 0 { HashMap::new() } else { HashMap::from([(0, n)]) }],
+        };
+        wl.stabilize(1, &HashSet::new());
+        wl
+    }
+
+    /// Final colour of each node.
+    pub fn colors(&self) -> &[usize] {
+        self.rounds.last().unwrap()
+    }
+
+    /// Number of rounds run before the colouring became stable.
+    pub fn round_count(&self) -> usize {
+        self.rounds.len() - 1
+    }
+
+    /// Add an edge between u and v, a self-loop if they are equal, and update
+    /// the colours.
+    pub fn add_edge(&mut self, u : usize, v : usize) {
+        self.adjacency[u].push(v);
+        if u != v {
+            self.adjacency[v].push(u);
+        }
+        self.stabilize(1, &HashSet::from([u, v]));
+    }
+
+    /// Remove an edge between u and v, and update the colours.
+    ///
+    /// Returns false, leaving the colours untouched, if there is no such edge.
+    pub fn remove_edge(&mut self, u : usize, v : usize) -> bool {
+        let Some(i) = self.adjacency[u].iter().position(|w| *w == v) else {
+            return false;
+        };
+        self.adjacency[u].swap_remove(i);
+        if u != v {
+            let j = self.adjacency[v].iter().position(|w| *w == u).unwrap();
+            self.adjacency[v].swap_remove(j);
+        }
+
+        self.stabilize(1, &HashSet::from([u, v]));
+        true
+    }
+
+    /// Colour of u in the round following the round `t`.
+    fn next_color(&self, t : usize, u : usize) -> usize {
+        let previous = &self.rounds[t];
+        let mut neigh : Vec<usize> = self.adjacency[u].iter().map(|v| previous[*v]).collect();
+        neigh.sort();
+
+        let mut state = DefaultHasher::new();
+        (previous[u], neigh).hash(&mut state);
+        state.finish() as usize
+    }
+
+    /// Recompute the rounds from the round t, where the nodes of `touched` have
+    /// new neighbors and the other nodes only change if a neighbor did.
+    /// Rounds that were never computed are computed for every node.
+    fn stabilize(&mut self, mut t : usize, touched : &HashSet<usize>) {
+        let n = self.adjacency.len();
+        let mut dirty : HashSet<usize> = touched.clone();
+
+        loop {
+            if t == self.rounds.len() {
+                let colors : Vec<usize> = (0..n).map(|u| self.next_color(t - 1, u)).collect();
+                let mut counts = HashMap::new();
+                colors.iter().for_each(|c| *counts.entry(*c).or_insert(0) += 1);
+                self.rounds.push(colors);
+                self.counts.push(counts);
+            } else {
+                let mut changed = Vec::new();
+                for u in dirty.iter() {
+                    let color = self.next_color(t - 1, *u);
+                    let old = self.rounds[t][*u];
+                    if color == old { continue; }
+
+                    let count = self.counts[t].get_mut(&old).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        self.counts[t].remove(&old);
+                    }
+                    *self.counts[t].entry(color).or_insert(0) += 1;
+                    self.rounds[t][*u] = color;
+                    changed.push(*u);
+                }
+
+                dirty = touched.clone();
+                for u in changed {
+                    dirty.insert(u);
+                    dirty.extend(self.adjacency[u].iter().copied());
+                }
+            }
+
+            // A round splitting no class ends the refinement
+            if self.counts[t].len() == self.counts[t - 1].len() {
+                self.rounds.truncate(t + 1);
+                self.counts.truncate(t + 1);
+                return;
+            }
+            t += 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::WlColoring;
+    use crate::coloring::Colouring;
+    use petgraph::graph::{NodeIndex, UnGraph};
+    use rand::{Rng, thread_rng};
+
+    /// Checks that two nodes share a colour iff they share a cell of c.
+    fn same_partition(colors : &[usize], c : &Colouring) -> bool {
+        (0..colors.len()).all(|u| (0..colors.len()).all(|v| (colors[u] == colors[v]) == (c.color_of(u) == c.color_of(v))))
+    }
+
+    #[test]
+    fn incremental_colors_match_batch_colors() {
+
+        let mut rng = thread_rng();
+        let n = 30;
+        let mut g = UnGraph::<usize, ()>::from_edges([(0, 1)]);
+        (2..n).for_each(|u| { g.add_node(u); });
+        let mut wl = WlColoring::from_graph(&g);
+
+        for _ in 0..200 {
+            // Self-loops included
+            let (u, v) = (rng.gen_range(0..n), rng.gen_range(0..n));
+
+            let (a, b) = (NodeIndex::new(u), NodeIndex::new(v));
+            if let Some(e) = g.find_edge(a, b) {
+                g.remove_edge(e);
+                assert!(wl.remove_edge(u, v));
+            } else {
+                g.add_edge(a, b, ());
+                wl.add_edge(u, v);
+            }
+
+            let batch = WlColoring::from_graph(&g);
+            assert_eq!(wl.colors(), batch.colors());
+            assert_eq!(wl.round_count(), batch.round_count());
+
+            let mut gc = Colouring::new(&g);
+            gc.wl_refine(&g);
+            assert!(same_partition(wl.colors(), &gc));
+        }
+    }
+
+    #[test]
+    fn removing_a_missing_edge() {
+
+        let mut wl = WlColoring::new(4);
+        wl.add_edge(0, 1);
+        let colors = wl.colors().to_vec();
+        assert!(!wl.remove_edge(2, 3));
+        assert_eq!(wl.colors(), colors);
+
+        // Path 0 - 1 - 2 - 3 : ends and inner nodes
+        wl.add_edge(1, 2);
+        wl.add_edge(2, 3);
+        let c = wl.colors();
+        assert!(c[0] == c[3] && c[1] == c[2] && c[0] != c[1]);
+    }
+
+    #[test]
+    fn self_loops() {
+
+        // A loop on an end of the path 0 - 1 - 2 sets it apart from the other
+        let mut wl = WlColoring::new(3);
+        wl.add_edge(0, 1);
+        wl.add_edge(1, 2);
+        wl.add_edge(0, 0);
+        let c = wl.colors();
+        assert!(c[0] != c[2] && c[0] != c[1]);
+        let g = UnGraph::<usize, ()>::from_edges([(0, 1), (1, 2), (0, 0)]);
+        assert_eq!(wl.colors(), WlColoring::from_graph(&g).colors());
+
+        assert!(wl.remove_edge(0, 0));
+        assert!(!wl.remove_edge(0, 0));
+        let c = wl.colors();
+        assert!(c[0] == c[2] && c[0] != c[1]);
+    }
+}
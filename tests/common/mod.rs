@@ -0,0 +1,66 @@
+//! Graph generators shared by the unit tests, the integration tests, the
+//! examples and the main binary.
+
+#![allow(dead_code)]
+
+use petgraph::Graph;
+use petgraph::graph::{UnGraph, NodeIndex};
+use petgraph::Undirected;
+use graphkey::generators;
+use rand::{Rng, thread_rng};
+use rand::seq::SliceRandom;
+
+/// Build the graph with nodes 0..n and the given edges.
+pub fn graph(n : usize, edges : &[(usize, usize)]) -> Graph::<usize, (), Undirected> {
+    let mut g = UnGraph::<usize, ()>::new_undirected();
+    g.reserve_nodes(n);
+    (0..n).for_each(|i| { g.add_node(i); });
+
+    g.reserve_edges(edges.len());
+    for (u, v) in edges {
+        g.add_edge(NodeIndex::new(*u), NodeIndex::new(*v), ());
+    }
+    g
+}
+
+/// Graph on 10 nodes with 14 edges, the running example of the unit tests.
+pub fn gen_test_graph() -> Graph::<usize, (), Undirected> {
+    graph(10, &[
+        (0, 3), (0, 5), (0, 8), (1, 4), (1, 6), (1, 8),
+        (2, 5), (2, 7), (3, 6), (3, 9), (4, 7), (4, 9),
+        (5, 8), (7, 9)
+    ])
+}
+
+/// Erdős–Rényi graph G(n, p).
+pub fn generate_random_graph(n : usize, p : f64) -> Graph::<usize, (), Undirected> {
+    generators::random_gnp(n, p, thread_rng().gen())
+}
+
+/// Relabel the nodes of `g` by a random permutation, and shuffle its edges.
+pub fn generate_permutated_graph(g : &Graph::<usize, (), Undirected>) -> Graph::<usize, (), Undirected> {
+    generators::permute(g, thread_rng().gen())
+}
+
+/// Move one random edge of `g` to a random non-adjacent pair, keeping the
+/// node and edge counts. Returns `g` unchanged if it is empty or complete.
+pub fn generate_rewired_graph(g : &Graph::<usize, (), Undirected>) -> Graph::<usize, (), Undirected> {
+    let n = g.node_count();
+    let mut edges : Vec<(usize, usize)> = g.edge_indices().map(|e| {
+        let (u, v) = g.edge_endpoints(e).unwrap();
+        (u.index(), v.index())
+    }).collect();
+
+    let non_edges : Vec<(usize, usize)> = (0..n)
+        .flat_map(|u| ((u + 1)..n).map(move |v| (u, v)))
+        .filter(|(u, v)| g.find_edge(NodeIndex::new(*u), NodeIndex::new(*v)).is_none())
+        .collect();
+    if edges.is_empty() || non_edges.is_empty() {
+        return g.clone();
+    }
+
+    let mut rng = thread_rng();
+    let i = rng.gen_range(0..edges.len());
+    edges[i] = *non_edges.choose(&mut rng).unwrap();
+    graph(n, &edges)
+}
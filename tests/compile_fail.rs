@@ -0,0 +1,7 @@
+//! Programs that must not compile, with the errors they must give.
+
+#[test]
+fn keys_of_different_kinds_are_not_comparable() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
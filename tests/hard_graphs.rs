@@ -0,0 +1,143 @@
+//! Structured graphs whose search trees are deep: strongly regular graphs,
+//! CFI graphs and the incidence graph of a projective plane. Colour refinement
+//! alone cannot tell the non-isomorphic pairs below apart.
+
+use graphkey::GraphKey;
+use petgraph::graph::UnGraph;
+
+mod common;
+
+use common::{graph, generate_permutated_graph as permuted};
+
+/// 4x4 rook's graph, SRG(16, 6, 2, 2): cells of a 4x4 board, adjacent when
+/// on the same row or column.
+fn rook_4x4() -> UnGraph<usize, ()> {
+    let mut edges = Vec::new();
+    for u in 0..16 {
+        for v in (u + 1)..16 {
+            if u / 4 == v / 4 || u % 4 == v % 4 {
+                edges.push((u, v));
+            }
+        }
+    }
+    graph(16, &edges)
+}
+
+/// Shrikhande graph, SRG(16, 6, 2, 2): Cayley graph of Z4 x Z4 generated by
+/// (1, 0), (0, 1) and (1, 1).
+fn shrikhande() -> UnGraph<usize, ()> {
+    let mut edges = Vec::new();
+    for u in 0..16 {
+        let (x, y) = (u / 4, u % 4);
+        for (dx, dy) in [(1, 0), (0, 1), (1, 1)] {
+            edges.push((u, ((x + dx) % 4) * 4 + (y + dy) % 4));
+        }
+    }
+    graph(16, &edges)
+}
+
+/// Paley graph of order 13, SRG(13, 6, 2, 3): u and v are adjacent when u - v
+/// is a non-zero square modulo 13.
+fn paley_13() -> UnGraph<usize, ()> {
+    let squares : Vec<usize> = (1..13).map(|x| x * x % 13).collect();
+    let mut edges = Vec::new();
+    for u in 0..13 {
+        for v in (u + 1)..13 {
+            if squares.contains(&(v - u)) {
+                edges.push((u, v));
+            }
+        }
+    }
+    graph(13, &edges)
+}
+
+/// Heawood graph: incidence graph of the points 0..7 and the lines of the
+/// Fano plane.
+fn heawood() -> UnGraph<usize, ()> {
+    let lines = [[0, 1, 2], [0, 3, 4], [0, 5, 6], [1, 3, 5], [1, 4, 6], [2, 3, 6], [2, 4, 5]];
+    let edges : Vec<(usize, usize)> = lines.iter().enumerate().flat_map(|(l, points)| points.iter().map(move |p| (*p, 7 + l))).collect();
+    graph(14, &edges)
+}
+
+/// CFI graph over K4, twisted on the edge 0-1 if `twisted`.
+///
+/// Each base vertex v becomes 4 middle nodes, one per even subset S of its 3
+/// incident edges, and 2 nodes a(v, e, 0), a(v, e, 1) per incident edge e; the
+/// middle node of S is adjacent to a(v, e, 1) if e is in S and to a(v, e, 0)
+/// otherwise. Each base edge links the a nodes of its endpoints, crossing them
+/// if twisted.
+fn cfi_k4(twisted : bool) -> UnGraph<usize, ()> {
+    let base_edges = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+    let incident = |v : usize| -> Vec<usize> { (0..6).filter(|e| base_edges[*e].0 == v || base_edges[*e].1 == v).collect() };
+
+    // Nodes of v : 4 middle nodes, then a(v, e, i) at 4 + 2 * (position of e) + i
+    let node = |v : usize, offset : usize| v * 10 + offset;
+    let a = |v : usize, e : usize, i : usize| node(v, 4 + 2 * incident(v).iter().position(|f| *f == e).unwrap() + i);
+
+    let mut edges = Vec::new();
+    for v in 0..4 {
+        let even_subsets = [0b000, 0b011, 0b101, 0b110];
+        for (m, subset) in even_subsets.iter().enumerate() {
+            for (k, e) in incident(v).into_iter().enumerate() {
+                edges.push((node(v, m), a(v, e, (subset >> k) & 1)));
+            }
+        }
+    }
+
+    for (e, (u, v)) in base_edges.iter().enumerate() {
+        let twist = usize::from(twisted && e == 0);
+        for i in 0..2 {
+            edges.push((a(*u, e, i), a(*v, e, i ^ twist)));
+        }
+    }
+
+    graph(40, &edges)
+}
+
+#[test]
+fn keys_are_invariant_on_hard_graphs() {
+
+    for g in [rook_4x4(), shrikhande(), paley_13(), heawood(), cfi_k4(false), cfi_k4(true)] {
+        let key = GraphKey::new(&g);
+        for _ in 0..3 {
+            assert_eq!(GraphKey::new(&permuted(&g)), key);
+        }
+    }
+}
+
+#[test]
+fn keys_separate_hard_pairs() {
+
+    // Colour refinement sees two 6-regular graphs on 16 nodes : only the
+    // search separates them
+    let (rook, shrikhande) = (rook_4x4(), shrikhande());
+    assert_eq!(GraphKey::approximate(&rook), GraphKey::approximate(&shrikhande));
+    assert_ne!(GraphKey::new(&rook), GraphKey::new(&permuted(&shrikhande)));
+
+    // Same for the two CFI graphs, which are both 3-regular
+    let (untwisted, twisted) = (cfi_k4(false), cfi_k4(true));
+    assert_eq!(GraphKey::approximate(&untwisted), GraphKey::approximate(&twisted));
+    assert_ne!(GraphKey::new(&untwisted), GraphKey::new(&permuted(&twisted)));
+}
+
+#[test]
+fn automorphism_groups_of_hard_graphs() {
+
+    assert_eq!(GraphKey::automorphism_group_order(&rook_4x4()), Some(1152));
+    assert_eq!(GraphKey::automorphism_group_order(&shrikhande()), Some(192));
+    assert_eq!(GraphKey::automorphism_group_order(&paley_13()), Some(78));
+    assert_eq!(GraphKey::automorphism_group_order(&heawood()), Some(336));
+}
+
+#[test]
+fn key_of_shrikhande_is_pinned() {
+
+    // Key computed before the search shared its best traces, which must not
+    // change how tree nodes compare. Rows end with usize::MAX
+    const S : usize = usize::MAX;
+    let expected = vec![
+        16, 10, 1, 1, 1, 1, 1, S, 1, 1, 3, 2, 3, 3, S, 2, 2, 1, 5, 2, S, 1, 1, 3, 3, 4, S, 1, 2, 5, 3, S,
+        3, 1, 3, 1, S, 1, 2, 2, 2, S, 2, 1, 5, S, 1, 1, 4, S, 1, 3, S, 4, 1, S, 2, 2, S, 1, 1, S, S, S,
+    ];
+    assert_eq!(*GraphKey::new(&permuted(&shrikhande())).get_descriptor(), expected);
+}
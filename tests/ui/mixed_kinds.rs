@@ -0,0 +1,8 @@
+use graphkey::GraphKey;
+use petgraph::{Directed, Undirected};
+
+fn mix(a : &GraphKey<Undirected>, b : &GraphKey<Directed>) -> bool {
+    a == b
+}
+
+fn main() {}
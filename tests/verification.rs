@@ -0,0 +1,31 @@
+//! Sampled verification of the keys computed by `GraphKey::new`. The sample
+//! rate is global to the process, hence the test binary of its own.
+
+use graphkey::GraphKey;
+
+mod common;
+
+use common::generate_random_graph;
+
+#[test]
+fn sampled_verification() {
+
+    let graphs : Vec<_> = (0..10).map(|_| generate_random_graph(20, 0.2)).collect();
+    assert_eq!(GraphKey::verification_sample_rate(), 0.0);
+
+    GraphKey::set_verification_sample_rate(1.0);
+    let before = GraphKey::verification_count();
+    graphs.iter().for_each(|g| { GraphKey::new(g); });
+    assert_eq!(GraphKey::verification_count(), before + 10);
+
+    GraphKey::set_verification_sample_rate(0.0);
+    let before = GraphKey::verification_count();
+    graphs.iter().for_each(|g| { GraphKey::new(g); });
+    assert_eq!(GraphKey::verification_count(), before);
+}
+
+#[test]
+#[should_panic]
+fn sample_rate_is_a_probability() {
+    GraphKey::set_verification_sample_rate(1.5);
+}